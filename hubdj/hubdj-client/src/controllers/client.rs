@@ -81,9 +81,13 @@ impl ClientController {
 			{
 				match result {
 					Ok(source) => {
-						player.clear();
-						player.queue_track(source).unwrap();
-						player.seek(elapsed).unwrap();
+						let played = player
+							.clear()
+							.and_then(|()| player.queue_track(source))
+							.and_then(|()| player.seek(elapsed));
+						if let Err(e) = played {
+							warn!("failed to play {url:?}: {e}");
+						}
 					}
 					Err(e) => {
 						warn!("error while handling track {url:?}: {e}");
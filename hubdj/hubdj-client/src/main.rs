@@ -8,6 +8,8 @@ use anyhow::Result;
 use controllers::client::ClientController;
 use druid::{AppLauncher, WindowDesc};
 use state::{State, StateDisconnected};
+use tf_db::backup::Backups;
+use tracing::warn;
 
 pub mod pb {
 	tonic::include_proto!("hubdj");
@@ -18,7 +20,13 @@ fn connect_to_db() -> Result<tf_db::Client> {
 		.expect("failed to get data directory");
 	std::fs::create_dir_all(dirs.data_dir())?;
 	let db_path = dirs.data_dir().join("db.slab");
-	tf_db::Client::new(db_path)
+	let db = tf_db::Client::new(db_path)?;
+	let backups = Backups::new(dirs.data_dir().join("snapshots"), 10)?;
+	let interval = std::time::Duration::from_secs(24 * 60 * 60);
+	if let Err(e) = backups.create_if_older_than(&db, interval) {
+		warn!("failed to back up the database: {e}");
+	}
+	Ok(db)
 }
 
 #[tokio::main]
@@ -0,0 +1,106 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use tf_plugin::{
+	player::{detect, util::symphonia::Metadata},
+	ImportPlugin, ImportedItem, TrackInfo,
+};
+use url::Url;
+
+pub struct LocalImportPlugin;
+
+fn track_info(path: &Path, metadata: Metadata) -> Result<TrackInfo> {
+	let url = Url::from_file_path(path).map_err(|_| anyhow!("invalid path: {path:?}"))?;
+	// the file name stands for the title when there's none
+	let title = metadata.title.unwrap_or_else(|| {
+		path.file_stem()
+			.map(|stem| stem.to_string_lossy().into_owned())
+			.unwrap_or_default()
+	});
+	// the same tag as the detected tempo
+	let tempo = metadata.bpm.map(|bpm| {
+		(
+			String::from(detect::TEMPO_TAG),
+			detect::tempo_tag_value(bpm),
+		)
+	});
+	let genre = metadata
+		.genre
+		.as_deref()
+		.and_then(genre_tag)
+		.map(|tag| (tag, 1.0));
+	Ok(TrackInfo {
+		url: Arc::new(url),
+		artists: metadata.artists.into_iter().collect(),
+		title,
+		tags: tempo.into_iter().chain(genre).collect(),
+	})
+}
+
+// "Drum & Bass" becomes "drum_bass", tag names are made of letters and underscores
+fn genre_tag(genre: &str) -> Option<String> {
+	let tag = genre
+		.to_lowercase()
+		.split(|c: char| !c.is_ascii_alphabetic())
+		.filter(|word| !word.is_empty())
+		.collect::<Vec<_>>()
+		.join("_");
+	(!tag.is_empty()).then_some(tag)
+}
+
+// The files of a directory and its subdirectories
+fn files(dir: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let path = entry.path();
+		// symlinked directories are skipped, they could lead back to a parent
+		if entry.file_type()?.is_dir() {
+			files(&path, out)?;
+		} else if !path.is_dir() {
+			out.push(path);
+		}
+	}
+	Ok(())
+}
+
+impl LocalImportPlugin {
+	pub fn import_impl(&self, path: &Path) -> Result<ImportedItem> {
+		if !path.is_dir() {
+			return Ok(ImportedItem::Track(track_info(
+				path,
+				Metadata::from_file(path)?,
+			)?));
+		}
+
+		let mut paths = vec![];
+		files(path, &mut paths)?;
+		// what can't be read isn't audio
+		let mut tracks = paths
+			.into_iter()
+			.filter_map(|path| Metadata::from_file(&path).ok().map(|m| (path, m)))
+			.collect::<Vec<_>>();
+		tracks.sort_by(|(a_path, a), (b_path, b)| {
+			(&a.album, a.track_number, a_path).cmp(&(&b.album, b.track_number, b_path))
+		});
+		Ok(ImportedItem::Playlist(
+			tracks
+				.into_iter()
+				.map(|(path, metadata)| track_info(&path, metadata))
+				.collect::<Result<_>>()?,
+		))
+	}
+}
+
+impl ImportPlugin for LocalImportPlugin {
+	fn import(&mut self, url: &Url) -> Option<Result<ImportedItem>> {
+		if url.scheme() != "file" {
+			return None;
+		}
+		let path = url.to_file_path().ok()?;
+		Some(self.import_impl(&path))
+	}
+}
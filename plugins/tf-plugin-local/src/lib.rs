@@ -1,16 +1,22 @@
 use anyhow::Result;
 use tf_plugin::{
 	player::{SourcePlugin, TrackInfo, TrackSource},
-	Plugin,
+	ImportPlugin, Plugin,
 };
 use url::Url;
 
+mod import;
+
 pub struct Local;
 
 impl Plugin for Local {
 	fn get_source_plugin(&self) -> Option<Box<dyn tf_plugin::SourcePlugin>> {
 		Some(Box::new(LocalSourcePlugin))
 	}
+
+	fn get_import_plugin(&self) -> Option<Box<dyn ImportPlugin>> {
+		Some(Box::new(import::LocalImportPlugin))
+	}
 }
 
 pub struct LocalSourcePlugin;
@@ -25,9 +31,14 @@ impl SourcePlugin for LocalSourcePlugin {
 			if let Ok(path) = url.to_file_path() {
 				let source =
 					tf_plugin::player::util::symphonia::Source::from_file(path).map(|source| {
+						let replay_gain = source.metadata.replay_gain;
 						TrackSource {
 							info: TrackInfo {
 								duration: source.duration,
+								loudness: replay_gain.track_loudness(),
+								album_loudness: replay_gain.album_loudness(),
+								cover: source.metadata.cover.clone(),
+								..Default::default()
 							},
 							sample_rate: source.sample_rate,
 							signal: Box::new(source),
@@ -44,6 +44,7 @@ impl SoundcloudImportPlugin {
 				url: Arc::new(url.clone()),
 				artists: im::Vector::from_iter(once(track.user.username)),
 				title: track.title,
+				tags: im::HashMap::new(),
 			})),
 			ResolvedItem::Playlist(playlist) => ImportedItem::Playlist(
 				playlist
@@ -54,6 +55,7 @@ impl SoundcloudImportPlugin {
 							url: Arc::new(track.permalink_url.clone()),
 							artists: im::Vector::from_iter(once(track.user.username.clone())),
 							title: track.title.clone(),
+							tags: im::HashMap::new(),
 						})
 					})
 					.collect(),
@@ -1,4 +1,4 @@
-use std::{sync::Arc, time::Duration};
+use std::{ops::Range, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use hls_m3u8::MediaPlaylist;
@@ -16,31 +16,29 @@ mod plugin;
 pub use plugin::SoundcloudSourcePlugin;
 
 pub struct SoundcloudSource {
+	duration: Duration,
 	segment_infos: SegmentInfos,
 	cache: Arc<Mutex<SegmentCache>>,
 	pub source: util::symphonia::Source,
 }
 
 impl SoundcloudSource {
-	pub fn new(media_playlist: &MediaPlaylist) -> Result<Self> {
+	// `key` identifies the track in the disk cache
+	pub fn new(key: &str, media_playlist: &MediaPlaylist) -> Result<Self> {
 		let duration = media_playlist.duration();
 		let segment_infos = SegmentInfos::from_hls(&media_playlist);
 		let cache = Arc::new(Mutex::new(SegmentCache::new(segment_infos.0.len())));
 
-		hls::Fetcher::spawn(duration, segment_infos.clone(), cache.clone())?;
+		hls::Fetcher::spawn(key, duration, segment_infos.clone(), cache.clone())?;
 
-		let hls_source = hls::MediaSource::new(
-			duration,
-			segment_infos.clone(),
-			cache.clone(),
-			Duration::ZERO,
-		);
+		let hls_source = hls::MediaSource::new(duration, segment_infos.clone(), cache.clone(), 0);
 
 		let mss = MediaSourceStream::new(Box::new(hls_source), Default::default());
 		let format = symphonia::default::formats::MpaReader::try_new(mss, &Default::default())?;
 		let symphonia_source = util::symphonia::Source::from_format_reader(Box::new(format))?;
 
 		Ok(Self {
+			duration,
 			segment_infos,
 			cache,
 			source: symphonia_source,
@@ -49,25 +47,38 @@ impl SoundcloudSource {
 }
 
 impl Source for SoundcloudSource {
-	fn seek(&mut self, pos: Duration) -> Result<(), SourceError> {
-		tracing::error!("SEEKING TO {pos:?}");
-		// Once symphonia's Mp3Reader supports SeekMode::Coarse,
-		// we can just replace this by: self.source.seek(pos)
-		// Until then, we recreate the source at the new location
+	// The reader is recreated at the start of the segment holding `pos`, whose time is known
+	// exactly, then the rest is decoded and dropped.
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+		let pos = pos.min(self.duration);
+		let (segment_idx, segment_start) = self.segment_infos.segment_start(pos);
 		let hls_source = hls::MediaSource::new(
-			self.source.duration,
+			self.duration,
 			self.segment_infos.clone(),
 			self.cache.clone(),
-			pos,
+			segment_idx,
 		);
 		let mss = MediaSourceStream::new(Box::new(hls_source), Default::default());
-		let format =
-			symphonia::default::formats::MpaReader::try_new(mss, &Default::default()).unwrap();
-		self.source = util::symphonia::Source::from_format_reader(Box::new(format)).unwrap();
-		Ok(())
+		let format = symphonia::default::formats::MpaReader::try_new(mss, &Default::default())
+			.map_err(|e| SourceError::General(Box::new(e)))?;
+		self.source = util::symphonia::Source::from_format_reader(Box::new(format))
+			.map_err(|e| SourceError::General(e.into()))?;
+		let sample_rate = self.source.sample_rate;
+		let skipped = self
+			.source
+			.skip(((pos - segment_start).as_secs_f64() * sample_rate).round() as u64)?;
+		Ok(segment_start + Duration::from_secs_f64(skipped as f64 / sample_rate))
 	}
 
-	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<(), SourceError> {
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
 		self.source.next(buf)
 	}
+
+	fn buffering(&self) -> Option<f32> {
+		self.cache.lock().buffering()
+	}
+
+	fn buffered(&self) -> Vec<Range<Duration>> {
+		self.cache.lock().buffered(&self.segment_infos)
+	}
 }
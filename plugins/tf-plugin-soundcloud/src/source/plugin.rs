@@ -1,7 +1,11 @@
-use std::convert::TryFrom;
+use std::{convert::TryFrom, io::Write};
 
 use anyhow::{anyhow, Result};
-use tf_plugin::player::{SourcePlugin, TrackInfo, TrackSource};
+use hls_m3u8::MediaPlaylist;
+use tf_plugin::player::{
+	util::hls::{self, SegmentInfos},
+	SourcePlugin, TrackInfo, TrackSource,
+};
 use url::Url;
 
 use crate::api;
@@ -12,6 +16,27 @@ pub struct SoundcloudSourcePlugin {
 
 impl SoundcloudSourcePlugin {
 	pub fn handle(&self, url: &Url) -> Result<TrackSource> {
+		let hls = self.media_playlist(url)?;
+		let source = super::SoundcloudSource::new(&cache_key(url), &hls)?;
+
+		Ok(TrackSource {
+			info: TrackInfo {
+				duration: source.source.duration,
+				..Default::default()
+			},
+			sample_rate: source.source.sample_rate,
+			signal: Box::new(source),
+		})
+	}
+
+	// The segments are MP3, put together they make a file as is.
+	pub fn download_track(&self, url: &Url, writer: &mut dyn Write) -> Result<&'static str> {
+		let hls = self.media_playlist(url)?;
+		hls::download(&cache_key(url), &SegmentInfos::from_hls(&hls), writer)?;
+		Ok("mp3")
+	}
+
+	fn media_playlist(&self, url: &Url) -> Result<MediaPlaylist<'static>> {
 		let api::ResolveResponse::Track(resolved) = serde_json::from_str(
 			&ureq::get(&format!(
 				"https://api-v2.soundcloud.com/resolve?client_id={}&url={}",
@@ -19,8 +44,9 @@ impl SoundcloudSourcePlugin {
 			))
 			.call()?
 			.into_string()?,
-		)? else {
-			return Err(anyhow!("url is not a track"))
+		)?
+		else {
+			return Err(anyhow!("url is not a track"));
 		};
 
 		let media_url = format!(
@@ -38,20 +64,20 @@ impl SoundcloudSourcePlugin {
 			.into_string()
 			.unwrap();
 
-		let hls = hls_m3u8::MediaPlaylist::try_from(hls_str.as_str()).unwrap();
-
-		let source = super::SoundcloudSource::new(&hls)?;
-
-		Ok(TrackSource {
-			info: TrackInfo {
-				duration: source.source.duration,
-			},
-			sample_rate: source.source.sample_rate,
-			signal: Box::new(source),
-		})
+		Ok(MediaPlaylist::try_from(hls_str.as_str())
+			.unwrap()
+			.into_owned())
 	}
 }
 
+// The segment urls are signed, the disk cache is keyed by the track url instead
+fn cache_key(url: &Url) -> String {
+	let mut key = url.clone();
+	key.set_query(None);
+	key.set_fragment(None);
+	key.into()
+}
+
 impl SourcePlugin for SoundcloudSourcePlugin {
 	fn name(&self) -> &'static str {
 		"Soundcloud"
@@ -61,4 +87,9 @@ impl SourcePlugin for SoundcloudSourcePlugin {
 		(url.scheme() == "https" && url.host_str() == Some("soundcloud.com"))
 			.then(|| self.handle(url))
 	}
+
+	fn download(&self, url: &Url, writer: &mut dyn Write) -> Option<Result<&'static str>> {
+		(url.scheme() == "https" && url.host_str() == Some("soundcloud.com"))
+			.then(|| self.download_track(url, writer))
+	}
 }
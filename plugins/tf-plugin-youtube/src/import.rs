@@ -60,6 +60,7 @@ impl YoutubeImportPlugin {
 						url: Arc::new(url.clone()),
 						artists: im::Vector::from_iter(once(video.channel().name().to_owned())),
 						title: video.title().to_owned(),
+						tags: im::HashMap::new(),
 					}))
 				}
 				"playlist" => {
@@ -84,6 +85,7 @@ impl YoutubeImportPlugin {
 							),
 							artists: im::Vector::from_iter(once(video.channel().name().to_owned())),
 							title: video.title().to_owned(),
+							tags: im::HashMap::new(),
 						}))
 					}
 					ImportedItem::Playlist(tracks)
@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{io::Write, time::Duration};
 
 use anyhow::{anyhow, Result};
 use symphonia::core::{io::MediaSourceStream, probe::Hint};
@@ -16,6 +16,32 @@ pub struct YoutubeSourcePlugin {
 
 impl YoutubeSourcePlugin {
 	pub fn handle(&self, url: &Url) -> Result<TrackSource> {
+		let (duration, stream_url, key, mime) = self.resolve(url)?;
+		let source = YoutubeSource::new(&stream_url, &key, &mime)?;
+
+		Ok(TrackSource {
+			info: TrackInfo {
+				duration,
+				..Default::default()
+			},
+			sample_rate: 44100.0,
+			signal: Box::new(source),
+		})
+	}
+
+	// The audio stream is already a file, MP4 unless the video only has WebM audio.
+	pub fn download_track(&self, url: &Url, writer: &mut dyn Write) -> Result<&'static str> {
+		let (_, stream_url, key, mime) = self.resolve(url)?;
+		std::io::copy(
+			&mut HttpProgressive::cached(stream_url.as_str(), &key)?,
+			&mut *writer,
+		)?;
+		Ok(extension(&mime))
+	}
+
+	// Finds the duration and the audio stream of the video, along with its key in the disk cache
+	// and its mime type
+	fn resolve(&self, url: &Url) -> Result<(Duration, Url, String, String)> {
 		let video_id: ytextract::video::Id = url
 			.query_pairs()
 			.find(|pair| pair.0 == "v")
@@ -32,18 +58,33 @@ impl YoutubeSourcePlugin {
 				.unwrap()
 				.filter(|s| s.is_audio())
 				.collect::<Vec<_>>();
-			Result::<_, anyhow::Error>::Ok((video, streams[1].clone()))
+			let stream = streams
+				.iter()
+				.find(|s| s.mime_type().to_string().starts_with("audio/mp4"))
+				.or(streams.first())
+				.ok_or(anyhow!("the video has no audio stream"))?
+				.clone();
+			Result::<_, anyhow::Error>::Ok((video, stream))
 		})?;
 
-		let duration = video.duration();
+		// the stream urls expire, the cache is keyed by the video instead, along with the format
+		// of the stream as another one may be picked next time
+		let key = format!(
+			"https://www.youtube.com/watch?v={video_id}&itag={}",
+			stream.itag()
+		);
 
-		let source = YoutubeSource::new(&stream.url())?;
+		let mime = stream.mime_type().to_string();
+		Ok((video.duration(), stream.url(), key, mime))
+	}
+}
 
-		Ok(TrackSource {
-			info: TrackInfo { duration },
-			sample_rate: 44100.0,
-			signal: Box::new(source),
-		})
+// The extension of the stream's container, from a mime type like "audio/webm; codecs=opus"
+fn extension(mime: &str) -> &'static str {
+	if mime.starts_with("audio/webm") {
+		"webm"
+	} else {
+		"m4a"
 	}
 }
 
@@ -56,6 +97,11 @@ impl SourcePlugin for YoutubeSourcePlugin {
 		(url.scheme() == "https" && url.host_str() == Some("www.youtube.com"))
 			.then(|| self.handle(url))
 	}
+
+	fn download(&self, url: &Url, writer: &mut dyn Write) -> Option<Result<&'static str>> {
+		(url.scheme() == "https" && url.host_str() == Some("www.youtube.com"))
+			.then(|| self.download_track(url, writer))
+	}
 }
 
 pub struct YoutubeSource {
@@ -63,14 +109,14 @@ pub struct YoutubeSource {
 }
 
 impl YoutubeSource {
-	pub fn new(url: &Url) -> Result<Self> {
-		let media_source = HttpProgressive::new(url.as_str())?;
+	pub fn new(url: &Url, key: &str, mime: &str) -> Result<Self> {
+		let media_source = HttpProgressive::cached(url.as_str(), key)?;
 
 		debug!("created media source");
 
 		let mss = MediaSourceStream::new(Box::new(media_source), Default::default());
 		let mut hint = Hint::new();
-		hint.mime_type("audio/aac");
+		hint.with_extension(extension(mime));
 		let source = util::symphonia::Source::from_mss(mss, hint)?;
 
 		debug!("created symphonia source");
@@ -80,11 +126,11 @@ impl YoutubeSource {
 }
 
 impl Source for YoutubeSource {
-	fn seek(&mut self, pos: Duration) -> Result<(), SourceError> {
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
 		self.source.seek(pos)
 	}
 
-	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<(), SourceError> {
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
 		self.source.next(buf)
 	}
 }
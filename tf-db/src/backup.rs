@@ -0,0 +1,344 @@
+use std::{
+	cmp::Reverse,
+	fs,
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{Client, TRACKS};
+
+const MANIFEST: &str = "manifest.json";
+const DB: &str = "db.slab";
+const TMP_EXTENSION: &str = "tmp";
+// The identity, clock and change log of the device are never restored, or the clock would go
+// back in time and the changes made with the stamps it hands out again would never be synced.
+const SYNC_TREES: [&[u8]; 3] = [b"meta", b"changelog", b"registers"];
+// The settings of the device and the files it downloaded aren't restored either, they describe
+// this device as it is now rather than the library.
+const LOCAL_TREES: [&[u8]; 2] = [b"settings", b"offline_copies"];
+
+// Snapshots are written to a temporary directory which is only renamed into place once
+// it has been flushed and verified, so a crash mid-backup never leaves a snapshot that looks valid.
+#[derive(Debug, Clone)]
+pub struct Backups {
+	dir: PathBuf,
+	keep: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+	pub path: PathBuf,
+	pub created: SystemTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Manifest {
+	created: u64,
+	checksum: u32,
+}
+
+impl Backups {
+	pub fn new<P>(dir: P, keep: usize) -> Result<Self>
+	where
+		P: AsRef<Path>,
+	{
+		let dir = dir.as_ref().to_owned();
+		fs::create_dir_all(&dir)?;
+		let backups = Self { dir, keep };
+		backups.remove_incomplete()?;
+		Ok(backups)
+	}
+
+	/// Lists the snapshots, newest first.
+	pub fn list(&self) -> Result<Vec<Snapshot>> {
+		let mut snapshots = vec![];
+		for entry in fs::read_dir(&self.dir)? {
+			let path = entry?.path();
+			if path.extension().is_some() {
+				continue;
+			}
+			match read_manifest(&path) {
+				Ok(manifest) => snapshots.push(Snapshot {
+					path,
+					created: UNIX_EPOCH + Duration::from_secs(manifest.created),
+				}),
+				Err(e) => warn!("ignoring invalid snapshot {path:?}: {e}"),
+			}
+		}
+		snapshots.sort_by_key(|s| Reverse(s.created));
+		Ok(snapshots)
+	}
+
+	pub fn latest(&self) -> Result<Option<Snapshot>> {
+		Ok(self.list()?.into_iter().next())
+	}
+
+	pub fn create(&self, db: &Client) -> Result<Snapshot> {
+		let created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+		let mut name = created.to_string();
+		while self.dir.join(&name).exists() {
+			name.push('_');
+		}
+		let path = self.dir.join(&name);
+		let tmp_path = path.with_extension(TMP_EXTENSION);
+
+		let checksum = {
+			let snapshot = sled::open(tmp_path.join(DB))?;
+			copy_trees(&db.db, &snapshot, &[])?;
+			snapshot.flush()?;
+			snapshot.checksum()?
+		};
+		fs::write(
+			tmp_path.join(MANIFEST),
+			serde_json::to_vec(&Manifest { created, checksum })?,
+		)?;
+		fs::rename(&tmp_path, &path)?;
+
+		let snapshot = Snapshot {
+			path,
+			created: UNIX_EPOCH + Duration::from_secs(created),
+		};
+		self.verify(&snapshot)?;
+		debug!("created snapshot {:?}", snapshot.path);
+
+		self.rotate()?;
+		Ok(snapshot)
+	}
+
+	/// Creates a snapshot unless the latest one is more recent than `interval`.
+	pub fn create_if_older_than(
+		&self,
+		db: &Client,
+		interval: Duration,
+	) -> Result<Option<Snapshot>> {
+		if let Some(latest) = self.latest()? {
+			if latest.created.elapsed().unwrap_or_default() < interval {
+				return Ok(None);
+			}
+		}
+		self.create(db).map(Some)
+	}
+
+	/// Checks that the snapshot opens, matches the checksum it was written with, and that all of its tracks decode.
+	pub fn verify(&self, snapshot: &Snapshot) -> Result<()> {
+		let manifest = read_manifest(&snapshot.path)?;
+		let db = sled::open(snapshot.path.join(DB))?;
+		let checksum = db.checksum()?;
+		if checksum != manifest.checksum {
+			return Err(anyhow!(
+				"snapshot {:?} is corrupted: checksum {checksum:08x} instead of {:08x}",
+				snapshot.path,
+				manifest.checksum
+			));
+		}
+		let tracks = match db.tree_names().contains(&TRACKS.into()) {
+			true => crate::check_tracks(&db.open_tree(TRACKS)?)?,
+			false => vec![],
+		};
+		if !tracks.is_empty() {
+			return Err(anyhow!(
+				"snapshot {:?} contains {} undecodable tracks",
+				snapshot.path,
+				tracks.len()
+			));
+		}
+		Ok(())
+	}
+
+	/// Replaces the library in `db` with the one of the snapshot, the device's own state is kept.
+	/// The tracks that differ are recorded as new changes, so the restore reaches the other devices.
+	pub fn restore(&self, snapshot: &Snapshot, db: &mut Client) -> Result<()> {
+		self.verify(snapshot)?;
+		let source = sled::open(snapshot.path.join(DB))?;
+		let before = db.iter_valid_tracks().collect();
+		let kept = [&SYNC_TREES[..], &LOCAL_TREES[..]].concat();
+		for name in db.db.tree_names() {
+			if !kept.contains(&&*name) {
+				db.db.open_tree(name)?.clear()?;
+			}
+		}
+		copy_trees(&source, &db.db, &kept)?;
+		db.record_restored(before)?;
+		db.db.flush()?;
+		Ok(())
+	}
+
+	fn rotate(&self) -> Result<()> {
+		for snapshot in self.list()?.into_iter().skip(self.keep) {
+			debug!("removing old snapshot {:?}", snapshot.path);
+			fs::remove_dir_all(&snapshot.path)?;
+		}
+		Ok(())
+	}
+
+	fn remove_incomplete(&self) -> Result<()> {
+		for entry in fs::read_dir(&self.dir)? {
+			let path = entry?.path();
+			if path.extension().and_then(|ext| ext.to_str()) == Some(TMP_EXTENSION) {
+				warn!("removing incomplete snapshot {path:?}");
+				fs::remove_dir_all(&path)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+fn read_manifest(path: &Path) -> Result<Manifest> {
+	let manifest = fs::read(path.join(MANIFEST)).context("missing manifest")?;
+	Ok(serde_json::from_slice(&manifest)?)
+}
+
+fn copy_trees(from: &sled::Db, to: &sled::Db, skip: &[&[u8]]) -> Result<()> {
+	for name in from.tree_names() {
+		if skip.contains(&&*name) {
+			continue;
+		}
+		let (src, dst) = (from.open_tree(&name)?, to.open_tree(&name)?);
+		for kv in src.iter() {
+			let (k, v) = kv?;
+			dst.insert(k, v)?;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashMap;
+
+	use super::*;
+	use crate::Track;
+
+	fn temp_dir() -> PathBuf {
+		std::env::temp_dir().join(format!("tf-db-test-{}", uuid::Uuid::new_v4()))
+	}
+
+	fn track(title: &str) -> Track {
+		Track {
+			source: String::from("file:///foo.mp3"),
+			artists: vec![String::from("foo")],
+			title: title.to_owned(),
+			tags: HashMap::from([(String::from("bar"), 0.5)]),
+		}
+	}
+
+	#[test]
+	fn test_snapshot_restore() {
+		let dir = temp_dir();
+		let mut db = Client::new(dir.join("db.slab")).unwrap();
+		let backups = Backups::new(dir.join("snapshots"), 2).unwrap();
+
+		let id = db.add_track(&track("before")).unwrap();
+		let snapshot = backups.create(&db).unwrap();
+
+		db.set_track(id, &track("after")).unwrap();
+		db.add_track(&track("other")).unwrap();
+
+		backups.restore(&snapshot, &mut db).unwrap();
+		let tracks = db.iter_tracks().collect::<Result<Vec<_>>>().unwrap();
+		assert_eq!(tracks.len(), 1);
+		assert_eq!(tracks[0].1.title, "before");
+
+		fs::remove_dir_all(dir).ok();
+	}
+
+	#[test]
+	fn test_restore_keeps_local_state() {
+		let dir = temp_dir();
+		let mut db = Client::new(dir.join("db.slab")).unwrap();
+		let backups = Backups::new(dir.join("snapshots"), 2).unwrap();
+
+		let id = db.add_track(&track("before")).unwrap();
+		db.set_setting("volume", &0.5).unwrap();
+		let snapshot = backups.create(&db).unwrap();
+
+		db.set_setting("volume", &0.8).unwrap();
+		let copy = crate::OfflineCopy {
+			path: dir.join("copy.m4a"),
+			source: String::from("file:///foo.mp3"),
+		};
+		db.set_offline_copy(id, &copy).unwrap();
+
+		backups.restore(&snapshot, &mut db).unwrap();
+		assert_eq!(db.get_setting::<f64>("volume").unwrap(), Some(0.8));
+		assert_eq!(
+			db.get_offline_copy(id).unwrap().map(|c| c.path),
+			Some(copy.path)
+		);
+
+		fs::remove_dir_all(dir).ok();
+	}
+
+	#[test]
+	fn test_restore_then_sync() {
+		let dir = temp_dir();
+		let shared = dir.join("shared");
+		let mut laptop = Client::new(dir.join("laptop.slab")).unwrap();
+		let mut desktop = Client::new(dir.join("desktop.slab")).unwrap();
+		let backups = Backups::new(dir.join("snapshots"), 2).unwrap();
+
+		let id = laptop.add_track(&track("before")).unwrap();
+		laptop.sync(&shared).unwrap();
+		desktop.sync(&shared).unwrap();
+		let snapshot = backups.create(&laptop).unwrap();
+
+		laptop.set_track(id, &track("after")).unwrap();
+		laptop.set_tag(id, "mood", 0.2).unwrap();
+		laptop.sync(&shared).unwrap();
+		desktop.sync(&shared).unwrap();
+		assert_eq!(desktop.get_track(id).unwrap().title, "after");
+
+		backups.restore(&snapshot, &mut laptop).unwrap();
+		// edited after the restore, with a clock that didn't go back
+		laptop.set_tag(id, "speed", 0.7).unwrap();
+		laptop.sync(&shared).unwrap();
+		desktop.sync(&shared).unwrap();
+
+		let track = desktop.get_track(id).unwrap();
+		assert_eq!(track.title, "before");
+		assert_eq!(track.tags.get("speed"), Some(&0.7));
+		assert_eq!(track.tags.get("mood"), None);
+		assert_eq!(track, laptop.get_track(id).unwrap());
+
+		fs::remove_dir_all(dir).ok();
+	}
+
+	#[test]
+	fn test_rotation() {
+		let dir = temp_dir();
+		let db = Client::new(dir.join("db.slab")).unwrap();
+		let backups = Backups::new(dir.join("snapshots"), 2).unwrap();
+
+		for _ in 0..4 {
+			backups.create(&db).unwrap();
+		}
+		assert_eq!(backups.list().unwrap().len(), 2);
+		assert!(backups
+			.create_if_older_than(&db, Duration::from_secs(3600))
+			.unwrap()
+			.is_none());
+
+		fs::remove_dir_all(dir).ok();
+	}
+
+	#[test]
+	fn test_integrity() {
+		let dir = temp_dir();
+		let mut db = Client::new(dir.join("db.slab")).unwrap();
+
+		db.add_track(&track("valid")).unwrap();
+		let corrupt = uuid::Uuid::new_v4();
+		db.tracks.insert(corrupt, b"{not json".as_slice()).unwrap();
+
+		let report = db.check_integrity().unwrap();
+		assert_eq!(report.len(), 1);
+		assert_eq!(report[0].key, corrupt.as_bytes().to_vec());
+		assert_eq!(db.list_filtered(&crate::Filter::All).unwrap().len(), 1);
+
+		fs::remove_dir_all(dir).ok();
+	}
+}
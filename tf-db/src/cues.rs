@@ -0,0 +1,134 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+
+// Positions in the audio of a track, in seconds. Like the waveform they are kept locally,
+// as they only make sense for the audio they were found in.
+
+// Where the audio starts and ends, leaving out the silence around it. Detected by the player.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Trim {
+	pub start: f32,
+	pub end: f32,
+}
+
+// Set by the user, they are preferred to the trim
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cues {
+	/// Where playback starts
+	pub cue_in: Option<f32>,
+	/// Where playback stops
+	pub cue_out: Option<f32>,
+	/// Positions to jump to, in order
+	#[serde(default)]
+	pub points: Vec<f32>,
+}
+
+impl Client {
+	pub fn get_trim(&self, id: Uuid) -> Result<Option<Trim>> {
+		self.trims
+			.get(id)?
+			.map(|t| Ok(serde_json::from_slice(&t)?))
+			.transpose()
+	}
+
+	pub fn set_trim(&mut self, id: Uuid, trim: Trim) -> Result<()> {
+		self.trims.insert(id, serde_json::to_vec(&trim)?)?;
+		Ok(())
+	}
+
+	pub fn get_cues(&self, id: Uuid) -> Result<Cues> {
+		Ok(self
+			.cues
+			.get(id)?
+			.map(|c| serde_json::from_slice(&c))
+			.transpose()?
+			.unwrap_or_default())
+	}
+
+	// Keeps the points in order
+	pub fn add_cue_point(&mut self, id: Uuid, point: f32) -> Result<()> {
+		let mut cues = self.get_cues(id)?;
+		let i = cues.points.partition_point(|p| *p < point);
+		cues.points.insert(i, point);
+		self.set_cues(id, &cues)
+	}
+
+	pub fn set_cues(&mut self, id: Uuid, cues: &Cues) -> Result<()> {
+		if *cues == Cues::default() {
+			self.cues.remove(id)?;
+		} else {
+			self.cues.insert(id, serde_json::to_vec(cues)?)?;
+		}
+		Ok(())
+	}
+
+	/// The part of the audio that is played: from the cue in or the end of the leading silence,
+	/// to the cue out or the start of the trailing silence. `None` means the end of the audio.
+	pub fn get_play_range(&self, id: Uuid) -> Result<(f32, Option<f32>)> {
+		let cues = self.get_cues(id)?;
+		let trim = self.get_trim(id)?;
+		Ok((
+			cues.cue_in.or(trim.map(|t| t.start)).unwrap_or(0.0),
+			cues.cue_out.or(trim.map(|t| t.end)),
+		))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::collections::HashMap;
+
+	use super::*;
+	use crate::Track;
+
+	#[test]
+	fn test_play_range() {
+		let dir = std::env::temp_dir().join(format!("tf-db-test-{}", Uuid::new_v4()));
+		let mut db = Client::new(dir.join("db.slab")).unwrap();
+		let id = db
+			.add_track(&Track {
+				source: String::from("https://youtu.be/foo"),
+				artists: vec![],
+				title: String::from("foo"),
+				tags: HashMap::new(),
+			})
+			.unwrap();
+		assert_eq!(db.get_play_range(id).unwrap(), (0.0, None));
+
+		db.set_trim(
+			id,
+			Trim {
+				start: 4.0,
+				end: 200.0,
+			},
+		)
+		.unwrap();
+		assert_eq!(db.get_play_range(id).unwrap(), (4.0, Some(200.0)));
+
+		// the talking at the end is left out too
+		db.set_cues(
+			id,
+			&Cues {
+				cue_in: None,
+				cue_out: Some(180.0),
+				..Default::default()
+			},
+		)
+		.unwrap();
+		assert_eq!(db.get_play_range(id).unwrap(), (4.0, Some(180.0)));
+
+		db.add_cue_point(id, 60.0).unwrap();
+		db.add_cue_point(id, 30.0).unwrap();
+		let cues = db.get_cues(id).unwrap();
+		assert_eq!(cues.points, [30.0, 60.0]);
+		assert_eq!(cues.cue_out, Some(180.0));
+
+		db.delete_track(id).unwrap();
+		assert_eq!(db.get_play_range(id).unwrap(), (0.0, None));
+
+		std::fs::remove_dir_all(dir).ok();
+	}
+}
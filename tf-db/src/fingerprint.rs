@@ -0,0 +1,37 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+
+// Computed by the player from the start of the audio, it is kept locally like the waveform.
+// Tracks with the same audio have similar fingerprints whatever their source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprint {
+	pub hashes: Vec<u32>,
+}
+
+impl Client {
+	pub fn get_fingerprint(&self, id: Uuid) -> Result<Option<Fingerprint>> {
+		self.fingerprints
+			.get(id)?
+			.map(|f| Ok(serde_json::from_slice(&f)?))
+			.transpose()
+	}
+
+	pub fn set_fingerprint(&mut self, id: Uuid, fingerprint: &Fingerprint) -> Result<()> {
+		self.fingerprints
+			.insert(id, serde_json::to_vec(fingerprint)?)?;
+		Ok(())
+	}
+
+	pub fn iter_fingerprints(&self) -> impl Iterator<Item = Result<(Uuid, Fingerprint)>> {
+		self.fingerprints.iter().map(|kv| {
+			let (id, fingerprint) = kv?;
+			Ok((
+				Uuid::from_bytes(id.as_ref().try_into()?),
+				serde_json::from_slice(&fingerprint)?,
+			))
+		})
+	}
+}
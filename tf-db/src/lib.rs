@@ -2,8 +2,12 @@ use std::{collections::HashSet, path::Path};
 
 use anyhow::{anyhow, Result};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use sync::Op;
+use tracing::warn;
 use uuid::Uuid;
 
+pub mod backup;
+
 mod data;
 pub use data::Track;
 
@@ -12,11 +16,57 @@ pub use filter::Filter;
 
 mod tags;
 
+mod loudness;
+pub use loudness::Loudness;
+
+mod waveform;
+pub use waveform::Waveform;
+
+mod fingerprint;
+pub use fingerprint::Fingerprint;
+
+mod cues;
+pub use cues::{Cues, Trim};
+
+mod offline;
+pub use offline::{OfflineCopy, OfflineMark};
+
+mod schedule;
+pub use schedule::{Alarm, Schedule, Stop};
+
+mod settings;
+
+mod suggestions;
+
+pub mod sync;
+
+const TRACKS: &[u8] = b"tracks";
+
+#[derive(Debug, Clone)]
+pub struct CorruptRecord {
+	pub key: Vec<u8>,
+	pub error: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct Client {
 	pub db: sled::Db,
 	pub tracks: sled::Tree,
 	pub tags: sled::Tree,
+	pub meta: sled::Tree,
+	pub changelog: sled::Tree,
+	pub registers: sled::Tree,
+	pub loudness: sled::Tree,
+	pub waveforms: sled::Tree,
+	pub fingerprints: sled::Tree,
+	pub trims: sled::Tree,
+	pub cues: sled::Tree,
+	pub offline_marks: sled::Tree,
+	pub offline_copies: sled::Tree,
+	pub settings: sled::Tree,
+	pub suggested_tags: sled::Tree,
+	pub dismissed_tags: sled::Tree,
+	pub analyses: sled::Tree,
 }
 
 impl Client {
@@ -24,29 +74,96 @@ impl Client {
 	where
 		P: AsRef<Path>,
 	{
-		let db = sled::open(path)?;
-		let tracks = db.open_tree(b"tracks")?;
+		let db = sled::open(&path)?;
+		let tracks = db.open_tree(TRACKS)?;
 		let tags = db.open_tree(b"tags")?;
+		let meta = db.open_tree(b"meta")?;
+		let changelog = db.open_tree(b"changelog")?;
+		let registers = db.open_tree(b"registers")?;
+		let loudness = db.open_tree(b"loudness")?;
+		let waveforms = db.open_tree(b"waveforms")?;
+		let fingerprints = db.open_tree(b"fingerprints")?;
+		let trims = db.open_tree(b"trims")?;
+		let cues = db.open_tree(b"cues")?;
+		let offline_marks = db.open_tree(b"offline_marks")?;
+		let offline_copies = db.open_tree(b"offline_copies")?;
+		let settings = db.open_tree(b"settings")?;
+		let suggested_tags = db.open_tree(b"suggested_tags")?;
+		let dismissed_tags = db.open_tree(b"dismissed_tags")?;
+		let analyses = db.open_tree(b"analyses")?;
 
-		Ok(Client { db, tracks, tags })
+		let mut client = Client {
+			db,
+			tracks,
+			tags,
+			meta,
+			changelog,
+			registers,
+			loudness,
+			waveforms,
+			fingerprints,
+			trims,
+			cues,
+			offline_marks,
+			offline_copies,
+			settings,
+			suggested_tags,
+			dismissed_tags,
+			analyses,
+		};
+		client.init_device(path.as_ref())?;
+		Ok(client)
 	}
 
 	pub fn add_track(&mut self, track: &Track) -> Result<Uuid> {
 		let id = Uuid::new_v4();
-		let track = serde_json::to_vec(track)?;
-		self.tracks.insert(id, track)?;
+		self.record_track(id, track)?;
 		Ok(id)
 	}
 
 	pub fn set_track(&mut self, id: Uuid, track: &Track) -> Result<Uuid> {
-		let track = serde_json::to_vec(&track)?;
-		self.tracks.insert(id, track)?;
+		let Ok(old) = self.get_track(id) else {
+			self.record_track(id, track)?;
+			return Ok(id);
+		};
+		if (&old.source, &old.artists, &old.title) != (&track.source, &track.artists, &track.title)
+		{
+			self.record(Op::SetInfo {
+				track: id,
+				source: track.source.clone(),
+				artists: track.artists.clone(),
+				title: track.title.clone(),
+			})?;
+		}
+		for (tag, value) in &track.tags {
+			if old.tags.get(tag) != Some(value) {
+				self.set_tag(id, tag, *value)?;
+			}
+		}
+		for tag in old.tags.keys().filter(|tag| !track.tags.contains_key(*tag)) {
+			self.record(Op::SetTag {
+				track: id,
+				tag: tag.clone(),
+				value: None,
+			})?;
+		}
 		Ok(id)
 	}
 
-	pub fn delete_track(&mut self, id: Uuid) -> Result<()> {
-		self.tracks.remove(id)?;
-		Ok(())
+	/// Returns the offline copy of the track, its file is left to the caller.
+	pub fn delete_track(&mut self, id: Uuid) -> Result<Option<OfflineCopy>> {
+		self.loudness.remove(id)?;
+		self.waveforms.remove(id)?;
+		self.fingerprints.remove(id)?;
+		self.trims.remove(id)?;
+		self.cues.remove(id)?;
+		let copy = self.remove_offline_copy(id)?;
+		self.suggested_tags.remove(id)?;
+		self.dismissed_tags.remove(id)?;
+		self.analyses.remove(id)?;
+		self.unmark_offline(&OfflineMark::Track(id))?;
+		self.record(Op::Delete { track: id })?;
+		Ok(copy)
 	}
 
 	pub fn get_track(&self, id: Uuid) -> Result<Track> {
@@ -68,23 +185,35 @@ impl Client {
 		})
 	}
 
+	// Like `iter_tracks`, but skips the records that can't be decoded.
+	// Use `check_integrity` to find out about them.
+	pub fn iter_valid_tracks(&mut self) -> impl Iterator<Item = (Uuid, Track)> {
+		self.iter_tracks().filter_map(|track| match track {
+			Ok(track) => Some(track),
+			Err(e) => {
+				warn!("skipping undecodable track: {e}");
+				None
+			}
+		})
+	}
+
 	// Apply the filter to the list of tracks.
 	pub fn list_filtered(&mut self, filter: &Filter) -> Result<Vec<(Uuid, Track)>> {
 		Ok(self
-			.iter_tracks()
-			.filter(|track| {
-				track
-					.as_ref()
-					.map(|(_, t)| filter.matches(t))
-					.unwrap_or(true)
-			})
-			.collect::<Result<_, _>>()?)
+			.iter_valid_tracks()
+			.filter(|(_, t)| filter.matches(t))
+			.collect())
+	}
+
+	/// Lists the track records that can't be decoded.
+	pub fn check_integrity(&self) -> Result<Vec<CorruptRecord>> {
+		check_tracks(&self.tracks)
 	}
 
 	pub fn get_tags(&mut self) -> Result<HashSet<String>> {
 		let mut tags = HashSet::default();
-		for t in self.iter_tracks() {
-			for (tag_name, _) in &t?.1.tags {
+		for t in self.iter_valid_tracks() {
+			for (tag_name, _) in &t.1.tags {
 				tags.insert(tag_name.to_owned());
 			}
 		}
@@ -106,3 +235,23 @@ impl Client {
 			.collect())
 	}
 }
+
+fn check_tracks(tracks: &sled::Tree) -> Result<Vec<CorruptRecord>> {
+	let mut corrupt = vec![];
+	for kv in tracks.iter() {
+		let (id, track) = kv?;
+		let error = match Uuid::from_slice(&id) {
+			Ok(_) => serde_json::from_slice::<Track>(&track)
+				.err()
+				.map(|e| e.to_string()),
+			Err(e) => Some(e.to_string()),
+		};
+		if let Some(error) = error {
+			corrupt.push(CorruptRecord {
+				key: id.to_vec(),
+				error,
+			});
+		}
+	}
+	Ok(corrupt)
+}
@@ -0,0 +1,28 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+
+// Measured by the player, it is kept locally and isn't part of the synced changes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Loudness {
+	/// Integrated loudness, in LUFS
+	pub integrated: f32,
+	/// True peak, in dBTP
+	pub true_peak: f32,
+}
+
+impl Client {
+	pub fn get_loudness(&self, id: Uuid) -> Result<Option<Loudness>> {
+		self.loudness
+			.get(id)?
+			.map(|l| Ok(serde_json::from_slice(&l)?))
+			.transpose()
+	}
+
+	pub fn set_loudness(&mut self, id: Uuid, loudness: Loudness) -> Result<()> {
+		self.loudness.insert(id, serde_json::to_vec(&loudness)?)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{Client, Filter, Track};
+
+// Tracks are made available offline one by one, or as the results of a query.
+// The marks and the copies are specific to this device, they aren't part of the synced changes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OfflineMark {
+	Track(Uuid),
+	Query(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OfflineCopy {
+	pub path: PathBuf,
+	/// The source it was downloaded from, the copy is outdated once the track's source changes
+	pub source: String,
+}
+
+impl Client {
+	pub fn mark_offline(&mut self, mark: &OfflineMark) -> Result<()> {
+		self.offline_marks.insert(serde_json::to_vec(mark)?, &[])?;
+		Ok(())
+	}
+
+	pub fn unmark_offline(&mut self, mark: &OfflineMark) -> Result<()> {
+		self.offline_marks.remove(serde_json::to_vec(mark)?)?;
+		Ok(())
+	}
+
+	pub fn is_marked_offline(&self, mark: &OfflineMark) -> Result<bool> {
+		Ok(self.offline_marks.contains_key(serde_json::to_vec(mark)?)?)
+	}
+
+	pub fn offline_marks(&self) -> Result<Vec<OfflineMark>> {
+		self.offline_marks
+			.iter()
+			.keys()
+			.map(|mark| Ok(serde_json::from_slice(&mark?)?))
+			.collect()
+	}
+
+	// The tracks that should have an offline copy, whether or not they have one yet.
+	pub fn list_offline(&mut self) -> Result<Vec<(Uuid, Track)>> {
+		let mut ids = vec![];
+		let mut filters = vec![];
+		for mark in self.offline_marks()? {
+			match mark {
+				OfflineMark::Track(id) => ids.push(id),
+				OfflineMark::Query(query) => match query.parse::<Filter>() {
+					Ok(filter) => filters.push(filter),
+					Err(e) => warn!("ignoring offline query `{query}`: {e}"),
+				},
+			}
+		}
+		let mut tracks = HashMap::new();
+		for (id, track) in self.iter_valid_tracks() {
+			if ids.contains(&id) || filters.iter().any(|f| f.matches(&track)) {
+				tracks.insert(id, track);
+			}
+		}
+		Ok(tracks.into_iter().collect())
+	}
+
+	pub fn get_offline_copy(&self, id: Uuid) -> Result<Option<OfflineCopy>> {
+		self.offline_copies
+			.get(id)?
+			.map(|c| Ok(serde_json::from_slice(&c)?))
+			.transpose()
+	}
+
+	pub fn set_offline_copy(&mut self, id: Uuid, copy: &OfflineCopy) -> Result<()> {
+		self.offline_copies.insert(id, serde_json::to_vec(copy)?)?;
+		Ok(())
+	}
+
+	// The file itself is left to the caller.
+	pub fn remove_offline_copy(&mut self, id: Uuid) -> Result<Option<OfflineCopy>> {
+		self.offline_copies
+			.remove(id)?
+			.map(|c| Ok(serde_json::from_slice(&c)?))
+			.transpose()
+	}
+
+	pub fn iter_offline_copies(&self) -> impl Iterator<Item = Result<(Uuid, OfflineCopy)>> {
+		self.offline_copies.iter().map(|kv| {
+			let (id, copy) = kv?;
+			Ok((Uuid::from_slice(&id)?, serde_json::from_slice(&copy)?))
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn track(source: &str, bar: f32) -> Track {
+		Track {
+			source: source.to_owned(),
+			artists: vec![String::from("foo")],
+			title: String::from("title"),
+			tags: HashMap::from([(String::from("bar"), bar)]),
+		}
+	}
+
+	#[test]
+	fn test_marks() {
+		let dir = std::env::temp_dir().join(format!("tf-db-test-{}", Uuid::new_v4()));
+		let mut db = Client::new(dir.join("db.slab")).unwrap();
+		let single = db.add_track(&track("https://a", 0.1)).unwrap();
+		let matching = db.add_track(&track("https://b", 0.9)).unwrap();
+		db.add_track(&track("https://c", 0.2)).unwrap();
+
+		db.mark_offline(&OfflineMark::Track(single)).unwrap();
+		db.mark_offline(&OfflineMark::Query(String::from("bar > 0.5")))
+			.unwrap();
+		let mut offline = db
+			.list_offline()
+			.unwrap()
+			.into_iter()
+			.map(|(id, _)| id)
+			.collect::<Vec<_>>();
+		offline.sort();
+		let mut expected = vec![single, matching];
+		expected.sort();
+		assert_eq!(offline, expected);
+
+		db.unmark_offline(&OfflineMark::Track(single)).unwrap();
+		assert!(!db.is_marked_offline(&OfflineMark::Track(single)).unwrap());
+		assert_eq!(db.list_offline().unwrap().len(), 1);
+
+		let copy = OfflineCopy {
+			path: dir.join("b.mp3"),
+			source: String::from("https://b"),
+		};
+		db.set_offline_copy(matching, &copy).unwrap();
+		assert_eq!(db.get_offline_copy(matching).unwrap(), Some(copy.clone()));
+		// the file is left to the caller
+		assert_eq!(db.delete_track(matching).unwrap(), Some(copy));
+		assert_eq!(db.get_offline_copy(matching).unwrap(), None);
+
+		std::fs::remove_dir_all(dir).ok();
+	}
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+
+const SCHEDULE_SETTING: &str = "schedule";
+
+// Playback actions planned ahead, like a sleep timer or an alarm.
+// They are kept with the settings of this device, so they survive a restart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+	pub stop: Option<Stop>,
+	pub alarm: Option<Alarm>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stop {
+	/// Playback fades out until this time, in seconds since the Unix epoch
+	At(u64),
+	/// Playback stops once this track is over
+	AfterTrack(Uuid),
+	/// Playback stops once the queue is over, with the tracks added to it in the meantime
+	AfterQueue,
+}
+
+/// Plays the tracks of a query every day at a time of the local day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alarm {
+	pub hour: u32,
+	pub minute: u32,
+	pub query: String,
+}
+
+impl Client {
+	pub fn get_schedule(&self) -> Result<Schedule> {
+		Ok(self.get_setting(SCHEDULE_SETTING)?.unwrap_or_default())
+	}
+
+	pub fn set_schedule(&mut self, schedule: &Schedule) -> Result<()> {
+		self.set_setting(SCHEDULE_SETTING, schedule)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_schedule() {
+		let dir = std::env::temp_dir().join(format!("tf-db-test-{}", Uuid::new_v4()));
+		let mut db = Client::new(dir.join("db.slab")).unwrap();
+		assert_eq!(db.get_schedule().unwrap(), Schedule::default());
+
+		let schedule = Schedule {
+			stop: Some(Stop::AfterTrack(Uuid::new_v4())),
+			alarm: Some(Alarm {
+				hour: 7,
+				minute: 30,
+				query: String::from("calm > 0.5"),
+			}),
+		};
+		db.set_schedule(&schedule).unwrap();
+		assert_eq!(db.get_schedule().unwrap(), schedule);
+		std::fs::remove_dir_all(dir).ok();
+	}
+}
@@ -0,0 +1,19 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::Client;
+
+// Preferences of this installation, they aren't synced with the other devices.
+impl Client {
+	pub fn get_setting<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+		self.settings
+			.get(key)?
+			.map(|value| Ok(serde_json::from_slice(&value)?))
+			.transpose()
+	}
+
+	pub fn set_setting<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
+		self.settings.insert(key, serde_json::to_vec(value)?)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,144 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+
+use crate::Client;
+
+// Tags guessed from the audio, like the tempo. They are kept locally, and only become part of the
+// track, and of the synced changes, once the user accepts them.
+impl Client {
+	// `None` if nothing was suggested for the track yet
+	pub fn get_suggested_tags(&self, id: Uuid) -> Result<Option<HashMap<String, f32>>> {
+		self.suggested_tags
+			.get(id)?
+			.map(|t| Ok(serde_json::from_slice(&t)?))
+			.transpose()
+	}
+
+	pub fn set_suggested_tags(&mut self, id: Uuid, tags: &HashMap<String, f32>) -> Result<()> {
+		self.suggested_tags.insert(id, serde_json::to_vec(tags)?)?;
+		Ok(())
+	}
+
+	// Adds to the current suggestions, leaving out the tags the track has or that were dismissed.
+	// Returns the suggestions as they are now.
+	pub fn suggest_tags(
+		&mut self,
+		id: Uuid,
+		tags: &HashMap<String, f32>,
+	) -> Result<HashMap<String, f32>> {
+		let own = self.get_track(id)?.tags;
+		let dismissed = self.get_dismissed_tags(id)?;
+		let mut suggested = self.get_suggested_tags(id)?.unwrap_or_default();
+		for (tag, value) in tags {
+			if !own.contains_key(tag) && !dismissed.contains(tag) {
+				suggested.insert(tag.clone(), *value);
+			}
+		}
+		self.set_suggested_tags(id, &suggested)?;
+		Ok(suggested)
+	}
+
+	pub fn accept_suggested_tag(&mut self, id: Uuid, tag: &str) -> Result<()> {
+		let value = self
+			.get_suggested_tags(id)?
+			.and_then(|tags| tags.get(tag).copied())
+			.ok_or(anyhow!("`{tag}` isn't suggested for track `{id}`"))?;
+		self.set_tag(id, tag, value)?;
+		self.dismiss_suggested_tag(id, tag)
+	}
+
+	// The tag won't be suggested again
+	pub fn dismiss_suggested_tag(&mut self, id: Uuid, tag: &str) -> Result<()> {
+		if let Some(mut tags) = self.get_suggested_tags(id)? {
+			tags.remove(tag);
+			self.set_suggested_tags(id, &tags)?;
+		}
+		let mut dismissed = self.get_dismissed_tags(id)?;
+		dismissed.insert(tag.to_owned());
+		self.dismissed_tags
+			.insert(id, serde_json::to_vec(&dismissed)?)?;
+		Ok(())
+	}
+
+	fn get_dismissed_tags(&self, id: Uuid) -> Result<HashSet<String>> {
+		self.dismissed_tags
+			.get(id)?
+			.map(|t| Ok(serde_json::from_slice(&t)?))
+			.transpose()
+			.map(Option::unwrap_or_default)
+	}
+
+	// Which version of the analysis the track went through, 0 if it wasn't analyzed yet.
+	// The track is analyzed again when the analysis gains something new.
+	pub fn get_analysis_version(&self, id: Uuid) -> Result<u32> {
+		Ok(self
+			.analyses
+			.get(id)?
+			.map(|v| u32::from_be_bytes(v.as_ref().try_into().unwrap_or_default()))
+			.unwrap_or(0))
+	}
+
+	pub fn set_analysis_version(&mut self, id: Uuid, version: u32) -> Result<()> {
+		self.analyses.insert(id, &version.to_be_bytes())?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::Track;
+
+	#[test]
+	fn test_accept() {
+		let dir = std::env::temp_dir().join(format!("tf-db-test-{}", Uuid::new_v4()));
+		let mut db = Client::new(dir.join("db.slab")).unwrap();
+		let id = db
+			.add_track(&Track {
+				source: String::from("file:///a.flac"),
+				artists: vec![String::from("foo")],
+				title: String::from("title"),
+				tags: HashMap::new(),
+			})
+			.unwrap();
+		assert_eq!(db.get_suggested_tags(id).unwrap(), None);
+
+		let suggested = HashMap::from([
+			(String::from("tempo"), 0.5),
+			(String::from("key_a_minor"), 0.8),
+		]);
+		db.set_suggested_tags(id, &suggested).unwrap();
+		assert_eq!(db.get_suggested_tags(id).unwrap(), Some(suggested));
+
+		db.accept_suggested_tag(id, "tempo").unwrap();
+		db.dismiss_suggested_tag(id, "key_a_minor").unwrap();
+		assert!(db.accept_suggested_tag(id, "key_a_minor").is_err());
+		assert_eq!(
+			db.get_track(id).unwrap().tags,
+			HashMap::from([(String::from("tempo"), 0.5)])
+		);
+		assert_eq!(db.get_suggested_tags(id).unwrap(), Some(HashMap::new()));
+
+		// neither the accepted nor the dismissed tags come back when the track is analyzed again
+		let suggested = db.suggest_tags(id, &suggested).unwrap();
+		assert_eq!(suggested, HashMap::new());
+		db.suggest_tags(id, &HashMap::from([(String::from("key_a_major"), 0.4)]))
+			.unwrap();
+		assert_eq!(
+			db.get_suggested_tags(id).unwrap(),
+			Some(HashMap::from([(String::from("key_a_major"), 0.4)]))
+		);
+
+		assert_eq!(db.get_analysis_version(id).unwrap(), 0);
+		db.set_analysis_version(id, 2).unwrap();
+		assert_eq!(db.get_analysis_version(id).unwrap(), 2);
+
+		db.delete_track(id).unwrap();
+		assert_eq!(db.get_suggested_tags(id).unwrap(), None);
+		assert_eq!(db.get_analysis_version(id).unwrap(), 0);
+
+		std::fs::remove_dir_all(dir).ok();
+	}
+}
@@ -0,0 +1,537 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{Client, Track};
+
+// Every modification of the library is recorded in a per-device change log, stamped with a
+// lamport clock. Devices exchange their logs through a shared folder, and each piece of data
+// (the info of a track, each of its tags, its deletion) is a last-writer-wins register,
+// so applying the logs in any order converges to the same state.
+
+const DEVICE: &[u8] = b"device";
+// where the database was when it got its identity
+const LOCATION: &[u8] = b"location";
+const CLOCK: &[u8] = b"clock";
+const SEEN: &[u8] = b"seen:";
+
+const INFO: &[u8] = b"info";
+const DELETED: &[u8] = b"deleted";
+const TAG: &[u8] = b"tag:";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Stamp {
+	pub time: u64,
+	pub device: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Op {
+	SetInfo {
+		track: Uuid,
+		source: String,
+		artists: Vec<String>,
+		title: String,
+	},
+	SetTag {
+		track: Uuid,
+		tag: String,
+		value: Option<f32>,
+	},
+	Delete {
+		track: Uuid,
+	},
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Change {
+	pub stamp: Stamp,
+	pub op: Op,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+	pub exported: usize,
+	pub applied: usize,
+}
+
+impl Client {
+	pub fn device_id(&self) -> Result<Uuid> {
+		let id = self.meta.get(DEVICE)?.context("device id is missing")?;
+		Ok(Uuid::from_slice(&id)?)
+	}
+
+	// Gives the database an identity, and logs the tracks that were created before the change log existed.
+	// A database copied to another place, like another machine, would share its identity with the
+	// original one and their changes would overwrite each other, so it gets a new one. Its tracks
+	// are logged again under it, as the original may not have exported everything.
+	pub(crate) fn init_device(&mut self, path: &Path) -> Result<()> {
+		let location = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+		let location = location.to_string_lossy();
+		let known = self.meta.get(LOCATION)?;
+		if self.meta.get(DEVICE)?.is_some() {
+			match known {
+				Some(known) if known != location.as_bytes() => {
+					warn!(
+						"the database was copied from {}, it syncs as a new device",
+						String::from_utf8_lossy(&known)
+					);
+				}
+				_ => {
+					self.meta.insert(LOCATION, location.as_bytes())?;
+					return Ok(());
+				}
+			}
+		}
+		self.meta.insert(DEVICE, Uuid::new_v4().as_bytes())?;
+		self.meta.insert(LOCATION, location.as_bytes())?;
+		for (id, track) in self.iter_valid_tracks().collect::<Vec<_>>() {
+			self.record_track(id, &track)?;
+		}
+		Ok(())
+	}
+
+	/// Records a local change in the log and applies it.
+	pub(crate) fn record(&mut self, op: Op) -> Result<()> {
+		let stamp = Stamp {
+			time: self.tick(0)?,
+			device: self.device_id()?,
+		};
+		let change = Change { stamp, op };
+		self.changelog
+			.insert(log_key(&stamp), serde_json::to_vec(&change)?)?;
+		self.apply(&change)?;
+		Ok(())
+	}
+
+	pub(crate) fn record_track(&mut self, id: Uuid, track: &Track) -> Result<()> {
+		self.record(Op::SetInfo {
+			track: id,
+			source: track.source.clone(),
+			artists: track.artists.clone(),
+			title: track.title.clone(),
+		})?;
+		for (tag, value) in &track.tags {
+			self.record(Op::SetTag {
+				track: id,
+				tag: tag.clone(),
+				value: Some(*value),
+			})?;
+		}
+		Ok(())
+	}
+
+	// A restore leaves the tracks as they were in a snapshot. They are recorded again where they
+	// differ from `before`, with new stamps, so the other devices follow instead of undoing it.
+	pub(crate) fn record_restored(&mut self, before: HashMap<Uuid, Track>) -> Result<()> {
+		let restored = self.iter_valid_tracks().collect::<HashMap<_, _>>();
+		for id in before.keys().filter(|id| !restored.contains_key(id)) {
+			self.record(Op::Delete { track: *id })?;
+		}
+		for (id, track) in &restored {
+			if before.get(id) == Some(track) {
+				continue;
+			}
+			for tag in self.registered_tags(*id)?.into_keys() {
+				if !track.tags.contains_key(&tag) {
+					self.record(Op::SetTag {
+						track: *id,
+						tag,
+						value: None,
+					})?;
+				}
+			}
+			self.record_track(*id, track)?;
+		}
+		Ok(())
+	}
+
+	/// Exports the local change log to `dir`, then applies the changes found in the logs of the other devices.
+	pub fn sync<P>(&mut self, dir: P) -> Result<SyncReport>
+	where
+		P: AsRef<Path>,
+	{
+		let dir = dir.as_ref();
+		fs::create_dir_all(dir)?;
+		let device = self.device_id()?;
+		self.compact(device)?;
+
+		let changes = self
+			.changelog
+			.scan_prefix(device.as_bytes())
+			.values()
+			.map(|change| Ok(serde_json::from_slice(&change?)?))
+			.collect::<Result<Vec<Change>>>()?;
+		let path = dir.join(format!("{device}.json"));
+		let tmp_path = path.with_extension("json.tmp");
+		fs::write(&tmp_path, serde_json::to_vec(&changes)?)?;
+		fs::rename(&tmp_path, &path)?;
+
+		let mut report = SyncReport {
+			exported: changes.len(),
+			applied: 0,
+		};
+
+		for entry in fs::read_dir(dir)? {
+			let path = entry?.path();
+			let Some(remote) = path
+				.file_name()
+				.and_then(|name| name.to_str())
+				.and_then(|name| name.strip_suffix(".json"))
+				.and_then(|name| Uuid::parse_str(name).ok())
+			else {
+				continue;
+			};
+			if remote == device {
+				continue;
+			}
+			let changes: Vec<Change> = match fs::read(&path)
+				.map_err(anyhow::Error::from)
+				.and_then(|log| Ok(serde_json::from_slice(&log)?))
+			{
+				Ok(changes) => changes,
+				Err(e) => {
+					warn!("skipping unreadable change log {path:?}: {e}");
+					continue;
+				}
+			};
+
+			let seen_key = [SEEN, remote.as_bytes()].concat();
+			let seen = self
+				.meta
+				.get(&seen_key)?
+				.map(|seen| read_u64(&seen))
+				.unwrap_or(0);
+			let mut last = seen;
+			for change in changes.iter().filter(|c| c.stamp.time > seen) {
+				self.tick(change.stamp.time)?;
+				self.apply(change)?;
+				last = last.max(change.stamp.time);
+				report.applied += 1;
+			}
+			self.meta.insert(seen_key, &last.to_be_bytes())?;
+		}
+		self.db.flush()?;
+
+		debug!("synced with {dir:?}: {report:?}");
+		Ok(report)
+	}
+
+	// Only the last local write of each register is kept in the log, the ones before it would
+	// lose against it wherever they are applied.
+	fn compact(&mut self, device: Uuid) -> Result<()> {
+		let mut last = HashMap::new();
+		let mut superseded = vec![];
+		for kv in self.changelog.scan_prefix(device.as_bytes()) {
+			let (key, change) = kv?;
+			let change: Change = serde_json::from_slice(&change)?;
+			if let Some(previous) = last.insert(field(&change.op), key) {
+				superseded.push(previous);
+			}
+		}
+		for key in &superseded {
+			self.changelog.remove(key)?;
+		}
+		if !superseded.is_empty() {
+			debug!("compacted {} changes out of the log", superseded.len());
+		}
+		Ok(())
+	}
+
+	// Advances the lamport clock past `seen` and returns the new time.
+	fn tick(&self, seen: u64) -> Result<u64> {
+		let time = self
+			.meta
+			.update_and_fetch(CLOCK, |old| {
+				Some(
+					(old.map(read_u64).unwrap_or(0).max(seen) + 1)
+						.to_be_bytes()
+						.to_vec(),
+				)
+			})?
+			.map(|time| read_u64(&time))
+			.unwrap_or_default();
+		Ok(time)
+	}
+
+	fn apply(&mut self, change: &Change) -> Result<()> {
+		let stamp = change.stamp;
+		match &change.op {
+			Op::SetInfo {
+				track: id,
+				source,
+				artists,
+				title,
+			} => {
+				if self.register(*id, DELETED)? > Some(stamp) {
+					return Ok(());
+				}
+				if !self.bump(*id, INFO, stamp, None)? {
+					return Ok(());
+				}
+				let mut track = match self.tracks.get(id)? {
+					Some(track) => serde_json::from_slice(&track)?,
+					None => Track {
+						source: String::new(),
+						artists: vec![],
+						title: String::new(),
+						tags: self.registered_tags(*id)?,
+					},
+				};
+				track.source = source.clone();
+				track.artists = artists.clone();
+				track.title = title.clone();
+				self.tracks.insert(id, serde_json::to_vec(&track)?)?;
+			}
+			Op::SetTag {
+				track: id,
+				tag,
+				value,
+			} => {
+				let field = [TAG, tag.as_bytes()].concat();
+				if !self.bump(*id, &field, stamp, *value)? {
+					return Ok(());
+				}
+				// the track might not have arrived yet, its tags will be picked up when it does
+				if let Some(track) = self.tracks.get(id)? {
+					let mut track: Track = serde_json::from_slice(&track)?;
+					match value {
+						Some(value) => track.tags.insert(tag.clone(), *value),
+						None => track.tags.remove(tag),
+					};
+					self.tracks.insert(id, serde_json::to_vec(&track)?)?;
+				}
+			}
+			Op::Delete { track: id } => {
+				if !self.bump(*id, DELETED, stamp, None)? {
+					return Ok(());
+				}
+				if self.register(*id, INFO)? < Some(stamp) {
+					self.tracks.remove(id)?;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn register(&self, id: Uuid, field: &[u8]) -> Result<Option<Stamp>> {
+		Ok(self
+			.registers
+			.get([id.as_bytes(), field].concat())?
+			.map(|r| serde_json::from_slice::<(Stamp, Option<f32>)>(&r))
+			.transpose()?
+			.map(|(stamp, _)| stamp))
+	}
+
+	// Updates the register if `stamp` is the most recent write, returns whether it was updated.
+	fn bump(&self, id: Uuid, field: &[u8], stamp: Stamp, value: Option<f32>) -> Result<bool> {
+		if self.register(id, field)? >= Some(stamp) {
+			return Ok(false);
+		}
+		self.registers.insert(
+			[id.as_bytes(), field].concat(),
+			serde_json::to_vec(&(stamp, value))?,
+		)?;
+		Ok(true)
+	}
+
+	fn registered_tags(&self, id: Uuid) -> Result<HashMap<String, f32>> {
+		let prefix = [id.as_bytes(), TAG].concat();
+		let mut tags = HashMap::new();
+		for kv in self.registers.scan_prefix(&prefix) {
+			let (k, v) = kv?;
+			let (_, value) = serde_json::from_slice::<(Stamp, Option<f32>)>(&v)?;
+			if let Some(value) = value {
+				tags.insert(String::from_utf8(k[prefix.len()..].to_vec())?, value);
+			}
+		}
+		Ok(tags)
+	}
+}
+
+// The register written by the operation
+fn field(op: &Op) -> (Uuid, Vec<u8>) {
+	match op {
+		Op::SetInfo { track, .. } => (*track, INFO.to_vec()),
+		Op::SetTag { track, tag, .. } => (*track, [TAG, tag.as_bytes()].concat()),
+		Op::Delete { track } => (*track, DELETED.to_vec()),
+	}
+}
+
+fn log_key(stamp: &Stamp) -> Vec<u8> {
+	[&stamp.device.as_bytes()[..], &stamp.time.to_be_bytes()].concat()
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+	u64::from_be_bytes(bytes.try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use super::*;
+
+	fn temp_dir() -> PathBuf {
+		std::env::temp_dir().join(format!("tf-db-test-{}", Uuid::new_v4()))
+	}
+
+	fn tracks(db: &mut Client) -> Vec<String> {
+		let mut tracks = db
+			.iter_valid_tracks()
+			.map(|(id, t)| {
+				let mut tags = t.tags.into_iter().collect::<Vec<_>>();
+				tags.sort_by(|a, b| a.0.cmp(&b.0));
+				format!("{id} {} {tags:?}", t.title)
+			})
+			.collect::<Vec<_>>();
+		tracks.sort();
+		tracks
+	}
+
+	#[test]
+	fn test_sync() {
+		let dir = temp_dir();
+		let shared = dir.join("shared");
+		let mut laptop = Client::new(dir.join("laptop.slab")).unwrap();
+		let mut desktop = Client::new(dir.join("desktop.slab")).unwrap();
+
+		let id = laptop
+			.add_track(&Track {
+				source: String::from("file:///foo.mp3"),
+				artists: vec![String::from("foo")],
+				title: String::from("bar"),
+				tags: HashMap::from([(String::from("speed"), 0.2)]),
+			})
+			.unwrap();
+		let deleted = laptop
+			.add_track(&Track {
+				source: String::from("file:///baz.mp3"),
+				artists: vec![],
+				title: String::from("baz"),
+				tags: HashMap::new(),
+			})
+			.unwrap();
+		laptop.sync(&shared).unwrap();
+		desktop.sync(&shared).unwrap();
+		assert_eq!(tracks(&mut laptop), tracks(&mut desktop));
+
+		// concurrent edits
+		laptop.set_tag(id, "speed", 0.4).unwrap();
+		laptop.set_tag(id, "mood", 0.1).unwrap();
+		desktop.set_tag(id, "speed", 0.9).unwrap();
+		desktop.delete_track(deleted).unwrap();
+
+		desktop.sync(&shared).unwrap();
+		laptop.sync(&shared).unwrap();
+		desktop.sync(&shared).unwrap();
+
+		let merged = tracks(&mut laptop);
+		assert_eq!(merged, tracks(&mut desktop));
+		assert_eq!(merged.len(), 1);
+		assert!(merged[0].contains("(\"mood\", 0.1)"));
+
+		// nothing new to apply
+		assert_eq!(laptop.sync(&shared).unwrap().applied, 0);
+
+		// only the last write of each register is exported: the info and both tags of the first
+		// track, the info of the one deleted on the desktop
+		for value in [0.5, 0.6, 0.7] {
+			laptop.set_tag(id, "speed", value).unwrap();
+		}
+		let report = laptop.sync(&shared).unwrap();
+		assert_eq!(report.exported, 4);
+		desktop.sync(&shared).unwrap();
+		assert_eq!(tracks(&mut laptop), tracks(&mut desktop));
+
+		fs::remove_dir_all(dir).ok();
+	}
+
+	#[test]
+	fn test_copied_database() {
+		let dir = temp_dir();
+		let shared = dir.join("shared");
+		let mut laptop = Client::new(dir.join("laptop.slab")).unwrap();
+		let id = laptop
+			.add_track(&Track {
+				source: String::from("file:///foo.mp3"),
+				artists: vec![],
+				title: String::from("foo"),
+				tags: HashMap::new(),
+			})
+			.unwrap();
+		let device = laptop.device_id().unwrap();
+		laptop.db.flush().unwrap();
+		drop(laptop);
+
+		copy_dir(&dir.join("laptop.slab"), &dir.join("desktop.slab"));
+		let mut laptop = Client::new(dir.join("laptop.slab")).unwrap();
+		let mut desktop = Client::new(dir.join("desktop.slab")).unwrap();
+		assert_eq!(laptop.device_id().unwrap(), device);
+		assert_ne!(desktop.device_id().unwrap(), device);
+
+		// their changes don't get mixed up
+		laptop.set_tag(id, "speed", 0.4).unwrap();
+		desktop.set_tag(id, "mood", 0.1).unwrap();
+		laptop.sync(&shared).unwrap();
+		desktop.sync(&shared).unwrap();
+		laptop.sync(&shared).unwrap();
+		assert_eq!(tracks(&mut laptop), tracks(&mut desktop));
+		assert_eq!(laptop.get_track(id).unwrap().tags.len(), 2);
+
+		fs::remove_dir_all(dir).ok();
+	}
+
+	fn copy_dir(from: &Path, to: &Path) {
+		fs::create_dir_all(to).unwrap();
+		for entry in fs::read_dir(from).unwrap() {
+			let entry = entry.unwrap();
+			let path = to.join(entry.file_name());
+			if entry.file_type().unwrap().is_dir() {
+				copy_dir(&entry.path(), &path);
+			} else {
+				fs::copy(entry.path(), path).unwrap();
+			}
+		}
+	}
+
+	#[test]
+	fn test_last_writer_wins() {
+		let dir = temp_dir();
+		let shared = dir.join("shared");
+		let mut a = Client::new(dir.join("a.slab")).unwrap();
+		let mut b = Client::new(dir.join("b.slab")).unwrap();
+
+		let id = a
+			.add_track(&Track {
+				source: String::from("file:///foo.mp3"),
+				artists: vec![],
+				title: String::from("foo"),
+				tags: HashMap::new(),
+			})
+			.unwrap();
+		a.sync(&shared).unwrap();
+		b.sync(&shared).unwrap();
+
+		// both writes happen at the same lamport time, the device id breaks the tie
+		a.set_tag(id, "speed", 0.1).unwrap();
+		b.set_tag(id, "speed", 0.7).unwrap();
+		a.set_tag(id, "speed", 0.3).unwrap();
+		a.sync(&shared).unwrap();
+		b.sync(&shared).unwrap();
+		a.sync(&shared).unwrap();
+
+		let expected = if a.device_id().unwrap() > b.device_id().unwrap() {
+			0.3
+		} else {
+			0.7
+		};
+		assert_eq!(a.get_track(id).unwrap().tags["speed"], expected);
+		assert_eq!(b.get_track(id).unwrap().tags["speed"], expected);
+
+		fs::remove_dir_all(dir).ok();
+	}
+}
@@ -0,0 +1,26 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::Client;
+
+// Computed by the player, it is kept locally like the loudness.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Waveform {
+	/// Min, max and RMS of each bucket, evenly spread over the track
+	pub buckets: Vec<[f32; 3]>,
+}
+
+impl Client {
+	pub fn get_waveform(&self, id: Uuid) -> Result<Option<Waveform>> {
+		self.waveforms
+			.get(id)?
+			.map(|w| Ok(serde_json::from_slice(&w)?))
+			.transpose()
+	}
+
+	pub fn set_waveform(&mut self, id: Uuid, waveform: &Waveform) -> Result<()> {
+		self.waveforms.insert(id, serde_json::to_vec(waveform)?)?;
+		Ok(())
+	}
+}
@@ -1,14 +1,18 @@
-use druid::Selector;
+use druid::{FileInfo, Selector};
 use uuid::Uuid;
 
 use crate::state::TrackImport;
 
 // Query
 pub const QUERY_RUN: Selector = Selector::new("query.run");
-pub const QUERY_PLAY: Selector = Selector::new("query.play");
+// Plays the tracks matching the query, shuffled
+pub const QUERY_PLAY: Selector<String> = Selector::new("query.play");
 
 pub const UI_TRACK_EDIT_OPEN: Selector<Uuid> = Selector::new("ui.track-edit.open");
 pub const UI_TRACK_EDIT_CLOSE: Selector = Selector::new("ui.track-edit.close");
+// Accepts or dismisses a tag suggested for the edited track
+pub const UI_TRACK_EDIT_ACCEPT_TAG: Selector<String> = Selector::new("ui.track-edit.accept-tag");
+pub const UI_TRACK_EDIT_DISMISS_TAG: Selector<String> = Selector::new("ui.track-edit.dismiss-tag");
 pub const UI_TRACK_IMPORT_OPEN: Selector<TrackImport> = Selector::new("ui.track-import.open");
 pub const UI_TRACK_ADD_CLOSE: Selector = Selector::new("ui.track-add.close");
 
@@ -18,3 +22,7 @@ pub const UI_TRACK_ADD_CLOSE: Selector = Selector::new("ui.track-add.close");
 pub const TRACK_ADD: Selector<tf_db::Track> = Selector::new("track.add");
 pub const TRACK_DELETE: Selector<Uuid> = Selector::new("track.delete");
 pub const TRACK_EDIT_TAG: Selector<(Uuid, String, f32)> = Selector::new("track.edit-tag");
+pub const DB_SYNC: Selector = Selector::new("db.sync");
+// The folder shared with the other devices, as picked in the open panel
+pub const DB_SYNC_DIR_PICKED: Selector<FileInfo> = Selector::new("db.sync-dir.picked");
+pub const DB_SYNC_DIR_CLEAR: Selector = Selector::new("db.sync-dir.clear");
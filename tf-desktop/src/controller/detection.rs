@@ -0,0 +1,277 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use druid::{
+	widget::Controller, Env, Event, EventCtx, ExtEventSink, LifeCycle, LifeCycleCtx, Selector,
+	Widget, WidgetId,
+};
+use tf_player::{
+	detect::{self, Detection, DetectionQueue, Key},
+	SourcePlugin,
+};
+use tracing::warn;
+use url::Url;
+use uuid::Uuid;
+
+use crate::State;
+
+// Looks for the tracks whose tempo, key and fingerprint weren't detected yet
+pub const DETECTION_SCAN: Selector = Selector::new("detection.scan");
+const DETECTION_DONE: Selector<(Uuid, Result<Detection, String>)> = Selector::new("detection.done");
+// The fingerprint of a track that isn't analyzed, taken by the player while it played
+pub const DETECTION_FINGERPRINT: Selector<(Uuid, detect::Fingerprint)> =
+	Selector::new("detection.fingerprint");
+
+// Bumped when the analysis finds out something new, the tracks analyzed before go through it again.
+// 1: tempo and key, 2: fingerprint and silence, 3: tempo as a tag value from 0 to 1
+const ANALYSIS_VERSION: u32 = 3;
+
+// Detects the tempo and key of the library's tracks in the background, and suggests them as tags.
+// The silence around their audio is left out when playing them.
+// Their fingerprint is kept to find the tracks with the same audio, whose tags are suggested too.
+// Like for the waveforms, only local files and offline copies are read, the other tracks are
+// fingerprinted while they are played.
+pub struct DetectionController {
+	db: tf_db::Client,
+	queue: Option<DetectionQueue>,
+	// tracks sent to the queue that aren't done yet
+	pending: HashSet<Uuid>,
+}
+
+impl DetectionController {
+	pub fn new(db: tf_db::Client) -> Self {
+		Self {
+			db,
+			queue: None,
+			pending: HashSet::new(),
+		}
+	}
+
+	fn spawn_queue(&mut self, sink: ExtEventSink, widget_id: WidgetId) -> Result<()> {
+		let (queue, results) = DetectionQueue::spawn()?;
+		std::thread::Builder::new()
+			.name(String::from("detection results"))
+			.spawn(move || {
+				for (id, detection) in results {
+					let Ok(id) = id.parse::<Uuid>() else {
+						continue;
+					};
+					let detection = detection.map_err(|e| e.to_string());
+					sink.submit_command(DETECTION_DONE, Box::new((id, detection)), widget_id)
+						.ok();
+				}
+			})?;
+		self.queue = Some(queue);
+		Ok(())
+	}
+
+	fn scan(&mut self, data: &State) -> Result<()> {
+		let Some(queue) = &self.queue else {
+			return Ok(());
+		};
+		for (id, track) in self.db.iter_valid_tracks().collect::<Vec<_>>() {
+			if self.pending.contains(&id) || self.db.get_analysis_version(id)? >= ANALYSIS_VERSION {
+				continue;
+			}
+			let url = match self.db.get_offline_copy(id)? {
+				Some(copy) if copy.source == track.source && copy.path.exists() => {
+					match Url::from_file_path(&copy.path) {
+						Ok(url) => url,
+						Err(()) => continue,
+					}
+				}
+				_ => match Url::parse(&track.source) {
+					Ok(url) if url.scheme() == "file" => url,
+					_ => continue,
+				},
+			};
+			let plugins: Vec<Box<dyn SourcePlugin>> = data
+				.plugins
+				.iter()
+				.filter_map(|p| p.read().get_source_plugin())
+				.collect();
+			queue.push(
+				id.to_string(),
+				Box::new(move || {
+					plugins
+						.iter()
+						.find_map(|p| p.handle_url(&url))
+						.ok_or_else(|| anyhow!("no plugin could handle the track: {url}"))?
+				}),
+			);
+			self.pending.insert(id);
+		}
+		Ok(())
+	}
+
+	// The tags of the tracks with the same audio are shared both ways, the detected ones are kept
+	fn save_fingerprint(
+		&mut self,
+		id: Uuid,
+		fingerprint: &detect::Fingerprint,
+		tags: &mut HashMap<String, f32>,
+	) -> Result<()> {
+		self.db.set_fingerprint(
+			id,
+			&tf_db::Fingerprint {
+				hashes: fingerprint.hashes.clone(),
+			},
+		)?;
+		let own = self.db.get_track(id)?.tags;
+		for (other, _) in same_audio(&self.db, id)? {
+			let Ok(track) = self.db.get_track(other) else {
+				continue;
+			};
+			for (tag, value) in track.tags {
+				tags.entry(tag).or_insert(value);
+			}
+			self.db.suggest_tags(other, &own)?;
+		}
+		Ok(())
+	}
+}
+
+// The other tracks with the same audio as `id`, whatever their source, best match first
+pub fn same_audio(db: &tf_db::Client, id: Uuid) -> Result<Vec<(Uuid, f32)>> {
+	let Some(fingerprint) = db.get_fingerprint(id)? else {
+		return Ok(vec![]);
+	};
+	let fingerprint = detect::Fingerprint {
+		hashes: fingerprint.hashes,
+	};
+	let others = db
+		.iter_fingerprints()
+		.filter_map(|f| match f {
+			Ok(f) => Some(f),
+			Err(e) => {
+				warn!("skipping undecodable fingerprint: {e}");
+				None
+			}
+		})
+		.filter(|(other, _)| *other != id)
+		.map(|(other, f)| (other, detect::Fingerprint { hashes: f.hashes }))
+		.collect::<Vec<_>>();
+	Ok(fingerprint.find_matches(others.iter().map(|(other, f)| (*other, f))))
+}
+
+// When the track is being edited
+fn show_suggested_tags(data: &mut State, id: Uuid, tags: HashMap<String, f32>) {
+	if let Some(edit) = data.track_edit.as_mut().filter(|edit| *edit.id == id) {
+		edit.suggested_tags = tags.into_iter().collect();
+	}
+}
+
+// The key is a tag of its own, weighted by the confidence in it
+fn suggested_tags(detection: &Detection) -> HashMap<String, f32> {
+	let mut tags = HashMap::new();
+	if let Some(bpm) = detection.bpm {
+		tags.insert(
+			String::from(detect::TEMPO_TAG),
+			detect::tempo_tag_value(bpm),
+		);
+	}
+	if let Some((key, confidence)) = detection.key {
+		tags.insert(key_tag(&key), confidence);
+	}
+	tags
+}
+
+// "key_f_sharp_minor", tag names are made of letters and underscores
+fn key_tag(key: &Key) -> String {
+	let name = key.to_string().to_lowercase();
+	format!("key_{}", name.replace('#', "_sharp").replace(' ', "_"))
+}
+
+impl<W: Widget<State>> Controller<State, W> for DetectionController {
+	fn event(
+		&mut self,
+		child: &mut W,
+		ctx: &mut EventCtx,
+		event: &Event,
+		data: &mut State,
+		env: &Env,
+	) {
+		let handled = match event {
+			Event::Command(cmd) => match cmd {
+				_ if cmd.is(DETECTION_SCAN) => {
+					if let Err(e) = self.scan(data) {
+						warn!("failed to look for the tracks to analyze: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(DETECTION_DONE) => {
+					let (id, detection) =
+						cmd.get_unchecked::<(Uuid, Result<Detection, String>)>(DETECTION_DONE);
+					self.pending.remove(id);
+					match detection {
+						Ok(detection) => {
+							if let Some(audible) = &detection.audible {
+								let trim = tf_db::Trim {
+									start: audible.start.as_secs_f32(),
+									end: audible.end.as_secs_f32(),
+								};
+								if let Err(e) = self.db.set_trim(*id, trim) {
+									warn!("failed to save the silence around {id}: {e}");
+								}
+							}
+							let mut tags = suggested_tags(detection);
+							if let Some(fingerprint) = &detection.fingerprint {
+								if let Err(e) = self.save_fingerprint(*id, fingerprint, &mut tags) {
+									warn!("failed to save the fingerprint of {id}: {e}");
+								}
+							}
+							match self.db.suggest_tags(*id, &tags) {
+								Ok(tags) => show_suggested_tags(data, *id, tags),
+								Err(e) => warn!("failed to save the tags suggested for {id}: {e}"),
+							}
+							if let Err(e) = self.db.set_analysis_version(*id, ANALYSIS_VERSION) {
+								warn!("failed to mark {id} as analyzed: {e}");
+							}
+						}
+						// tried again on the next scan
+						Err(e) => warn!("failed to detect the tempo and key of {id}: {e}"),
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(DETECTION_FINGERPRINT) => {
+					let (id, fingerprint) =
+						cmd.get_unchecked::<(Uuid, detect::Fingerprint)>(DETECTION_FINGERPRINT);
+					let mut tags = HashMap::new();
+					match self
+						.save_fingerprint(*id, fingerprint, &mut tags)
+						.and_then(|()| self.db.suggest_tags(*id, &tags))
+					{
+						Ok(tags) => show_suggested_tags(data, *id, tags),
+						Err(e) => warn!("failed to save the fingerprint of {id}: {e}"),
+					}
+					druid::Handled::Yes
+				}
+				_ => druid::Handled::No,
+			},
+			_ => druid::Handled::No,
+		};
+
+		if handled.is_handled() {
+			ctx.set_handled();
+		}
+
+		child.event(ctx, event, data, env);
+	}
+
+	fn lifecycle(
+		&mut self,
+		child: &mut W,
+		ctx: &mut LifeCycleCtx,
+		event: &LifeCycle,
+		data: &State,
+		env: &Env,
+	) {
+		if let LifeCycle::WidgetAdded = event {
+			match self.spawn_queue(ctx.get_external_handle(), ctx.widget_id()) {
+				Ok(()) => ctx.submit_command(DETECTION_SCAN),
+				Err(e) => warn!("failed to start the tempo and key detection: {e}"),
+			}
+		}
+		child.lifecycle(ctx, event, data, env)
+	}
+}
@@ -49,6 +49,7 @@ impl<W: Widget<State>> Controller<State, W> for ImportController {
 																(rand::random(), name.to_owned())
 															})
 															.collect(),
+														tags: track.tags,
 													}),
 												),
 											);
@@ -72,6 +73,7 @@ impl<W: Widget<State>> Controller<State, W> for ImportController {
 																		)
 																	})
 																	.collect(),
+																tags: track.tags,
 															})
 															.collect(),
 														tags: im::Vector::new(),
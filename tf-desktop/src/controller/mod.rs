@@ -1,4 +1,7 @@
+pub mod detection;
 pub mod import;
+pub mod offline;
 pub mod playback;
+pub mod schedule;
 pub mod search;
 pub mod tag_searcher;
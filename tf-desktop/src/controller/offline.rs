@@ -0,0 +1,227 @@
+use std::{
+	collections::HashSet,
+	fs::File,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::Sender;
+use druid::{
+	widget::Controller, Env, Event, EventCtx, ExtEventSink, LifeCycle, LifeCycleCtx, Selector,
+	Widget, WidgetId,
+};
+use tf_db::{OfflineCopy, OfflineMark};
+use tf_player::SourcePlugin;
+use tracing::{debug, warn};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{controller::detection, State};
+
+// Marks or unmarks a track or a query to be available offline
+pub const OFFLINE_MARK: Selector<(OfflineMark, bool)> = Selector::new("offline.mark");
+// Downloads what is missing and removes the copies that aren't wanted anymore
+pub const OFFLINE_SYNC: Selector = Selector::new("offline.sync");
+const OFFLINE_DOWNLOADED: Selector<(Uuid, Result<OfflineCopy, String>)> =
+	Selector::new("offline.downloaded");
+
+// Keeps a copy of the tracks marked offline in a directory, so they play without the network.
+// The downloads are done one at a time on a background thread.
+pub struct OfflineController {
+	db: tf_db::Client,
+	dir: PathBuf,
+	downloads: Option<Sender<(Uuid, String)>>,
+	// tracks sent to the download thread that aren't done yet
+	pending: HashSet<Uuid>,
+}
+
+impl OfflineController {
+	pub fn new(db: tf_db::Client, dir: PathBuf) -> Self {
+		Self {
+			db,
+			dir,
+			downloads: None,
+			pending: HashSet::new(),
+		}
+	}
+
+	fn spawn_download_thread(&mut self, data: &State, sink: ExtEventSink, widget_id: WidgetId) {
+		let plugins: Vec<Box<dyn SourcePlugin>> = data
+			.plugins
+			.iter()
+			.filter_map(|p| p.read().get_source_plugin())
+			.collect();
+		let dir = self.dir.clone();
+		let (sender, receiver) = crossbeam_channel::unbounded::<(Uuid, String)>();
+		std::thread::Builder::new()
+			.name(String::from("offline downloads"))
+			.spawn(move || {
+				for (id, source) in receiver {
+					let result = download(&plugins, &dir, id, &source).map_err(|e| e.to_string());
+					sink.submit_command(OFFLINE_DOWNLOADED, Box::new((id, result)), widget_id)
+						.ok();
+				}
+			})
+			.unwrap();
+		self.downloads = Some(sender);
+	}
+
+	fn sync(&mut self, data: &mut State) -> Result<()> {
+		let wanted = self.db.list_offline()?;
+
+		for copy in self.db.iter_offline_copies().collect::<Vec<_>>() {
+			let (id, copy) = copy?;
+			let outdated = match wanted.iter().find(|(w, _)| *w == id) {
+				Some((_, track)) => track.source != copy.source,
+				None => true,
+			};
+			if outdated || !copy.path.exists() {
+				debug!("removing the offline copy of {id}");
+				self.db.remove_offline_copy(id)?;
+				std::fs::remove_file(&copy.path).ok();
+				data.downloaded.remove(&id);
+			}
+		}
+
+		for (id, track) in wanted {
+			// local files are always available
+			if track.source.starts_with("file:")
+				|| self.pending.contains(&id)
+				|| self.db.get_offline_copy(id)?.is_some()
+			{
+				continue;
+			}
+			if let Some(downloads) = &self.downloads {
+				downloads.send((id, track.source)).ok();
+				self.pending.insert(id);
+			}
+		}
+		Ok(())
+	}
+}
+
+// Written under a temporary name first, so a half downloaded file is never taken for a copy.
+fn download(
+	plugins: &[Box<dyn SourcePlugin>],
+	dir: &Path,
+	id: Uuid,
+	source: &str,
+) -> Result<OfflineCopy> {
+	let url = Url::parse(source)?;
+	std::fs::create_dir_all(dir)?;
+	let partial = dir.join(format!("{id}.part"));
+	let mut file = File::create(&partial)?;
+	let extension = match plugins.iter().find_map(|p| p.download(&url, &mut file)) {
+		Some(Ok(extension)) => extension,
+		Some(Err(e)) => {
+			std::fs::remove_file(&partial).ok();
+			return Err(e);
+		}
+		None => {
+			std::fs::remove_file(&partial).ok();
+			return Err(anyhow!("no plugin can download {url}"));
+		}
+	};
+	file.sync_all()?;
+	let path = dir.join(format!("{id}.{extension}"));
+	std::fs::rename(&partial, &path)?;
+	Ok(OfflineCopy {
+		path,
+		source: source.to_owned(),
+	})
+}
+
+impl<W: Widget<State>> Controller<State, W> for OfflineController {
+	fn event(
+		&mut self,
+		child: &mut W,
+		ctx: &mut EventCtx,
+		event: &Event,
+		data: &mut State,
+		env: &Env,
+	) {
+		let handled = match event {
+			Event::Command(cmd) => match cmd {
+				_ if cmd.is(OFFLINE_MARK) => {
+					let (mark, offline) = cmd.get_unchecked::<(OfflineMark, bool)>(OFFLINE_MARK);
+					let result = if *offline {
+						self.db.mark_offline(mark)
+					} else {
+						self.db.unmark_offline(mark)
+					};
+					match result {
+						Ok(()) => {
+							match (mark, offline) {
+								(OfflineMark::Track(id), true) => {
+									data.offline_tracks.insert(*id);
+								}
+								(OfflineMark::Track(id), false) => {
+									data.offline_tracks.remove(id);
+								}
+								(OfflineMark::Query(query), true) => {
+									data.offline_queries.insert(query.clone());
+								}
+								(OfflineMark::Query(query), false) => {
+									data.offline_queries.remove(query);
+								}
+							}
+							ctx.submit_command(OFFLINE_SYNC);
+						}
+						Err(e) => warn!("failed to mark {mark:?} offline: {e}"),
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(OFFLINE_SYNC) => {
+					if let Err(e) = self.sync(data) {
+						warn!("failed to update the offline tracks: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(OFFLINE_DOWNLOADED) => {
+					let (id, result) = cmd
+						.get_unchecked::<(Uuid, Result<OfflineCopy, String>)>(OFFLINE_DOWNLOADED);
+					self.pending.remove(id);
+					match result {
+						Ok(copy) => match self.db.set_offline_copy(*id, copy) {
+							Ok(()) => {
+								data.downloaded.insert(*id);
+								// it may have been unmarked or edited in the meantime
+								ctx.submit_command(OFFLINE_SYNC);
+								ctx.submit_command(detection::DETECTION_SCAN);
+							}
+							Err(e) => {
+								warn!("failed to record the offline copy of {id}: {e}");
+								std::fs::remove_file(&copy.path).ok();
+							}
+						},
+						Err(e) => warn!("failed to download {id}: {e}"),
+					}
+					druid::Handled::Yes
+				}
+				_ => druid::Handled::No,
+			},
+			_ => druid::Handled::No,
+		};
+
+		if handled.is_handled() {
+			ctx.set_handled();
+		}
+
+		child.event(ctx, event, data, env);
+	}
+
+	fn lifecycle(
+		&mut self,
+		child: &mut W,
+		ctx: &mut LifeCycleCtx,
+		event: &LifeCycle,
+		data: &State,
+		env: &Env,
+	) {
+		if let LifeCycle::WidgetAdded = event {
+			self.spawn_download_thread(data, ctx.get_external_handle(), ctx.widget_id());
+			ctx.submit_command(OFFLINE_SYNC);
+		}
+		child.lifecycle(ctx, event, data, env)
+	}
+}
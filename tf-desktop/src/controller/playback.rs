@@ -1,48 +1,128 @@
-use std::{rc::Rc, time::Duration};
+use std::{
+	collections::{HashMap, VecDeque},
+	rc::Rc,
+	sync::Arc,
+	time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use crossbeam_channel::Receiver;
 use druid::{
 	widget::Controller, Env, Event, EventCtx, ExtEventSink, LifeCycle, LifeCycleCtx, Selector,
-	SingleUse, Widget, WidgetId,
+	SingleUse, TimerToken, Widget, WidgetId,
 };
+use parking_lot::RwLock;
 use tf_player::{
 	player::{self},
-	SourcePlugin, TrackSource,
+	SourcePlugin, TrackInfo, TrackSource,
 };
-use tracing::warn;
+use tracing::{debug, warn};
 use url::Url;
+use uuid::Uuid;
 
-use crate::{media_controls::MediaControls, state::Track, State};
+use crate::{
+	controller::{detection, schedule},
+	media_controls::MediaControls,
+	state::Track,
+	State,
+};
 
 pub const PLAYER_CLEAR: Selector = Selector::new("player.clear");
 pub const PLAYER_ENQUEUE: Selector<Track> = Selector::new("player.enqueue");
 pub const PLAYER_PLAY_PAUSE: Selector = Selector::new("player.play-pause");
 pub const PLAYER_SEEK: Selector<Duration> = Selector::new("player.seek");
+pub const PLAYER_SET_LOOP: Selector<(Duration, Duration)> = Selector::new("player.loop.set");
+pub const PLAYER_CLEAR_LOOP: Selector = Selector::new("player.loop.clear");
+// Marks a cue point on the current track, at the given position
+pub const PLAYER_ADD_CUE: Selector<Duration> = Selector::new("player.cue.add");
+pub const PLAYER_CLEAR_CUES: Selector = Selector::new("player.cue.clear");
+pub const PLAYER_JUMP_TO_CUE: Selector<usize> = Selector::new("player.cue.jump");
 pub const PLAYER_PREV: Selector = Selector::new("player.prev");
 pub const PLAYER_NEXT: Selector = Selector::new("player.next");
 pub const PLAYER_EVENT: Selector<player::Event> = Selector::new("player.event");
 pub const PLAYER_SET_VOLUME: Selector<f32> = Selector::new("player.set-volume");
+pub const PLAYER_SET_OUTPUT_DEVICE: Selector<Option<String>> =
+	Selector::new("player.set-output-device");
+pub const PLAYER_SET_SPEED: Selector<f64> = Selector::new("player.set-speed");
+pub const PLAYER_SET_PRESERVE_PITCH: Selector<bool> = Selector::new("player.set-preserve-pitch");
+// How long the tracks overlap, in seconds
+pub const PLAYER_SET_CROSSFADE: Selector<f64> = Selector::new("player.set-crossfade");
+// Fades out over the given duration, then pauses
+pub const PLAYER_FADE_OUT: Selector<Duration> = Selector::new("player.fade-out");
+pub const PLAYER_CANCEL_FADE_OUT: Selector = Selector::new("player.fade-out.cancel");
+pub const PLAYER_COMPUTED_WAVEFORM: Selector<(Uuid, player::Waveform)> =
+	Selector::new("player.waveform.computed");
 pub const PLAYER_CREATED_SOURCE: Selector<(Track, SingleUse<tf_player::TrackSource>)> =
 	Selector::new("player.source.created");
 
+pub const OUTPUT_DEVICE_SETTING: &str = "output_device";
+pub const CROSSFADE_SETTING: &str = "crossfade";
+
+// Tracks at the front of the queue handed to the player, the first ones are prefetched
+const UPCOMING_LEN: usize = 4;
+// How long before the start of the crossfade, or the end of the queue without one, the next track
+// is handed to the player. One that wasn't prefetched is asked for earlier, its source may take a
+// while to resolve.
+const QUEUE_AHEAD: Duration = Duration::from_secs(3);
+const RESOLVE_AHEAD: Duration = Duration::from_secs(20);
+// How often a missing output device is looked for
+const DEVICE_CHECK: Duration = Duration::from_secs(5);
+
 pub struct PlaybackController {
+	db: tf_db::Client,
 	player: player::Controller,
+	// ids of the tracks handed to the player, the first one is playing
+	sent: VecDeque<Uuid>,
+	// the front of the queue, as last handed to the player
+	upcoming: Vec<Uuid>,
+	// the tracks whose source couldn't be played, with the track played instead when they were last resolved
+	fallbacks: Arc<RwLock<HashMap<Uuid, Uuid>>>,
+	// the crossfade only starts once the next track is queued, which must happen ahead of it
+	crossfade: Duration,
+	// the next track, while its source is being resolved to queue it
+	resolving: Option<Uuid>,
+	// the next track, queued from the prefetched ones until the player has its source ready
+	prefetching: Option<Track>,
 	event_receiver: Option<Receiver<player::Event>>,
 	media_controls: Option<MediaControls>,
+	// the chosen output device is missing, the default one plays until it comes back
+	device_missing: bool,
+	device_timer: TimerToken,
 }
 
 impl PlaybackController {
-	pub fn new() -> Result<Self> {
-		let (player, events) = player::Player::spawn()?;
+	pub fn new(db: tf_db::Client) -> Result<Self> {
+		let device: Option<String> = db.get_setting(OUTPUT_DEVICE_SETTING)?;
+		let device_missing = device.as_ref().map_or(false, |d| !is_available(d));
+		let (player, events) = player::Player::spawn_with(player::sink::Cpal { device })?;
+		if let Err(e) = player.set_normalization(player::Normalization::Track) {
+			warn!("failed to set the normalization: {e}");
+		}
+		let crossfade = Duration::from_secs_f64(db.get_setting(CROSSFADE_SETTING)?.unwrap_or(0.0));
+		if let Err(e) = player.set_crossfade(crossfade_of(crossfade)) {
+			warn!("failed to set the crossfade: {e}");
+		}
 
 		Ok(Self {
+			db,
 			player,
+			sent: VecDeque::new(),
+			upcoming: vec![],
+			fallbacks: Arc::default(),
+			crossfade,
+			resolving: None,
+			prefetching: None,
 			event_receiver: Some(events),
 			media_controls: None,
+			device_missing,
+			device_timer: TimerToken::INVALID,
 		})
 	}
 
+	pub fn analysis_tap(&self) -> player::AnalysisTap {
+		self.player.analysis_tap()
+	}
+
 	pub fn spawn_event_thread(&mut self, sink: ExtEventSink) {
 		let events = self.event_receiver.take();
 		std::thread::Builder::new()
@@ -68,24 +148,133 @@ impl PlaybackController {
 			.iter()
 			.filter_map(|p| p.read().get_source_plugin())
 			.collect();
-		let url = Url::parse(&track.source).unwrap();
+		let url = self.source_url(track);
+		let info = self.track_info(data, track);
+		let db = self.db.clone();
+		let fallbacks = self.fallbacks.clone();
+		let id = *track.id;
 		let track = track.clone();
-		std::thread::spawn(move || {
-			if let Some(result) = plugins.iter().find_map(|p| p.handle_url(&url)) {
-				match result {
-					Ok(source) => sink
-						.submit_command(
-							PLAYER_CREATED_SOURCE,
-							Box::new((track, SingleUse::new(source))),
-							widget_id,
-						)
-						.unwrap(),
-					Err(e) => {
-						warn!("error while handling track {url:?}: {e}");
-					}
+		std::thread::spawn(
+			move || match resolve(&db, &plugins, &fallbacks, id, &url, info) {
+				Ok(source) => sink
+					.submit_command(
+						PLAYER_CREATED_SOURCE,
+						Box::new((track, SingleUse::new(source))),
+						widget_id,
+					)
+					.unwrap(),
+				Err(e) => warn!("error while handling track {url:?}: {e}"),
+			},
+		);
+	}
+
+	// Queues the track from the prefetched ones if it is upcoming, otherwise its source is created
+	// first. Either way it only counts as queued once the player has its source.
+	fn queue_next(
+		&mut self,
+		data: &mut State,
+		track: &Track,
+		sink: ExtEventSink,
+		widget_id: WidgetId,
+	) {
+		if self.resolving == Some(*track.id) {
+			return;
+		}
+		self.resolving = Some(*track.id);
+		if !self.upcoming.contains(&track.id) {
+			self.request_track_audio_source(data, track, sink, widget_id);
+			return;
+		}
+		if let Err(e) = self.player.queue_upcoming(&track.id.to_string()) {
+			warn!("{e}");
+			self.resolving = None;
+			return;
+		}
+		self.prefetching = Some(track.clone());
+	}
+
+	// The prefetched track the player reported on, if it is still the one waited for
+	fn take_prefetching(&mut self, key: &str) -> Option<Track> {
+		match &self.prefetching {
+			Some(track) if track.id.to_string() == key => self.prefetching.take(),
+			_ => None,
+		}
+	}
+
+	// Lets the player prefetch the next tracks, and drop those that were taken off the queue.
+	fn update_upcoming(&mut self, data: &State) {
+		let ids = data
+			.queue
+			.iter()
+			.take(UPCOMING_LEN)
+			.map(|t| *t.id)
+			.collect::<Vec<_>>();
+		if ids == self.upcoming {
+			return;
+		}
+		let upcoming = data
+			.queue
+			.iter()
+			.take(UPCOMING_LEN)
+			.map(|track| {
+				let plugins: Vec<Box<dyn SourcePlugin>> = data
+					.plugins
+					.iter()
+					.filter_map(|p| p.read().get_source_plugin())
+					.collect();
+				let url = self.source_url(track);
+				let info = self.track_info(data, track);
+				let db = self.db.clone();
+				let fallbacks = self.fallbacks.clone();
+				let id = *track.id;
+				player::Upcoming {
+					key: track.id.to_string(),
+					resolve: Box::new(move || resolve(&db, &plugins, &fallbacks, id, &url, info)),
 				}
-			} else {
-				warn!("no plugin could handle the track: {url:?}");
+			})
+			.collect();
+		match self.player.set_upcoming(upcoming) {
+			Ok(()) => self.upcoming = ids,
+			Err(e) => warn!("{e}"),
+		}
+	}
+
+	fn source_url(&self, track: &Track) -> Url {
+		source_url(&self.db, *track.id, &track.source).unwrap()
+	}
+
+	// Local files and offline copies that don't have a waveform yet are read a second time in the background,
+	// streamed tracks only get one once they are played in full.
+	pub fn request_track_waveform(
+		&self,
+		data: &State,
+		track: &Track,
+		sink: ExtEventSink,
+		widget_id: WidgetId,
+	) {
+		let url = self.source_url(track);
+		if url.scheme() != "file" || self.waveform(track).is_some() {
+			return;
+		}
+		let plugins: Vec<Box<dyn SourcePlugin>> = data
+			.plugins
+			.iter()
+			.filter_map(|p| p.read().get_source_plugin())
+			.collect();
+		let id = *track.id;
+		std::thread::spawn(move || {
+			let Some(Ok(source)) = plugins.iter().find_map(|p| p.handle_url(&url)) else {
+				return;
+			};
+			match player::Waveform::compute(source) {
+				Ok(waveform) => sink
+					.submit_command(
+						PLAYER_COMPUTED_WAVEFORM,
+						Box::new((id, waveform)),
+						widget_id,
+					)
+					.unwrap(),
+				Err(e) => warn!("failed to compute the waveform of {url}: {e}"),
 			}
 		});
 	}
@@ -96,12 +285,140 @@ impl PlaybackController {
 		track: &Track,
 		track_source: tf_player::TrackSource,
 	) {
-		self.player.queue_track(track_source).unwrap();
+		if let Err(e) = self.player.queue_track(track_source) {
+			warn!("failed to queue the track: {e}");
+			return;
+		}
+		self.queued(data, track);
+	}
+
+	fn queued(&mut self, data: &mut State, track: &Track) {
+		self.resolving = None;
+		self.sent.push_back(*track.id);
 		data.current_track = Some(track.clone());
 		self.update_media_controls(data);
 		self.play();
 	}
 
+	// What is known of the track from its previous plays
+	fn track_info(&self, data: &State, track: &Track) -> TrackInfo {
+		// the tracks of the current query play the role of the album
+		let album = data
+			.history
+			.iter()
+			.chain(&data.current_track)
+			.chain(&data.queue)
+			.chain([track])
+			.filter_map(|t| self.loudness(t))
+			.collect::<Vec<_>>();
+		TrackInfo {
+			loudness: self.loudness(track),
+			album_loudness: player::Loudness::combine(&album),
+			waveform: self.waveform(track).map(Arc::new),
+			fingerprinted: matches!(self.db.get_fingerprint(*track.id), Ok(Some(_))),
+			cues: self.cues(*track.id),
+			..Default::default()
+		}
+	}
+
+	// The cue points of the track, they are moved along with the start when it is trimmed
+	fn cues(&self, id: Uuid) -> Vec<Duration> {
+		match self.db.get_cues(id) {
+			Ok(cues) => cues
+				.points
+				.into_iter()
+				.map(|p| Duration::from_secs_f32(p.max(0.0)))
+				.collect(),
+			Err(e) => {
+				warn!("failed to read the cues of {id}: {e}");
+				vec![]
+			}
+		}
+	}
+
+	fn add_cue(&mut self, position: Duration) -> Result<()> {
+		let Some(&id) = self.sent.front() else {
+			return Ok(());
+		};
+		let (start, _) = self.db.get_play_range(id)?;
+		self.db.add_cue_point(id, start + position.as_secs_f32())?;
+		self.update_cues(id)
+	}
+
+	fn clear_cues(&mut self) -> Result<()> {
+		let Some(&id) = self.sent.front() else {
+			return Ok(());
+		};
+		let mut cues = self.db.get_cues(id)?;
+		cues.points.clear();
+		self.db.set_cues(id, &cues)?;
+		self.update_cues(id)
+	}
+
+	// The cue points are stored relative to the whole audio, the player knows them relative to
+	// the part that is played.
+	fn update_cues(&self, id: Uuid) -> Result<()> {
+		let (start, end) = self.db.get_play_range(id)?;
+		let cues = self
+			.cues(id)
+			.into_iter()
+			.filter(|cue| {
+				let cue = cue.as_secs_f32();
+				cue >= start && end.map_or(true, |end| cue <= end)
+			})
+			.map(|cue| cue.saturating_sub(Duration::from_secs_f32(start)))
+			.collect();
+		self.player.set_cues(cues)
+	}
+
+	// The track whose audio is playing, the measurements of the player belong to it
+	fn playing_audio(&self) -> Option<Uuid> {
+		let id = *self.sent.front()?;
+		Some(self.fallbacks.read().get(&id).copied().unwrap_or(id))
+	}
+
+	fn loudness(&self, track: &Track) -> Option<player::Loudness> {
+		match self.db.get_loudness(*track.id) {
+			Ok(loudness) => loudness.map(|l| player::Loudness {
+				integrated: l.integrated,
+				true_peak: l.true_peak,
+			}),
+			Err(e) => {
+				warn!("failed to read the loudness of {}: {e}", track.id);
+				None
+			}
+		}
+	}
+
+	fn waveform(&self, track: &Track) -> Option<player::Waveform> {
+		match self.db.get_waveform(*track.id) {
+			Ok(waveform) => waveform.map(|w| player::Waveform {
+				buckets: w
+					.buckets
+					.into_iter()
+					.map(|[min, max, rms]| player::Bucket { min, max, rms })
+					.collect(),
+			}),
+			Err(e) => {
+				warn!("failed to read the waveform of {}: {e}", track.id);
+				None
+			}
+		}
+	}
+
+	fn save_waveform(&mut self, id: Uuid, waveform: &player::Waveform) {
+		let waveform = tf_db::Waveform {
+			buckets: waveform
+				.buckets
+				.iter()
+				.map(|b| [b.min, b.max, b.rms])
+				.collect(),
+		};
+		if let Err(e) = self.db.set_waveform(id, &waveform) {
+			warn!("failed to save the waveform of {id}: {e}");
+		}
+	}
+
 	pub fn update_media_controls(&mut self, data: &State) {
 		match &data.current_track {
 			Some(track) => {
@@ -121,6 +438,14 @@ impl PlaybackController {
 		}
 	}
 
+	// The sleep timer stops playback once the current track is over
+	fn stops_after_current(&self, data: &State) -> bool {
+		match (data.schedule.stop, &data.current_track) {
+			(Some(tf_db::Stop::AfterTrack(id)), Some(track)) => *track.id == id,
+			_ => false,
+		}
+	}
+
 	pub fn play_pause(&mut self, data: &State) {
 		if let Some(p) = data.player_state.get_playing() {
 			if p.paused {
@@ -132,16 +457,138 @@ impl PlaybackController {
 	}
 
 	pub fn play(&mut self) {
-		self.player.play().unwrap();
+		if let Err(e) = self.player.play() {
+			warn!("failed to play: {e}");
+		}
 		self.media_controls.as_mut().map(|c| c.set_is_playing(true));
 	}
 
 	pub fn pause(&mut self) {
-		self.player.pause().unwrap();
+		if let Err(e) = self.player.pause() {
+			warn!("failed to pause: {e}");
+		}
 		self.media_controls
 			.as_mut()
 			.map(|c| c.set_is_playing(false));
 	}
+
+	// Switches back to the chosen output device once it is available again
+	fn check_output_device(&mut self, ctx: &mut EventCtx, data: &State) {
+		if !self.device_missing {
+			return;
+		}
+		match &data.output_device {
+			Some(device) if is_available(device) => {
+				debug!("the output device {device:?} is back");
+				if let Err(e) = self.player.set_output_device(Some(device.clone())) {
+					warn!("failed to set the output device: {e}");
+				}
+				self.device_missing = false;
+			}
+			Some(_) => self.device_timer = ctx.request_timer(DEVICE_CHECK),
+			None => self.device_missing = false,
+		}
+	}
+}
+
+fn crossfade_of(duration: Duration) -> player::Crossfade {
+	player::Crossfade {
+		duration,
+		..Default::default()
+	}
+}
+
+fn is_available(device: &str) -> bool {
+	match player::sink::output_devices() {
+		Ok(devices) => devices.iter().any(|d| d == device),
+		Err(e) => {
+			warn!("failed to list the output devices: {e}");
+			false
+		}
+	}
+}
+
+// The offline copy is played instead of the source when there is one.
+fn source_url(db: &tf_db::Client, id: Uuid, source: &str) -> Result<Url> {
+	match db.get_offline_copy(id) {
+		Ok(Some(copy)) if copy.source == source && copy.path.exists() => {
+			if let Ok(url) = Url::from_file_path(&copy.path) {
+				return Ok(url);
+			}
+		}
+		Ok(_) => {}
+		Err(e) => warn!("failed to read the offline copy of {id}: {e}"),
+	}
+	Ok(Url::parse(source)?)
+}
+
+// When the source can't be played, like when it was taken down, the tracks with the same audio
+// are tried instead. `info` is what is known of the track, it doesn't apply to the others.
+// The track that is played instead is kept in `fallbacks`.
+fn resolve(
+	db: &tf_db::Client,
+	plugins: &[Box<dyn SourcePlugin>],
+	fallbacks: &RwLock<HashMap<Uuid, Uuid>>,
+	id: Uuid,
+	url: &Url,
+	info: TrackInfo,
+) -> Result<TrackSource> {
+	let handle = |url: &Url| {
+		plugins
+			.iter()
+			.find_map(|p| p.handle_url(url))
+			.ok_or_else(|| anyhow!("no plugin could handle the track: {url}"))?
+	};
+	let error = match handle(url) {
+		Ok(mut source) => {
+			fallbacks.write().remove(&id);
+			source.info = with_source_info(info, source.info);
+			return trim(db, id, source);
+		}
+		Err(e) => e,
+	};
+	for (other, _) in detection::same_audio(db, id).unwrap_or_default() {
+		let Ok(track) = db.get_track(other) else {
+			continue;
+		};
+		let Ok(fallback) = source_url(db, other, &track.source) else {
+			continue;
+		};
+		if let Ok(mut source) = handle(&fallback) {
+			warn!("{url} can't be played ({error}), playing {fallback} instead");
+			fallbacks.write().insert(id, other);
+			// it was matched by its fingerprint
+			source.info.fingerprinted = true;
+			return trim(db, other, source);
+		}
+	}
+	Err(error)
+}
+
+// Playback starts at the cue in and stops at the cue out, or leaves out the silence around the audio
+fn trim(db: &tf_db::Client, id: Uuid, source: TrackSource) -> Result<TrackSource> {
+	let (start, end) = match db.get_play_range(id) {
+		Ok(range) => range,
+		Err(e) => {
+			warn!("failed to read the cues of {id}: {e}");
+			return Ok(source);
+		}
+	};
+	Ok(source.trim(
+		Duration::from_secs_f32(start.max(0.0)),
+		end.map(|end| Duration::from_secs_f32(end.max(0.0))),
+	)?)
+}
+
+// What was measured on previous plays is preferred to what the source read from its tags
+fn with_source_info(known: TrackInfo, source: TrackInfo) -> TrackInfo {
+	TrackInfo {
+		duration: source.duration,
+		loudness: known.loudness.or(source.loudness),
+		album_loudness: known.album_loudness.or(source.album_loudness),
+		cover: source.cover,
+		..known
+	}
 }
 
 impl<W: Widget<State>> Controller<State, W> for PlaybackController {
@@ -154,6 +601,10 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 		env: &Env,
 	) {
 		let handled = match event {
+			Event::Timer(token) if *token == self.device_timer => {
+				self.check_output_device(ctx, data);
+				druid::Handled::Yes
+			}
 			Event::Command(cmd) => match cmd {
 				_ if cmd.is(PLAYER_EVENT) => {
 					match cmd.get_unchecked::<player::Event>(PLAYER_EVENT) {
@@ -164,9 +615,16 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 								.unwrap_or_default() + Duration::from_secs(
 								self.player.nb_queued() as u64 * 10000000,
 							);
-							if until_empty < Duration::from_secs(3) {
+							let ahead = self.crossfade
+								+ match data.queue.front() {
+									Some(track) if !self.upcoming.contains(&track.id) => {
+										RESOLVE_AHEAD
+									}
+									_ => QUEUE_AHEAD,
+								};
+							if until_empty < ahead && !self.stops_after_current(data) {
 								if let Some(track) = data.queue.front() {
-									self.request_track_audio_source(
+									self.queue_next(
 										data,
 										&track.clone(),
 										ctx.get_external_handle(),
@@ -176,31 +634,128 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 							}
 							data.player_state = Rc::new(ps.clone());
 						}
+						player::Event::Loudness(loudness) => {
+							if let Some(id) = self.playing_audio() {
+								let loudness = tf_db::Loudness {
+									integrated: loudness.integrated,
+									true_peak: loudness.true_peak,
+								};
+								if let Err(e) = self.db.set_loudness(id, loudness) {
+									warn!("failed to save the loudness of {id}: {e}");
+								}
+							}
+						}
+						player::Event::Waveform(waveform) => {
+							// the waveform of a trimmed track doesn't cover all of its audio
+							if let Some(id) = self.playing_audio() {
+								let range = self.db.get_play_range(id);
+								if matches!(range, Ok((start, None)) if start == 0.0) {
+									self.save_waveform(id, waveform);
+								}
+							}
+						}
+						player::Event::Fingerprint(fingerprint) => {
+							// like the ones of the analysis, it is taken from the start of the audio
+							if let Some(id) = self.playing_audio() {
+								if matches!(self.db.get_play_range(id), Ok((start, _)) if start == 0.0)
+								{
+									ctx.submit_command(
+										detection::DETECTION_FINGERPRINT
+											.with((id, fingerprint.clone())),
+									);
+								}
+							}
+						}
+						player::Event::Buffering(progress) => {
+							debug!("buffering the current track: {:.0}%", progress * 100.0);
+						}
+						player::Event::OutputDeviceLost => {
+							warn!("the output device went away, playing on the default one");
+							// the saved device stays chosen, it is switched back to once it returns
+							self.device_missing = true;
+							self.device_timer = ctx.request_timer(DEVICE_CHECK);
+						}
+						player::Event::SourceError { error, .. } => {
+							warn!("skipping track that failed to play: {error}");
+							// the next track wasn't requested yet, it's made current by the following TrackEnd
+							if self.player.nb_queued() == 0 && !self.stops_after_current(data) {
+								if let Some(track) = data.queue.front() {
+									self.request_track_audio_source(
+										data,
+										&track.clone(),
+										ctx.get_external_handle(),
+										ctx.widget_id(),
+									)
+								}
+							}
+						}
+						player::Event::UpcomingQueued(key) => {
+							if let Some(track) = self.take_prefetching(key) {
+								self.queued(data, &track);
+							}
+						}
+						player::Event::UpcomingFailed { key, error } => {
+							warn!("failed to prefetch the track {key}: {error}");
+							// its source is resolved again, it may fall back on another one this time
+							if let Some(track) = self.take_prefetching(key) {
+								self.request_track_audio_source(
+									data,
+									&track,
+									ctx.get_external_handle(),
+									ctx.widget_id(),
+								)
+							}
+						}
+						player::Event::DecoderRestarted => {
+							warn!("the player restarted, playing the current track again");
+							self.sent.clear();
+							self.resolving = None;
+							self.prefetching = None;
+							if let Some(track) = data.current_track.clone() {
+								self.request_track_audio_source(
+									data,
+									&track,
+									ctx.get_external_handle(),
+									ctx.widget_id(),
+								)
+							}
+						}
 						player::Event::TrackEnd => {
+							self.sent.pop_front();
+							let stopped = self.stops_after_current(data);
 							data.history.push_front(data.current_track.take().unwrap());
-							if let Some(track) = data.queue.pop_front() {
+							// what follows stays in the queue when the sleep timer stopped playback
+							let next = if stopped {
+								None
+							} else {
+								data.queue.pop_front()
+							};
+							if let Some(track) = next {
 								data.current_track = Some(track);
 								self.update_media_controls(data);
 							} else {
 								data.current_track = None;
 								self.update_media_controls(data);
+								if data.schedule.stop == Some(tf_db::Stop::AfterQueue) {
+									ctx.submit_command(schedule::SCHEDULE_STOP.with(None));
+								}
 							}
 						}
 					}
 					druid::Handled::No
 				}
 				_ if cmd.is(PLAYER_CLEAR) => {
-					self.player.clear();
+					if let Err(e) = self.player.clear() {
+						warn!("{e}");
+					}
+					self.sent.clear();
+					self.resolving = None;
+					self.prefetching = None;
 					druid::Handled::Yes
 				}
 				_ if cmd.is(PLAYER_ENQUEUE) => {
 					let track = cmd.get_unchecked::<Track>(PLAYER_ENQUEUE);
-					self.request_track_audio_source(
-						data,
-						track,
-						ctx.get_external_handle(),
-						ctx.widget_id(),
-					);
+					self.queue_next(data, track, ctx.get_external_handle(), ctx.widget_id());
 					druid::Handled::Yes
 				}
 				_ if cmd.is(PLAYER_PLAY_PAUSE) => {
@@ -209,15 +764,13 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 				}
 				_ if cmd.is(PLAYER_PREV) && self.player.nb_queued() == 0 => {
 					if let Some(track) = data.history.pop_front() {
-						self.request_track_audio_source(
-							data,
-							&track,
-							ctx.get_external_handle(),
-							ctx.widget_id(),
-						);
+						self.queue_next(data, &track, ctx.get_external_handle(), ctx.widget_id());
 						data.queue.push_front(data.current_track.take().unwrap());
 						data.current_track = Some(track);
-						self.player.skip().unwrap();
+						if let Err(e) = self.player.skip() {
+							warn!("failed to skip the track: {e}");
+						}
+						self.sent.pop_front();
 						self.update_media_controls(data);
 					}
 					druid::Handled::Yes
@@ -225,7 +778,8 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 				_ if cmd.is(PLAYER_NEXT) => {
 					if self.player.nb_queued() == 0 {
 						if let Some(track) = data.queue.pop_front() {
-							self.request_track_audio_source(
+							// it was likely prefetched, being next in the queue
+							self.queue_next(
 								data,
 								&track,
 								ctx.get_external_handle(),
@@ -233,7 +787,10 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 							);
 							data.history.push_front(data.current_track.take().unwrap());
 							data.current_track = Some(track);
-							self.player.skip().unwrap();
+							if let Err(e) = self.player.skip() {
+								warn!("failed to skip the track: {e}");
+							}
+							self.sent.pop_front();
 							self.update_media_controls(data);
 						}
 					}
@@ -241,18 +798,125 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 				}
 				_ if cmd.is(PLAYER_SEEK) => {
 					let pos = cmd.get_unchecked::<Duration>(PLAYER_SEEK);
-					self.player.seek(*pos).unwrap();
+					if let Err(e) = self.player.seek(*pos) {
+						warn!("failed to seek: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_SET_LOOP) => {
+					let (start, end) = cmd.get_unchecked::<(Duration, Duration)>(PLAYER_SET_LOOP);
+					if let Err(e) = self.player.set_loop(*start, *end) {
+						warn!("failed to set the loop: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_CLEAR_LOOP) => {
+					if let Err(e) = self.player.clear_loop() {
+						warn!("failed to clear the loop: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_ADD_CUE) => {
+					let position = cmd.get_unchecked::<Duration>(PLAYER_ADD_CUE);
+					if let Err(e) = self.add_cue(*position) {
+						warn!("failed to add the cue: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_CLEAR_CUES) => {
+					if let Err(e) = self.clear_cues() {
+						warn!("failed to clear the cues: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_JUMP_TO_CUE) => {
+					let n = cmd.get_unchecked::<usize>(PLAYER_JUMP_TO_CUE);
+					if let Err(e) = self.player.jump_to_cue(*n) {
+						warn!("failed to jump to the cue: {e}");
+					}
 					druid::Handled::Yes
 				}
 				_ if cmd.is(PLAYER_SET_VOLUME) => {
 					let volume = cmd.get_unchecked::<f32>(PLAYER_SET_VOLUME);
-					self.player.set_volume(*volume).unwrap();
+					if let Err(e) = self.player.set_volume(*volume) {
+						warn!("failed to set the volume: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_SET_OUTPUT_DEVICE) => {
+					let device = cmd.get_unchecked::<Option<String>>(PLAYER_SET_OUTPUT_DEVICE);
+					if let Err(e) = self.player.set_output_device(device.clone()) {
+						warn!("failed to set the output device: {e}");
+					}
+					self.device_missing = false;
+					if let Err(e) = self.db.set_setting(OUTPUT_DEVICE_SETTING, device) {
+						warn!("failed to save the output device: {e}");
+					}
+					data.output_device = device.clone();
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_SET_SPEED) => {
+					let speed = cmd.get_unchecked::<f64>(PLAYER_SET_SPEED);
+					if let Err(e) = self.player.set_speed(*speed as f32) {
+						warn!("failed to set the speed: {e}");
+					}
+					data.speed = *speed;
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_SET_PRESERVE_PITCH) => {
+					let preserve = cmd.get_unchecked::<bool>(PLAYER_SET_PRESERVE_PITCH);
+					let mode = if *preserve {
+						player::SpeedMode::PreservePitch
+					} else {
+						player::SpeedMode::Varispeed
+					};
+					if let Err(e) = self.player.set_speed_mode(mode) {
+						warn!("failed to set the speed mode: {e}");
+					}
+					data.preserve_pitch = *preserve;
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_SET_CROSSFADE) => {
+					let seconds = cmd.get_unchecked::<f64>(PLAYER_SET_CROSSFADE);
+					self.crossfade = Duration::from_secs_f64(*seconds);
+					if let Err(e) = self.player.set_crossfade(crossfade_of(self.crossfade)) {
+						warn!("failed to set the crossfade: {e}");
+					}
+					if let Err(e) = self.db.set_setting(CROSSFADE_SETTING, seconds) {
+						warn!("failed to save the crossfade: {e}");
+					}
+					data.crossfade = *seconds;
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_FADE_OUT) => {
+					let duration = cmd.get_unchecked::<Duration>(PLAYER_FADE_OUT);
+					if let Err(e) = self.player.fade_out(*duration) {
+						warn!("failed to fade out: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_CANCEL_FADE_OUT) => {
+					if let Err(e) = self.player.cancel_fade_out() {
+						warn!("failed to cancel the fade out: {e}");
+					}
 					druid::Handled::Yes
 				}
 				_ if cmd.is(PLAYER_CREATED_SOURCE) => {
 					let (track, source) =
 						cmd.get_unchecked::<(Track, SingleUse<TrackSource>)>(PLAYER_CREATED_SOURCE);
 					self.queue_track(data, track, source.take().unwrap());
+					self.request_track_waveform(
+						data,
+						track,
+						ctx.get_external_handle(),
+						ctx.widget_id(),
+					);
+					druid::Handled::Yes
+				}
+				_ if cmd.is(PLAYER_COMPUTED_WAVEFORM) => {
+					let (id, waveform) =
+						cmd.get_unchecked::<(Uuid, player::Waveform)>(PLAYER_COMPUTED_WAVEFORM);
+					self.save_waveform(*id, waveform);
 					druid::Handled::Yes
 				}
 				_ => druid::Handled::No,
@@ -265,6 +929,7 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 		}
 
 		child.event(ctx, event, data, env);
+		self.update_upcoming(data);
 	}
 
 	fn lifecycle(
@@ -278,6 +943,9 @@ impl<W: Widget<State>> Controller<State, W> for PlaybackController {
 		if let LifeCycle::WidgetAdded = event {
 			self.spawn_event_thread(ctx.get_external_handle());
 			self.media_controls = MediaControls::new(ctx.window()).ok();
+			if self.device_missing {
+				self.device_timer = ctx.request_timer(DEVICE_CHECK);
+			}
 		}
 		child.lifecycle(ctx, event, data, env)
 	}
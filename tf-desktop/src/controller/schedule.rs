@@ -0,0 +1,200 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveTime};
+use druid::{
+	widget::Controller, Env, Event, EventCtx, LifeCycle, LifeCycleCtx, Selector, TimerToken,
+	UpdateCtx, Widget,
+};
+use tf_db::{Alarm, Schedule, Stop};
+use tracing::{debug, warn};
+
+use crate::{command, controller::playback, State};
+
+// Sets when playback stops, None cancels the sleep timer
+pub const SCHEDULE_STOP: Selector<Option<Stop>> = Selector::new("schedule.stop");
+// Sets the alarm, None removes it
+pub const SCHEDULE_ALARM: Selector<Option<Alarm>> = Selector::new("schedule.alarm");
+
+// How long playback fades out before the sleep timer stops it
+const SLEEP_FADE: Duration = Duration::from_secs(60);
+const TICK: Duration = Duration::from_secs(1);
+
+// Runs the sleep timer and the alarm. The schedule is saved as it changes and picked up on the
+// next start, a sleep timer that went off in the meantime is dropped.
+// Stopping after a track is left to the playback controller, which doesn't queue what follows it,
+// and which is also the one to find out that the queue is over.
+pub struct ScheduleController {
+	db: tf_db::Client,
+	timer: TimerToken,
+	// when the alarm goes off next
+	next_alarm: Option<DateTime<Local>>,
+	// the sleep timer started fading out
+	fading: bool,
+}
+
+impl ScheduleController {
+	pub fn new(db: tf_db::Client) -> Self {
+		Self {
+			db,
+			timer: TimerToken::INVALID,
+			next_alarm: None,
+			fading: false,
+		}
+	}
+
+	fn save(&mut self, data: &mut State, schedule: Schedule) -> Result<()> {
+		self.db.set_schedule(&schedule)?;
+		self.update_next_alarm(&schedule);
+		data.schedule = schedule;
+		Ok(())
+	}
+
+	fn update_next_alarm(&mut self, schedule: &Schedule) {
+		self.next_alarm = schedule
+			.alarm
+			.as_ref()
+			.and_then(|alarm| next_alarm(alarm, Local::now()));
+	}
+
+	fn tick(&mut self, ctx: &mut EventCtx, data: &mut State) -> Result<()> {
+		let playing = data.player_state.get_playing().map_or(false, |p| !p.paused);
+		// pausing cancels the fade out, it starts again when playing
+		if !playing {
+			self.fading = false;
+		}
+		if let Some(Stop::At(time)) = data.schedule.stop {
+			let left = Duration::from_secs(time).saturating_sub(unix_time());
+			if left.is_zero() {
+				debug!("the sleep timer went off");
+				self.fading = false;
+				let schedule = Schedule {
+					stop: None,
+					..data.schedule.clone()
+				};
+				self.save(data, schedule)?;
+			} else if left <= SLEEP_FADE && playing && !self.fading {
+				ctx.submit_command(playback::PLAYER_FADE_OUT.with(left));
+				self.fading = true;
+			}
+		}
+
+		if self.next_alarm.map_or(false, |next| Local::now() >= next) {
+			if let Some(alarm) = &data.schedule.alarm {
+				debug!("the alarm went off, playing `{}`", alarm.query);
+				ctx.submit_command(command::QUERY_PLAY.with(alarm.query.clone()));
+			}
+			self.update_next_alarm(&data.schedule);
+		}
+		Ok(())
+	}
+}
+
+// Seconds since the Unix epoch, as the stop times are saved
+pub fn unix_time() -> Duration {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+}
+
+// The alarm goes off every day, the next time is later today or tomorrow
+fn next_alarm(alarm: &Alarm, now: DateTime<Local>) -> Option<DateTime<Local>> {
+	let time = NaiveTime::from_hms_opt(alarm.hour, alarm.minute, 0)?;
+	let mut next = now.date_naive().and_time(time);
+	if next <= now.naive_local() {
+		next += chrono::Duration::days(1);
+	}
+	next.and_local_timezone(Local).earliest()
+}
+
+impl<W: Widget<State>> Controller<State, W> for ScheduleController {
+	fn event(
+		&mut self,
+		child: &mut W,
+		ctx: &mut EventCtx,
+		event: &Event,
+		data: &mut State,
+		env: &Env,
+	) {
+		let handled = match event {
+			Event::Timer(token) if *token == self.timer => {
+				if let Err(e) = self.tick(ctx, data) {
+					warn!("failed to update the schedule: {e}");
+				}
+				self.timer = ctx.request_timer(TICK);
+				druid::Handled::Yes
+			}
+			Event::Command(cmd) => match cmd {
+				_ if cmd.is(SCHEDULE_STOP) => {
+					let stop = cmd.get_unchecked::<Option<Stop>>(SCHEDULE_STOP);
+					if self.fading {
+						ctx.submit_command(playback::PLAYER_CANCEL_FADE_OUT);
+						self.fading = false;
+					}
+					let schedule = Schedule {
+						stop: *stop,
+						..data.schedule.clone()
+					};
+					if let Err(e) = self.save(data, schedule) {
+						warn!("failed to save the sleep timer: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ if cmd.is(SCHEDULE_ALARM) => {
+					let alarm = cmd.get_unchecked::<Option<Alarm>>(SCHEDULE_ALARM);
+					let schedule = Schedule {
+						alarm: alarm.clone(),
+						..data.schedule.clone()
+					};
+					if let Err(e) = self.save(data, schedule) {
+						warn!("failed to save the alarm: {e}");
+					}
+					druid::Handled::Yes
+				}
+				_ => druid::Handled::No,
+			},
+			_ => druid::Handled::No,
+		};
+
+		if handled.is_handled() {
+			ctx.set_handled();
+		}
+
+		child.event(ctx, event, data, env);
+	}
+
+	fn lifecycle(
+		&mut self,
+		child: &mut W,
+		ctx: &mut LifeCycleCtx,
+		event: &LifeCycle,
+		data: &State,
+		env: &Env,
+	) {
+		if let LifeCycle::WidgetAdded = event {
+			self.update_next_alarm(&data.schedule);
+			self.timer = ctx.request_timer(TICK);
+		}
+		child.lifecycle(ctx, event, data, env)
+	}
+
+	// The stop after a track is done once it isn't the current track anymore,
+	// whether it is over or was skipped
+	fn update(
+		&mut self,
+		child: &mut W,
+		ctx: &mut UpdateCtx,
+		old_data: &State,
+		data: &State,
+		env: &Env,
+	) {
+		if let Some(Stop::AfterTrack(id)) = data.schedule.stop {
+			let is_current =
+				|state: &State| state.current_track.as_ref().map_or(false, |t| *t.id == id);
+			if is_current(old_data) && !is_current(data) {
+				ctx.submit_command(SCHEDULE_STOP.with(None));
+			}
+		}
+		child.update(ctx, old_data, data, env)
+	}
+}
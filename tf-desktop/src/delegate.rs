@@ -1,12 +1,19 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use anyhow::Result;
-use druid::AppDelegate;
+use druid::{AppDelegate, FileInfo};
 use rand::seq::SliceRandom;
-use tracing::error;
+use tracing::{error, warn};
 use uuid::Uuid;
 
-use crate::{command, controller::playback, state::TrackEdit, State};
+use crate::{
+	command,
+	controller::{detection, offline, playback},
+	state::TrackEdit,
+	State,
+};
+
+pub const SYNC_DIR_SETTING: &str = "sync_dir";
 
 pub struct Delegate {
 	db: tf_db::Client,
@@ -17,8 +24,17 @@ impl Delegate {
 		Ok(Self { db })
 	}
 
+	fn set_sync_dir(&mut self, data: &mut State, dir: Option<PathBuf>) -> Result<()> {
+		self.db.set_setting(SYNC_DIR_SETTING, &dir)?;
+		data.sync_dir = dir.map(Arc::new);
+		Ok(())
+	}
+
 	fn apply_track_edit(&mut self, edit: TrackEdit) -> Result<()> {
 		self.db.set_track(*edit.id, &edit.get_track())?;
+		let mut cues = self.db.get_cues(*edit.id)?;
+		edit.apply_cues(&mut cues);
+		self.db.set_cues(*edit.id, &cues)?;
 		Ok(())
 	}
 }
@@ -59,8 +75,13 @@ impl AppDelegate<State> for Delegate {
 				druid::Handled::Yes
 			}
 			_ if cmd.is(command::QUERY_PLAY) => {
-				match data.query.parse::<tf_db::Filter>() {
+				let query = cmd.get_unchecked::<String>(command::QUERY_PLAY);
+				match query.parse::<tf_db::Filter>() {
 					Ok(filter) => match self.db.list_filtered(&filter) {
+						// what is playing goes on
+						Ok(tracks) if tracks.is_empty() => {
+							warn!("no track matches `{query}`, there is nothing to play")
+						}
 						Ok(mut tracks) => {
 							tracks.shuffle(&mut rand::thread_rng());
 							ctx.submit_command(playback::PLAYER_CLEAR);
@@ -82,9 +103,18 @@ impl AppDelegate<State> for Delegate {
 				data.selected_track = Some(Arc::new(*id));
 				if let Some(track_edit) = data.track_edit.take() {
 					self.apply_track_edit(track_edit).unwrap();
+					ctx.submit_command(offline::OFFLINE_SYNC);
 				}
 				if let Ok(track) = self.db.get_track(*id) {
-					data.track_edit = Some(TrackEdit::new(*id, track));
+					let suggested_tags = self.db.get_suggested_tags(*id).unwrap_or_else(|e| {
+						error!("{e}");
+						None
+					});
+					let cues = self.db.get_cues(*id).unwrap_or_else(|e| {
+						error!("{e}");
+						Default::default()
+					});
+					data.track_edit = Some(TrackEdit::new(*id, track, suggested_tags, cues));
 				}
 				druid::Handled::Yes
 			}
@@ -92,6 +122,28 @@ impl AppDelegate<State> for Delegate {
 				data.selected_track = None;
 				if let Some(track_edit) = data.track_edit.take() {
 					self.apply_track_edit(track_edit).unwrap();
+					ctx.submit_command(offline::OFFLINE_SYNC);
+				}
+				druid::Handled::Yes
+			}
+			_ if cmd.is(command::UI_TRACK_EDIT_ACCEPT_TAG) => {
+				let tag = cmd.get_unchecked::<String>(command::UI_TRACK_EDIT_ACCEPT_TAG);
+				if let Some(edit) = &mut data.track_edit {
+					match self.db.accept_suggested_tag(*edit.id, tag) {
+						// the edit is saved with the tag, or it would be removed again
+						Ok(()) => edit.accept_suggested_tag(tag),
+						Err(e) => error!("{e}"),
+					}
+				}
+				druid::Handled::Yes
+			}
+			_ if cmd.is(command::UI_TRACK_EDIT_DISMISS_TAG) => {
+				let tag = cmd.get_unchecked::<String>(command::UI_TRACK_EDIT_DISMISS_TAG);
+				if let Some(edit) = &mut data.track_edit {
+					match self.db.dismiss_suggested_tag(*edit.id, tag) {
+						Ok(()) => edit.suggested_tags.retain(|(name, _)| name != tag),
+						Err(e) => error!("{e}"),
+					}
 				}
 				druid::Handled::Yes
 			}
@@ -114,6 +166,8 @@ impl AppDelegate<State> for Delegate {
 						data.tracks.push_back((id, track).into());
 						data.new_track_search = String::new();
 						data.track_import = None;
+						ctx.submit_command(offline::OFFLINE_SYNC);
+						ctx.submit_command(detection::DETECTION_SCAN);
 					}
 					Err(e) => error!("{:?}", e),
 				}
@@ -121,8 +175,16 @@ impl AppDelegate<State> for Delegate {
 			}
 			_ if cmd.is(command::TRACK_DELETE) => {
 				let id = cmd.get_unchecked::<Uuid>(command::TRACK_DELETE);
-				if let Ok(()) = self.db.delete_track(*id) {
-					data.tracks.retain(|track| *track.id != *id);
+				match self.db.delete_track(*id) {
+					Ok(copy) => {
+						if let Some(copy) = copy {
+							std::fs::remove_file(copy.path).ok();
+						}
+						data.tracks.retain(|track| *track.id != *id);
+						data.offline_tracks.remove(id);
+						data.downloaded.remove(id);
+					}
+					Err(e) => error!("failed to delete {id}: {e}"),
 				}
 				druid::Handled::Yes
 			}
@@ -131,6 +193,34 @@ impl AppDelegate<State> for Delegate {
 				if let Err(e) = self.db.set_tag(*track, tag, *value) {
 					error!("{e}");
 				}
+				ctx.submit_command(offline::OFFLINE_SYNC);
+				druid::Handled::Yes
+			}
+			_ if cmd.is(command::DB_SYNC) => {
+				if let Some(dir) = &data.sync_dir {
+					match self.db.sync(dir.as_path()) {
+						Ok(report) if report.applied > 0 => {
+							ctx.submit_command(command::QUERY_RUN);
+							ctx.submit_command(offline::OFFLINE_SYNC);
+						}
+						Ok(_) => {}
+						Err(e) => error!("failed to sync with {dir:?}: {e}"),
+					}
+				}
+				druid::Handled::Yes
+			}
+			_ if cmd.is(command::DB_SYNC_DIR_PICKED) => {
+				let dir = cmd.get_unchecked::<FileInfo>(command::DB_SYNC_DIR_PICKED);
+				match self.set_sync_dir(data, Some(dir.path().to_owned())) {
+					Ok(()) => ctx.submit_command(command::DB_SYNC),
+					Err(e) => error!("failed to save the sync folder: {e}"),
+				}
+				druid::Handled::Yes
+			}
+			_ if cmd.is(command::DB_SYNC_DIR_CLEAR) => {
+				if let Err(e) = self.set_sync_dir(data, None) {
+					error!("failed to save the sync folder: {e}");
+				}
 				druid::Handled::Yes
 			}
 			_ => druid::Handled::No,
@@ -143,16 +233,23 @@ impl AppDelegate<State> for Delegate {
 		_handle: druid::WindowHandle,
 		_data: &mut State,
 		_env: &druid::Env,
-		_ctx: &mut druid::DelegateCtx,
+		ctx: &mut druid::DelegateCtx,
 	) {
+		ctx.submit_command(command::DB_SYNC);
 	}
 
 	fn window_removed(
 		&mut self,
 		_id: druid::WindowId,
-		_data: &mut State,
+		data: &mut State,
 		_env: &druid::Env,
 		_ctx: &mut druid::DelegateCtx,
 	) {
+		// export the changes made during this session
+		if let Some(dir) = &data.sync_dir {
+			if let Err(e) = self.db.sync(dir.as_path()) {
+				error!("failed to sync with {dir:?}: {e}");
+			}
+		}
 	}
 }
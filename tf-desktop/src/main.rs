@@ -1,5 +1,9 @@
+use std::time::Duration;
+
 use anyhow::{anyhow, Result};
 use druid::{AppLauncher, WindowDesc};
+use tf_db::backup::Backups;
+use tracing::{error, warn};
 
 #[macro_use]
 mod util;
@@ -35,9 +39,16 @@ fn main() -> Result<()> {
 		.with(fmt_layer)
 		.init();
 
-	let mut db = connect_to_db()?;
+	let dirs = directories::ProjectDirs::from("", "Azorlogh", "tunefire")
+		.expect("failed to get data directory");
+	let mut db = connect_to_db(&dirs)?;
 
-	let main_window = WindowDesc::new(ui::ui(&db)).window_size((1000.0, 800.0));
+	if let Err(e) = tf_player::util::cache::init(dirs.cache_dir().join("audio"), AUDIO_CACHE_SIZE) {
+		warn!("failed to set up the audio cache: {e}");
+	}
+
+	let main_window =
+		WindowDesc::new(ui::ui(&db, dirs.data_dir().join("offline"))).window_size((1000.0, 800.0));
 	let state = State::new(&mut db)?;
 	AppLauncher::with_window(main_window)
 		.delegate(delegate::Delegate::new(db)?)
@@ -46,10 +57,33 @@ fn main() -> Result<()> {
 		.map_err(|err| anyhow!("failed to start app: {}", err))
 }
 
-fn connect_to_db() -> Result<tf_db::Client> {
-	let dirs = directories::ProjectDirs::from("", "Azorlogh", "tunefire")
-		.expect("failed to get data directory");
+fn connect_to_db(dirs: &directories::ProjectDirs) -> Result<tf_db::Client> {
 	std::fs::create_dir_all(dirs.data_dir())?;
 	let db_path = dirs.data_dir().join("db.slab");
-	tf_db::Client::new(db_path)
+	let db = tf_db::Client::new(db_path)?;
+
+	for record in db.check_integrity()? {
+		warn!("undecodable track {:?}: {}", record.key, record.error);
+	}
+
+	let backups = Backups::new(dirs.data_dir().join("snapshots"), BACKUPS_KEPT)?;
+	if let Err(e) = backups.create_if_older_than(&db, BACKUP_INTERVAL) {
+		warn!("failed to back up the database: {e}");
+	}
+	let backup_db = db.clone();
+	std::thread::Builder::new()
+		.name(String::from("backups"))
+		.spawn(move || loop {
+			std::thread::sleep(BACKUP_CHECK_INTERVAL);
+			if let Err(e) = backups.create_if_older_than(&backup_db, BACKUP_INTERVAL) {
+				error!("failed to back up the database: {e}");
+			}
+		})?;
+
+	Ok(db)
 }
+
+const BACKUPS_KEPT: usize = 10;
+const AUDIO_CACHE_SIZE: u64 = 1 << 30;
+const BACKUP_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
@@ -1,4 +1,4 @@
-use std::{rc::Rc, sync::Arc};
+use std::{path::PathBuf, rc::Rc, sync::Arc};
 
 use anyhow::Result;
 use druid::{im, Data, Lens};
@@ -33,6 +33,21 @@ pub struct State {
 	pub current_track: Option<Track>,
 	pub selected_track: Option<Arc<Uuid>>,
 	pub volume: f64,
+	pub output_device: Option<String>,
+	pub speed: f64,
+	pub preserve_pitch: bool,
+	// how long the tracks overlap, in seconds
+	pub crossfade: f64,
+	// marked to be available offline, the tracks of the queries aren't listed
+	pub offline_tracks: im::HashSet<Uuid>,
+	pub offline_queries: im::HashSet<String>,
+	// tracks that have a local copy
+	pub downloaded: im::HashSet<Uuid>,
+	#[data(same_fn = "PartialEq::eq")]
+	pub schedule: tf_db::Schedule,
+	// the folder where the changes are exchanged with the other devices
+	#[data(same_fn = "PartialEq::eq")]
+	pub sync_dir: Option<Arc<PathBuf>>,
 }
 
 impl State {
@@ -44,6 +59,22 @@ impl State {
 			.map(Into::into)
 			.collect();
 
+		let (mut offline_tracks, mut offline_queries) = (im::HashSet::new(), im::HashSet::new());
+		for mark in db.offline_marks()? {
+			match mark {
+				tf_db::OfflineMark::Track(id) => {
+					offline_tracks.insert(id);
+				}
+				tf_db::OfflineMark::Query(query) => {
+					offline_queries.insert(query);
+				}
+			}
+		}
+		let downloaded = db
+			.iter_offline_copies()
+			.map(|copy| copy.map(|(id, _)| id))
+			.collect::<Result<_>>()?;
+
 		let mut plugins: Vec<Box<dyn Plugin>> = vec![];
 		#[cfg(feature = "local")]
 		plugins.push(Box::new(tf_plugin_local::Local));
@@ -70,6 +101,20 @@ impl State {
 			current_track: None,
 			selected_track: None,
 			volume: 1.0,
+			output_device: db.get_setting(crate::controller::playback::OUTPUT_DEVICE_SETTING)?,
+			speed: 1.0,
+			preserve_pitch: true,
+			crossfade: db
+				.get_setting(crate::controller::playback::CROSSFADE_SETTING)?
+				.unwrap_or(0.0),
+			offline_tracks,
+			offline_queries,
+			downloaded,
+			schedule: db.get_schedule()?,
+			sync_dir: db
+				.get_setting::<Option<PathBuf>>(crate::delegate::SYNC_DIR_SETTING)?
+				.flatten()
+				.map(Arc::new),
 		})
 	}
 }
@@ -14,11 +14,21 @@ pub struct TrackEdit {
 	pub artists: IdentifiedVector<String>,
 	pub source: String,
 	pub tags: im::Vector<(u128, (String, f32))>,
+	// detected from the audio, the user can accept them
+	pub suggested_tags: im::Vector<(String, f32)>,
+	// in seconds, empty when playback starts or stops with the audio
+	pub cue_in: String,
+	pub cue_out: String,
 	pub tag_suggestions: TagSuggestions,
 }
 
 impl TrackEdit {
-	pub fn new(id: Uuid, track: tf_db::Track) -> Self {
+	pub fn new(
+		id: Uuid,
+		track: tf_db::Track,
+		suggested_tags: Option<HashMap<String, f32>>,
+		cues: tf_db::Cues,
+	) -> Self {
 		Self {
 			id: Rc::new(id),
 			title: track.title,
@@ -32,6 +42,9 @@ impl TrackEdit {
 					.iter()
 					.map(|(n, v)| (rand::random(), (n.to_owned(), *v))),
 			),
+			suggested_tags: suggested_tags.into_iter().flatten().collect(),
+			cue_in: cues.cue_in.map(|c| c.to_string()).unwrap_or_default(),
+			cue_out: cues.cue_out.map(|c| c.to_string()).unwrap_or_default(),
 			tag_suggestions: TagSuggestions {
 				tags: im::Vector::new(),
 				selected: 0,
@@ -39,10 +52,24 @@ impl TrackEdit {
 		}
 	}
 
+	pub fn accept_suggested_tag(&mut self, tag: &str) {
+		if let Some(i) = self.suggested_tags.iter().position(|(name, _)| name == tag) {
+			let suggested = self.suggested_tags.remove(i);
+			self.tags.retain(|(_, (name, _))| name != tag);
+			self.tags.push_back((rand::random(), suggested));
+		}
+	}
+
 	pub fn get_tags(&self) -> HashMap<String, f32> {
 		self.tags.iter().map(|(_, t)| t).cloned().collect()
 	}
 
+	// Cues that aren't numbers are left out, the cue points are kept
+	pub fn apply_cues(&self, cues: &mut tf_db::Cues) {
+		cues.cue_in = self.cue_in.trim().parse().ok();
+		cues.cue_out = self.cue_out.trim().parse().ok();
+	}
+
 	pub fn get_track(&self) -> Track {
 		Track {
 			source: self.source.clone(),
@@ -21,6 +21,8 @@ pub struct NewTrack {
 	pub source: String,
 	pub title: String,
 	pub artists: IdentifiedVector<String>,
+	// known from the source, like the genre in a file's metadata
+	pub tags: im::HashMap<String, f32>,
 }
 
 impl NewTrack {
@@ -29,7 +31,7 @@ impl NewTrack {
 			source: self.source.clone(),
 			artists: self.artists.iter().map(|(_, name)| name).cloned().collect(),
 			title: self.title.clone(),
-			tags: Default::default(),
+			tags: self.tags.clone().into_iter().collect(),
 		}
 	}
 }
@@ -1,17 +1,29 @@
 use std::{rc::Rc, time::Duration};
 
+use chrono::{Local, TimeZone};
 use druid::{
-	widget::{Button, Container, Flex, Label, Maybe, Painter, SizedBox, Slider},
-	BoxConstraints, Data, EventCtx, Lens, Size, Widget, WidgetExt,
+	lens::Map,
+	menu::{Menu, MenuItem},
+	widget::{Button, Container, Either, Flex, Label, Maybe, Painter, SizedBox, Slider},
+	BoxConstraints, Data, EventCtx, Lens, Point, Size, Widget, WidgetExt,
 };
-use tf_player::player::state::Playing;
+use tf_db::Stop;
+use tf_player::player::{sink, state::Playing, AnalysisTap};
+use tracing::warn;
 
 use super::{draw_icon_button, ICON_NEXT, ICON_PAUSE, ICON_PLAY, ICON_PREV};
 use crate::{
-	controller::playback,
+	controller::{
+		playback,
+		schedule::{self, SCHEDULE_STOP},
+	},
 	state::Track,
 	theme,
-	widget::{controllers::OnDebounce, overlay, player_bar::PlayerBar},
+	widget::{
+		common::spectrum::Spectrum, controllers::OnDebounce, cover_art::CoverArt, overlay,
+		player_bar::PlayerBar,
+	},
+	State,
 };
 
 #[derive(Clone, Data, Lens)]
@@ -19,9 +31,15 @@ pub struct MediaBarState {
 	pub playing: Rc<Playing>,
 	pub current_track: Option<Track>,
 	pub volume: f64,
+	pub output_device: Option<String>,
+	pub speed: f64,
+	pub preserve_pitch: bool,
+	pub crossfade: f64,
+	#[data(same_fn = "PartialEq::eq")]
+	pub stop: Option<Stop>,
 }
 
-pub fn ui() -> impl Widget<MediaBarState> {
+pub fn ui(tap: AnalysisTap) -> impl Widget<MediaBarState> {
 	let buttons = Flex::row()
 		.with_child(prev_button())
 		.with_default_spacer()
@@ -31,6 +49,12 @@ pub fn ui() -> impl Widget<MediaBarState> {
 
 	let right_buttons = Flex::row()
 		.with_flex_spacer(1.0)
+		.with_child(
+			Spectrum::new(tap)
+				.fix_size(120.0, 36.0)
+				.lens(Map::new(|s: &MediaBarState| !s.playing.paused, |_, _| {})),
+		)
+		.with_default_spacer()
 		.with_child(
 			Slider::new()
 				.controller(OnDebounce::trailing(
@@ -43,6 +67,40 @@ pub fn ui() -> impl Widget<MediaBarState> {
 				.lens(MediaBarState::volume),
 		)
 		.with_default_spacer()
+		.with_child(
+			Button::dynamic(|data: &MediaBarState, _| sleep_label(data.stop)).on_click(
+				|ctx: &mut EventCtx, data: &mut MediaBarState, _| {
+					let menu = sleep_menu(data.stop);
+					ctx.show_context_menu::<State>(menu, ctx.to_window(Point::ZERO));
+				},
+			),
+		)
+		.with_default_spacer()
+		.with_child(
+			Button::dynamic(|data: &MediaBarState, _| format!("{}×", data.speed)).on_click(
+				|ctx: &mut EventCtx, data: &mut MediaBarState, _| {
+					let menu = speed_menu(data.speed, data.preserve_pitch);
+					ctx.show_context_menu::<State>(menu, ctx.to_window(Point::ZERO));
+				},
+			),
+		)
+		.with_default_spacer()
+		.with_child(
+			Button::dynamic(|data: &MediaBarState, _| crossfade_label(data.crossfade)).on_click(
+				|ctx: &mut EventCtx, data: &mut MediaBarState, _| {
+					let menu = crossfade_menu(data.crossfade);
+					ctx.show_context_menu::<State>(menu, ctx.to_window(Point::ZERO));
+				},
+			),
+		)
+		.with_default_spacer()
+		.with_child(Button::new("🔈").on_click(
+			|ctx: &mut EventCtx, data: &mut MediaBarState, _| {
+				let menu = output_device_menu(&data.output_device);
+				ctx.show_context_menu::<State>(menu, ctx.to_window(Point::ZERO));
+			},
+		))
+		.with_default_spacer()
 		.with_child(Button::new("☰").on_click(
 			move |ctx: &mut EventCtx, _: &mut MediaBarState, _| {
 				ctx.submit_command(overlay::SHOW_MIDDLE.with((
@@ -63,18 +121,34 @@ pub fn ui() -> impl Widget<MediaBarState> {
 	)
 	.expand_width();
 
+	let cover = CoverArt::default()
+		.fix_size(36.0, 36.0)
+		.lens(Map::new(
+			|data: &Rc<Playing>| data.track.cover.clone(),
+			|_, _| {},
+		))
+		.lens(MediaBarState::playing);
+
 	Flex::column()
 		.with_child(
 			Flex::row()
+				.with_child(cover)
+				.with_default_spacer()
 				.with_flex_child(track_info.lens(MediaBarState::current_track), 1.0)
 				.with_child(buttons.lens(MediaBarState::playing))
 				.with_flex_child(right_buttons, 1.0),
 		)
 		.with_child(
 			Flex::row()
-				.with_child(Label::new(|data: &Rc<Playing>, _: &_| {
-					format_duration(&data.offset)
-				}))
+				.with_child(Either::new(
+					|data: &Rc<Playing>, _| data.buffering.is_some(),
+					Label::new(|data: &Rc<Playing>, _: &_| {
+						let progress = data.buffering.unwrap_or_default();
+						format!("Buffering {:.0}%", progress * 100.0)
+					})
+					.with_text_color(theme::FOREGROUND_DIM),
+					Label::new(|data: &Rc<Playing>, _: &_| format_duration(&data.offset)),
+				))
 				.with_default_spacer()
 				.with_flex_child(PlayerBar::default(), 1.0)
 				.with_default_spacer()
@@ -112,6 +186,135 @@ fn next_button() -> impl Widget<Rc<Playing>> {
 		})
 }
 
+fn output_device_menu(current: &Option<String>) -> Menu<State> {
+	let devices = sink::output_devices().unwrap_or_else(|e| {
+		warn!("failed to list the output devices: {e}");
+		vec![]
+	});
+	let default = MenuItem::new("Default")
+		.selected(current.is_none())
+		.on_activate(|ctx, _, _| ctx.submit_command(playback::PLAYER_SET_OUTPUT_DEVICE.with(None)));
+	let mut menu = Menu::new("Output device").entry(default);
+	// the default device plays until the chosen one comes back
+	if let Some(missing) = current.as_ref().filter(|device| !devices.contains(device)) {
+		menu = menu.entry(MenuItem::new(format!("{missing} (missing)")).enabled(false));
+	}
+	devices.into_iter().fold(menu, |menu, device| {
+		let selected = current.as_ref() == Some(&device);
+		menu.entry(
+			MenuItem::new(device.clone())
+				.selected(selected)
+				.on_activate(move |ctx, _, _| {
+					ctx.submit_command(
+						playback::PLAYER_SET_OUTPUT_DEVICE.with(Some(device.clone())),
+					)
+				}),
+		)
+	})
+}
+
+// Shows when the sleep timer stops playback
+fn sleep_label(stop: Option<Stop>) -> String {
+	match stop {
+		None => String::from("⏾"),
+		Some(Stop::At(time)) => match Local.timestamp_opt(time as i64, 0).single() {
+			Some(time) => format!("⏾ {}", time.format("%H:%M")),
+			None => String::from("⏾"),
+		},
+		Some(Stop::AfterTrack(_)) => String::from("⏾ ⏭"),
+		Some(Stop::AfterQueue) => String::from("⏾ ☰"),
+	}
+}
+
+const SLEEP_MINUTES: [u64; 6] = [5, 15, 30, 45, 60, 90];
+
+fn sleep_menu(current: Option<Stop>) -> Menu<State> {
+	let menu = SLEEP_MINUTES
+		.into_iter()
+		.fold(Menu::new("Sleep timer"), |menu, minutes| {
+			menu.entry(
+				MenuItem::new(format!("Stop in {minutes} minutes")).on_activate(
+					move |ctx, _, _| {
+						let time = schedule::unix_time() + Duration::from_secs(minutes * 60);
+						ctx.submit_command(SCHEDULE_STOP.with(Some(Stop::At(time.as_secs()))))
+					},
+				),
+			)
+		})
+		.separator()
+		.entry(
+			MenuItem::new("Stop after this track").on_activate(|ctx, data: &mut State, _| {
+				if let Some(track) = &data.current_track {
+					ctx.submit_command(SCHEDULE_STOP.with(Some(Stop::AfterTrack(*track.id))))
+				}
+			}),
+		)
+		.entry(
+			MenuItem::new("Stop after the queue").on_activate(|ctx, _, _| {
+				ctx.submit_command(SCHEDULE_STOP.with(Some(Stop::AfterQueue)))
+			}),
+		);
+	if current.is_none() {
+		return menu;
+	}
+	menu.separator().entry(
+		MenuItem::new("Cancel the sleep timer")
+			.on_activate(|ctx, _, _| ctx.submit_command(SCHEDULE_STOP.with(None))),
+	)
+}
+
+const SPEEDS: [f64; 7] = [0.5, 0.75, 0.9, 1.0, 1.1, 1.25, 1.5];
+
+fn speed_menu(current: f64, preserve_pitch: bool) -> Menu<State> {
+	let preserve = MenuItem::new("Preserve pitch")
+		.selected(preserve_pitch)
+		.on_activate(move |ctx, _, _| {
+			ctx.submit_command(playback::PLAYER_SET_PRESERVE_PITCH.with(!preserve_pitch))
+		});
+	SPEEDS
+		.into_iter()
+		.fold(Menu::new("Speed"), |menu, speed| {
+			menu.entry(
+				MenuItem::new(format!("{speed}×"))
+					.selected(speed == current)
+					.on_activate(move |ctx, _, _| {
+						ctx.submit_command(playback::PLAYER_SET_SPEED.with(speed))
+					}),
+			)
+		})
+		.separator()
+		.entry(preserve)
+}
+
+fn crossfade_label(crossfade: f64) -> String {
+	if crossfade > 0.0 {
+		format!("⤨ {crossfade}s")
+	} else {
+		String::from("⤨")
+	}
+}
+
+const CROSSFADES: [f64; 6] = [0.0, 2.0, 4.0, 6.0, 8.0, 12.0];
+
+fn crossfade_menu(current: f64) -> Menu<State> {
+	CROSSFADES
+		.into_iter()
+		.fold(Menu::new("Crossfade"), |menu, crossfade| {
+			let label = if crossfade > 0.0 {
+				format!("Crossfade over {crossfade}s")
+			} else {
+				String::from("No crossfade")
+			};
+			menu.entry(
+				MenuItem::new(label)
+					.selected(crossfade == current)
+					.on_activate(move |ctx, _, _| {
+						ctx.submit_command(playback::PLAYER_SET_CROSSFADE.with(crossfade))
+					}),
+			)
+		})
+}
+
 fn format_duration(d: &Duration) -> String {
 	format!("{:02}:{:02}", d.as_secs() / 60, d.as_secs() % 60)
 }
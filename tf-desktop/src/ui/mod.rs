@@ -1,19 +1,27 @@
-use std::rc::Rc;
+use std::{path::PathBuf, rc::Rc};
 
 use druid::{
 	keyboard_types::Key,
 	kurbo::{BezPath, Circle},
 	lens::Map,
-	widget::{ControllerHost, Flex, Label, Maybe, Painter, Scroll, SizedBox, TextBox},
-	Affine, Env, EventCtx, PaintCtx, RenderContext, TextAlignment, Vec2, Widget, WidgetExt,
+	menu::{Menu, MenuItem},
+	widget::{Button, ControllerHost, Flex, Label, Maybe, Painter, Scroll, SizedBox, TextBox},
+	Affine, Color, Env, EventCtx, FileDialogOptions, PaintCtx, Point, RenderContext, TextAlignment,
+	Vec2, Widget, WidgetExt,
 };
+use tf_db::Alarm;
 use tf_player::player;
 
 use self::media_bar::MediaBarState;
 use crate::{
 	command,
 	controller::{
-		import::ImportController, playback::PlaybackController, search::SearchController,
+		detection::DetectionController,
+		import::ImportController,
+		offline::{OfflineController, OFFLINE_MARK},
+		playback::PlaybackController,
+		schedule::{ScheduleController, SCHEDULE_ALARM},
+		search::SearchController,
 	},
 	data::ctx::Ctx,
 	theme,
@@ -27,7 +35,7 @@ mod track_edit;
 mod track_import;
 mod track_list;
 
-pub fn ui(db: &tf_db::Client) -> impl Widget<State> {
+pub fn ui(db: &tf_db::Client, offline_dir: PathBuf) -> impl Widget<State> {
 	let query_box = query_box();
 
 	let track_edit_db = db.clone();
@@ -41,6 +49,10 @@ pub fn ui(db: &tf_db::Client) -> impl Widget<State> {
 				.lens(State::track_edit),
 		);
 
+	let playback =
+		PlaybackController::new(db.clone()).expect("Couldn't create playback controller");
+	let tap = playback.analysis_tap();
+
 	let mut root = Flex::column();
 	root.add_default_spacer();
 	root.add_child(query_box);
@@ -50,12 +62,17 @@ pub fn ui(db: &tf_db::Client) -> impl Widget<State> {
 	root.add_child(search_bar());
 	root.add_default_spacer();
 	root.add_child(
-		Maybe::new(|| media_bar::ui(), || SizedBox::empty()).lens(Map::new(
+		Maybe::new(move || media_bar::ui(tap.clone()), || SizedBox::empty()).lens(Map::new(
 			|s: &State| {
 				s.player_state.get_playing().map(|p| MediaBarState {
 					playing: Rc::new(p.clone()),
 					current_track: s.current_track.clone(),
 					volume: s.volume,
+					output_device: s.output_device.clone(),
+					speed: s.speed,
+					preserve_pitch: s.preserve_pitch,
+					crossfade: s.crossfade,
+					stop: s.schedule.stop,
 				})
 			},
 			|s: &mut State, inner: Option<MediaBarState>| {
@@ -73,9 +90,12 @@ pub fn ui(db: &tf_db::Client) -> impl Widget<State> {
 		.with_child(
 			root.padding(10.0)
 				.expand_width()
-				.controller(PlaybackController::new().expect("Couldn't create playback controller"))
+				.controller(playback)
 				.controller(SearchController)
-				.controller(ImportController),
+				.controller(ImportController)
+				.controller(OfflineController::new(db.clone(), offline_dir))
+				.controller(DetectionController::new(db.clone()))
+				.controller(ScheduleController::new(db.clone())),
 		)
 		.with_child(
 			Maybe::new(
@@ -91,6 +111,12 @@ fn query_box() -> impl Widget<State> {
 	Flex::row()
 		.with_child(play_query_button())
 		.with_default_spacer()
+		.with_child(offline_query_button())
+		.with_default_spacer()
+		.with_child(alarm_button())
+		.with_default_spacer()
+		.with_child(sync_button())
+		.with_default_spacer()
 		.with_flex_child(
 			ControllerHost::new(
 				TextBox::new()
@@ -123,11 +149,120 @@ fn search_bar() -> impl Widget<State> {
 fn play_query_button() -> impl Widget<State> {
 	Painter::new(|ctx, _: &State, env| draw_icon_button(ctx, env, ICON_FIRE))
 		.fix_size(36.0, 36.0)
-		.on_click(|ctx: &mut EventCtx, _, _| {
-			ctx.submit_command(command::QUERY_PLAY);
+		.on_click(|ctx: &mut EventCtx, data: &mut State, _| {
+			ctx.submit_command(command::QUERY_PLAY.with(data.query.clone()));
 		})
 }
 
+// Keeps the results of the query available offline, as they change
+fn offline_query_button() -> impl Widget<State> {
+	Painter::new(|ctx, data: &State, env| {
+		let color = if data.offline_queries.contains(&data.query) {
+			theme::ACCENT
+		} else {
+			theme::FOREGROUND_DIM
+		};
+		draw_colored_icon_button(ctx, env, ICON_DOWNLOAD, color)
+	})
+	.fix_size(36.0, 36.0)
+	.on_click(|ctx: &mut EventCtx, data: &mut State, _| {
+		let offline = !data.offline_queries.contains(&data.query);
+		ctx.submit_command(
+			OFFLINE_MARK.with((tf_db::OfflineMark::Query(data.query.clone()), offline)),
+		);
+	})
+}
+
+// Plays the query every day at a time picked from its menu
+fn alarm_button() -> impl Widget<State> {
+	Button::dynamic(|data: &State, _| match &data.schedule.alarm {
+		Some(alarm) => format!("⏰ {:02}:{:02}", alarm.hour, alarm.minute),
+		None => String::from("⏰"),
+	})
+	.on_click(|ctx: &mut EventCtx, data: &mut State, _| {
+		let menu = alarm_menu(&data.schedule.alarm, &data.query);
+		ctx.show_context_menu::<State>(menu, ctx.to_window(Point::ZERO));
+	})
+}
+
+fn alarm_menu(current: &Option<Alarm>, query: &str) -> Menu<State> {
+	let mut menu = Menu::new("Alarm");
+	if let Some(alarm) = current {
+		menu = menu
+			.entry(
+				MenuItem::new(format!(
+					"Plays {} at {:02}:{:02}",
+					query_label(&alarm.query),
+					alarm.hour,
+					alarm.minute
+				))
+				.enabled(false),
+			)
+			.entry(
+				MenuItem::new("Remove the alarm")
+					.on_activate(|ctx, _, _| ctx.submit_command(SCHEDULE_ALARM.with(None))),
+			)
+			.separator();
+	}
+	// one submenu per hour, by quarters of an hour
+	let mut times = Menu::new(format!("Play {} every day at", query_label(query)));
+	for hour in 0..24 {
+		let mut quarters = Menu::new(format!("{hour:02}:00"));
+		for minute in [0, 15, 30, 45] {
+			let alarm = Alarm {
+				hour,
+				minute,
+				query: query.to_owned(),
+			};
+			quarters = quarters.entry(MenuItem::new(format!("{hour:02}:{minute:02}")).on_activate(
+				move |ctx, _, _| ctx.submit_command(SCHEDULE_ALARM.with(Some(alarm.clone()))),
+			));
+		}
+		times = times.entry(quarters);
+	}
+	menu.entry(times)
+}
+
+// The library is synced with the other devices through a folder they share, like a synced drive
+fn sync_button() -> impl Widget<State> {
+	Button::new("⇅").on_click(|ctx: &mut EventCtx, data: &mut State, _| {
+		let menu = sync_menu(data.sync_dir.as_deref());
+		ctx.show_context_menu::<State>(menu, ctx.to_window(Point::ZERO));
+	})
+}
+
+fn sync_menu(current: Option<&PathBuf>) -> Menu<State> {
+	let pick = MenuItem::new("Choose the sync folder…").on_activate(|ctx, _, _| {
+		let options = FileDialogOptions::new()
+			.select_directories()
+			.accept_command(command::DB_SYNC_DIR_PICKED);
+		ctx.submit_command(druid::commands::SHOW_OPEN_PANEL.with(options))
+	});
+	let Some(dir) = current else {
+		return Menu::new("Sync").entry(pick);
+	};
+	Menu::new("Sync")
+		.entry(MenuItem::new(format!("Syncs with {}", dir.display())).enabled(false))
+		.entry(
+			MenuItem::new("Sync now").on_activate(|ctx, _, _| ctx.submit_command(command::DB_SYNC)),
+		)
+		.separator()
+		.entry(pick)
+		.entry(
+			MenuItem::new("Stop syncing")
+				.on_activate(|ctx, _, _| ctx.submit_command(command::DB_SYNC_DIR_CLEAR)),
+		)
+}
+
+// The empty query matches every track, as its placeholder shows
+fn query_label(query: &str) -> &str {
+	if query.is_empty() {
+		"*"
+	} else {
+		query
+	}
+}
+
 pub const ICON_FIRE: &str = include_str!("../../assets/fire.svg");
 pub const ICON_PLAY: &str = include_str!("../../assets/play.svg");
 pub const ICON_PAUSE: &str = include_str!("../../assets/pause.svg");
@@ -135,9 +270,20 @@ pub const ICON_PREV: &str = include_str!("../../assets/previous.svg");
 pub const ICON_NEXT: &str = include_str!("../../assets/next.svg");
 pub const ICON_EDIT: &str = include_str!("../../assets/edit.svg");
 pub const ICON_DELETE: &str = include_str!("../../assets/delete.svg");
+pub const ICON_DOWNLOAD: &str = include_str!("../../assets/download.svg");
 pub const _ICON_SETTINGS: &str = include_str!("../../assets/settings.svg");
 
 pub fn draw_icon_button(ctx: &mut PaintCtx, env: &Env, icon_svg: &str) {
+	draw_colored_icon_button(ctx, env, icon_svg, crate::theme::FOREGROUND_DIM)
+}
+
+// Like `draw_icon_button`, with another color than the default one when not hovered
+pub fn draw_colored_icon_button(
+	ctx: &mut PaintCtx,
+	env: &Env,
+	icon_svg: &str,
+	color: druid::Key<Color>,
+) {
 	let size = ctx.size();
 	let rad = size.min_side() / 2.0;
 	if ctx.is_hot() && ctx.has_focus() {
@@ -150,7 +296,7 @@ pub fn draw_icon_button(ctx: &mut PaintCtx, env: &Env, icon_svg: &str) {
 	let color = env.get(if ctx.is_hot() {
 		crate::theme::FOREGROUND
 	} else {
-		crate::theme::FOREGROUND_DIM
+		color
 	});
 	ctx.fill(
 		Affine::translate(Vec2::new(size.min_side() / 2.0, size.min_side() / 2.0))
@@ -5,6 +5,8 @@ use druid::{
 	Insets, Widget, WidgetExt,
 };
 
+use tf_player::player::Readiness;
+
 use crate::{state::Track, theme, State};
 
 pub fn ui() -> impl Widget<State> {
@@ -15,12 +17,34 @@ pub fn ui() -> impl Widget<State> {
 		.with_tab("Queue", {
 			Flex::column()
 				.with_child(Maybe::new(track_ui, || SizedBox::empty()).lens(State::current_track))
+				.with_child(EnvScope::new(
+					|env, _| env.set(druid::theme::TEXT_COLOR, env.get(theme::FOREGROUND_DIM)),
+					Label::dynamic(|s: &State, _| next_status(s))
+						.with_text_size(12.0)
+						.align_left(),
+				))
 				.with_flex_child(List::new(track_ui).lens(State::queue), 1.0)
 		})
 		.with_tab("History", List::new(track_ui).lens(State::history))
 		.background(theme::BACKGROUND)
 }
 
+// How ready the next track is to start
+fn next_status(state: &State) -> String {
+	let readiness = state.queue.front().and_then(|next| {
+		let upcoming = &state.player_state.get_playing()?.upcoming;
+		let key = next.id.to_string();
+		upcoming.iter().find(|(k, _)| *k == key).map(|(_, r)| r)
+	});
+	match readiness {
+		None | Some(Readiness::Waiting) => String::new(),
+		Some(Readiness::Resolving) => String::from("Next: loading"),
+		Some(Readiness::Buffering(progress)) => format!("Next: buffering {:.0}%", progress * 100.0),
+		Some(Readiness::Ready) => String::from("Next: ready"),
+		Some(Readiness::Failed(e)) => format!("Next: failed ({e})"),
+	}
+}
+
 fn track_ui() -> impl Widget<Track> {
 	Flex::column()
 		.with_child(
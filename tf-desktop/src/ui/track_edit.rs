@@ -2,7 +2,7 @@ use druid::{
 	im,
 	keyboard_types::Key,
 	lens,
-	widget::{Container, CrossAxisAlignment, Flex, Label, TextBox},
+	widget::{Container, CrossAxisAlignment, Flex, Label, List, TextBox},
 	Data, Widget, WidgetExt,
 };
 
@@ -67,6 +67,25 @@ pub fn ui(db: &tf_db::Client) -> impl Widget<TrackEdit> {
 				.with_child(TextBox::new().lens(TrackEdit::source)),
 		)
 		.with_default_spacer()
+		.with_child(
+			Flex::row()
+				.with_child(Label::new("cue in"))
+				.with_child(
+					TextBox::new()
+						.with_placeholder("start")
+						.lens(TrackEdit::cue_in)
+						.fix_width(64.0),
+				)
+				.with_default_spacer()
+				.with_child(Label::new("cue out"))
+				.with_child(
+					TextBox::new()
+						.with_placeholder("end")
+						.lens(TrackEdit::cue_out)
+						.fix_width(64.0),
+				),
+		)
+		.with_default_spacer()
 		.with_child(
 			SmartList::new(|| TagEdit::new(), |data| data.data.0)
 				.controller(ItemDeleter::<
@@ -91,6 +110,28 @@ pub fn ui(db: &tf_db::Client) -> impl Widget<TrackEdit> {
 				data.tags.push_back((rand::random(), ("".to_owned(), 0.5)));
 			}),
 		)
+		.with_default_spacer()
+		.with_child(
+			List::new(|| {
+				Flex::row()
+					.with_flex_child(
+						Label::new(|(tag, value): &(String, f32), _: &_| format!("{tag}: {value}"))
+							.with_text_color(crate::theme::FOREGROUND_DIM),
+						1.0,
+					)
+					.with_child(FocusableButton::new("ACCEPT").on_click(
+						|ctx, (tag, _): &mut (String, f32), _| {
+							ctx.submit_command(command::UI_TRACK_EDIT_ACCEPT_TAG.with(tag.clone()))
+						},
+					))
+					.with_child(FocusableButton::new("×").on_click(
+						|ctx, (tag, _): &mut (String, f32), _| {
+							ctx.submit_command(command::UI_TRACK_EDIT_DISMISS_TAG.with(tag.clone()))
+						},
+					))
+			})
+			.lens(TrackEdit::suggested_tags),
+		)
 		.with_flex_spacer(1.0)
 		.with_child(
 			FocusableButton::new("CLOSE").on_click(|ctx, _: &mut TrackEdit, _| {
@@ -1,7 +1,7 @@
 use std::{sync::Arc, time::Duration};
 
 use druid::{
-	lens,
+	im, lens,
 	widget::{
 		Axis, Container, CrossAxisAlignment, Either, EnvScope, Flex, Label, List,
 		MainAxisAlignment, Painter, SizedBox,
@@ -10,10 +10,16 @@ use druid::{
 };
 use uuid::Uuid;
 
-use super::{draw_icon_button, ICON_DELETE, ICON_EDIT, ICON_PAUSE, ICON_PLAY};
+use super::{
+	draw_colored_icon_button, draw_icon_button, ICON_DELETE, ICON_DOWNLOAD, ICON_EDIT, ICON_PAUSE,
+	ICON_PLAY,
+};
 use crate::{
 	command,
-	controller::playback::{PLAYER_CLEAR, PLAYER_ENQUEUE, PLAYER_PLAY_PAUSE},
+	controller::{
+		offline::OFFLINE_MARK,
+		playback::{PLAYER_CLEAR, PLAYER_ENQUEUE, PLAYER_PLAY_PAUSE},
+	},
 	data::ctx::Ctx,
 	state::Track,
 	theme,
@@ -31,6 +37,8 @@ use crate::{
 pub struct TrackCtx {
 	pub playing: Option<Arc<Uuid>>,
 	pub selected: Option<Arc<Uuid>>,
+	pub offline: im::HashSet<Uuid>,
+	pub downloaded: im::HashSet<Uuid>,
 }
 
 const TRACK_HEIGHT: f64 = 64.0;
@@ -169,6 +177,7 @@ pub fn ui() -> impl Widget<State> {
 			})
 			.fix_width(64.0),
 		)
+		.with_child(column_ui("", offline_button).fix_width(64.0))
 		.with_child(column_ui("", delete_button).fix_width(64.0))
 		.with_default_spacer()
 		.cross_axis_alignment(CrossAxisAlignment::Start);
@@ -194,6 +203,8 @@ pub fn ui() -> impl Widget<State> {
 							|s: &State| TrackCtx {
 								playing: s.current_track.as_ref().map(|t| t.id.clone()),
 								selected: s.selected_track.as_ref().cloned(),
+								offline: s.offline_tracks.clone(),
+								downloaded: s.downloaded.clone(),
 							},
 							|_, _| {},
 						),
@@ -226,6 +237,8 @@ where
 				|s: &State| TrackCtx {
 					playing: s.current_track.as_ref().map(|t| t.id.clone()),
 					selected: s.selected_track.as_ref().cloned(),
+					offline: s.offline_tracks.clone(),
+					downloaded: s.downloaded.clone(),
 				},
 				|_, _| {},
 			),
@@ -256,6 +269,27 @@ fn play_track_button() -> impl Widget<Ctx<TrackCtx, Track>> {
 	})
 }
 
+// Highlighted once the track was downloaded, or while it is waiting to be
+fn offline_button() -> impl Widget<Ctx<TrackCtx, Track>> {
+	Painter::new(|ctx, data: &Ctx<TrackCtx, Track>, env| {
+		let color = if data.ctx.downloaded.contains(&*data.data.id) {
+			theme::ACCENT
+		} else if data.ctx.offline.contains(&*data.data.id) {
+			theme::FOREGROUND
+		} else {
+			theme::FOREGROUND_DIM
+		};
+		draw_colored_icon_button(ctx, env, ICON_DOWNLOAD, color)
+	})
+	.fix_size(36.0, 36.0)
+	.on_click(|ctx: &mut EventCtx, data: &mut Ctx<TrackCtx, Track>, _| {
+		let id = *data.data.id;
+		let offline = !data.ctx.offline.contains(&id);
+		ctx.submit_command(OFFLINE_MARK.with((tf_db::OfflineMark::Track(id), offline)));
+	})
+	.center()
+}
+
 fn delete_button() -> impl Widget<Ctx<TrackCtx, Track>> {
 	Painter::new(|ctx, _, env| draw_icon_button(ctx, env, ICON_DELETE))
 		.fix_size(36.0, 36.0)
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use druid::{
+	piet::ImageFormat,
+	widget::{prelude::*, Image},
+	ImageBuf,
+};
+use tf_player::Cover;
+use tracing::warn;
+
+// Shows the artwork embedded in a track, decoded once per cover
+#[derive(Default)]
+pub struct CoverArt {
+	image: Option<Image>,
+}
+
+impl CoverArt {
+	fn set_cover(&mut self, cover: &Option<Arc<Cover>>) {
+		self.image = cover.as_deref().and_then(decode).map(Image::new);
+	}
+}
+
+fn decode(cover: &Cover) -> Option<ImageBuf> {
+	match image::load_from_memory(&cover.data) {
+		Ok(image) => {
+			let image = image.to_rgba8();
+			Some(ImageBuf::from_raw(
+				image.as_raw().as_slice(),
+				ImageFormat::RgbaSeparate,
+				image.width() as usize,
+				image.height() as usize,
+			))
+		}
+		Err(e) => {
+			warn!("failed to decode a cover of type {}: {e}", cover.media_type);
+			None
+		}
+	}
+}
+
+impl Widget<Option<Arc<Cover>>> for CoverArt {
+	fn event(&mut self, _: &mut EventCtx, _: &Event, _: &mut Option<Arc<Cover>>, _: &Env) {}
+
+	fn lifecycle(
+		&mut self,
+		_: &mut LifeCycleCtx,
+		event: &LifeCycle,
+		data: &Option<Arc<Cover>>,
+		_: &Env,
+	) {
+		if let LifeCycle::WidgetAdded = event {
+			self.set_cover(data);
+		}
+	}
+
+	fn update(
+		&mut self,
+		ctx: &mut UpdateCtx,
+		old_data: &Option<Arc<Cover>>,
+		data: &Option<Arc<Cover>>,
+		_: &Env,
+	) {
+		if !old_data.same(data) {
+			self.set_cover(data);
+			ctx.request_layout();
+		}
+	}
+
+	fn layout(
+		&mut self,
+		ctx: &mut LayoutCtx,
+		bc: &BoxConstraints,
+		data: &Option<Arc<Cover>>,
+		env: &Env,
+	) -> Size {
+		match &mut self.image {
+			Some(image) => image.layout(ctx, bc, data, env),
+			None => bc.min(),
+		}
+	}
+
+	fn paint(&mut self, ctx: &mut PaintCtx, data: &Option<Arc<Cover>>, env: &Env) {
+		if let Some(image) = &mut self.image {
+			image.paint(ctx, data, env);
+		}
+	}
+}
@@ -1,3 +1,4 @@
+pub mod cover_art;
 pub mod overlay;
 pub mod player_bar;
 pub mod player_tick;
@@ -1,14 +1,21 @@
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 
 use druid::{
 	kurbo::{Line, Size},
+	menu::{Menu, MenuItem},
 	piet::{LineCap, LineJoin, RenderContext, StrokeStyle},
 	widget::prelude::*,
-	Point,
+	Point, Rect,
 };
 use tf_player::player;
 
-use crate::{controller::playback::PLAYER_SEEK, theme};
+use crate::{
+	controller::playback::{
+		PLAYER_ADD_CUE, PLAYER_CLEAR_CUES, PLAYER_CLEAR_LOOP, PLAYER_JUMP_TO_CUE, PLAYER_SEEK,
+		PLAYER_SET_LOOP,
+	},
+	theme, State,
+};
 
 #[derive(Default)]
 pub struct PlayerBar {
@@ -20,6 +27,18 @@ type Data = Rc<player::state::Playing>;
 impl Widget<Data> for PlayerBar {
 	fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut Data, _env: &Env) {
 		match event {
+			Event::MouseDown(evt) if evt.button.is_right() => {
+				if !ctx.is_disabled() {
+					let position = data
+						.track
+						.duration
+						.mul_f64((evt.pos.x / ctx.size().width).clamp(0.0, 1.0));
+					ctx.show_context_menu::<State>(
+						marker_menu(data, position),
+						ctx.to_window(evt.pos),
+					);
+				}
+			}
 			Event::MouseDown(_) => {
 				if !ctx.is_disabled() {
 					ctx.set_active(true);
@@ -67,6 +86,20 @@ impl Widget<Data> for PlayerBar {
 	fn paint(&mut self, ctx: &mut PaintCtx, data: &Data, env: &Env) {
 		let size = ctx.size();
 
+		let progress = if ctx.is_active() {
+			self.position_preview
+		} else {
+			data.offset.as_secs_f64() / data.track.duration.as_secs_f64()
+		};
+
+		if let Some(waveform) = &data.track.waveform {
+			paint_waveform(ctx, env, waveform, progress);
+			// under the waveform, which takes the whole height
+			paint_buffered(ctx, env, data, size.height - 1.0, 2.0);
+			paint_markers(ctx, env, data);
+			return;
+		}
+
 		let left = Point::new(0.0, size.height / 2.0);
 
 		let right = Point::new(size.width, size.height / 2.0);
@@ -82,11 +115,8 @@ impl Widget<Data> for PlayerBar {
 			},
 		);
 
-		let progress = if ctx.is_active() {
-			self.position_preview
-		} else {
-			data.offset.as_secs_f64() / data.track.duration.as_secs_f64()
-		};
+		paint_buffered(ctx, env, data, size.height / 2.0, 6.0);
+
 		let progress_right = Point::new(size.width * progress, size.height / 2.0);
 
 		ctx.stroke_styled(
@@ -99,5 +129,140 @@ impl Widget<Data> for PlayerBar {
 				..Default::default()
 			},
 		);
+
+		paint_markers(ctx, env, data);
+	}
+}
+
+// Loops and cues are set at the position that was clicked
+fn marker_menu(data: &Data, position: Duration) -> Menu<State> {
+	let duration = data.track.duration;
+	let looping = data.looping.clone();
+	let mut menu = Menu::new("Markers")
+		.entry(
+			MenuItem::new("Add cue")
+				.on_activate(move |ctx, _, _| ctx.submit_command(PLAYER_ADD_CUE.with(position))),
+		)
+		.separator()
+		.entry({
+			let end = looping.as_ref().map_or(duration, |looping| looping.end);
+			MenuItem::new("Start loop here").on_activate(move |ctx, _, _| {
+				ctx.submit_command(PLAYER_SET_LOOP.with((position, end)))
+			})
+		})
+		.entry({
+			let start = looping
+				.as_ref()
+				.map_or(Duration::ZERO, |looping| looping.start);
+			MenuItem::new("End loop here").on_activate(move |ctx, _, _| {
+				ctx.submit_command(PLAYER_SET_LOOP.with((start, position)))
+			})
+		});
+	if looping.is_some() {
+		menu = menu.entry(
+			MenuItem::new("Clear loop")
+				.on_activate(|ctx, _, _| ctx.submit_command(PLAYER_CLEAR_LOOP)),
+		);
+	}
+	if !data.track.cues.is_empty() {
+		menu = menu.separator();
+		for n in 0..data.track.cues.len() {
+			menu = menu.entry(
+				MenuItem::new(format!("Jump to cue {}", n + 1))
+					.on_activate(move |ctx, _, _| ctx.submit_command(PLAYER_JUMP_TO_CUE.with(n))),
+			);
+		}
+		menu = menu.entry(
+			MenuItem::new("Clear cues")
+				.on_activate(|ctx, _, _| ctx.submit_command(PLAYER_CLEAR_CUES)),
+		);
+	}
+	menu
+}
+
+// The loop as a band over the bar, and a line at each cue
+fn paint_markers(ctx: &mut PaintCtx, env: &Env, data: &Data) {
+	let duration = data.track.duration.as_secs_f64();
+	if duration == 0.0 {
+		return;
+	}
+	let size = ctx.size();
+	let x = |t: Duration| size.width * (t.as_secs_f64() / duration).min(1.0);
+	if let Some(looping) = &data.looping {
+		ctx.fill(
+			Rect::new(x(looping.start), 0.0, x(looping.end), size.height),
+			&env.get(theme::FOREGROUND).with_alpha(0.15),
+		);
+	}
+	for &cue in &data.track.cues {
+		ctx.stroke(
+			Line::new(Point::new(x(cue), 0.0), Point::new(x(cue), size.height)),
+			&env.get(theme::FOREGROUND),
+			1.0,
+		);
+	}
+}
+
+// The parts of a streamed track that are loaded, as a line at height `y`
+fn paint_buffered(ctx: &mut PaintCtx, env: &Env, data: &Data, y: f64, width: f64) {
+	let duration = data.track.duration.as_secs_f64();
+	if duration == 0.0 {
+		return;
+	}
+	let size = ctx.size();
+	for range in &data.buffered {
+		let x = |t: Duration| size.width * (t.as_secs_f64() / duration).min(1.0);
+		ctx.stroke_styled(
+			Line::new(Point::new(x(range.start), y), Point::new(x(range.end), y)),
+			&env.get(theme::BACKGROUND_HIGHLIGHT1),
+			width,
+			&StrokeStyle {
+				line_join: LineJoin::Round,
+				line_cap: LineCap::Round,
+				..Default::default()
+			},
+		);
+	}
+}
+
+// One line per pixel column from the lowest to the highest sample, with the RMS drawn over it.
+fn paint_waveform(ctx: &mut PaintCtx, env: &Env, waveform: &player::Waveform, progress: f64) {
+	let size = ctx.size();
+	let center = size.height / 2.0;
+	let len = waveform.buckets.len();
+	let columns = size.width.ceil() as usize;
+	for column in 0..columns {
+		let first = column * len / columns;
+		let last = ((column + 1) * len / columns).max(first + 1).min(len);
+		let Some(buckets) = waveform.buckets.get(first..last) else {
+			continue;
+		};
+		let (min, max, rms) = buckets
+			.iter()
+			.fold((0f32, 0f32, 0f32), |(min, max, rms), b| {
+				(min.min(b.min), max.max(b.max), rms.max(b.rms))
+			});
+
+		let x = column as f64 + 0.5;
+		let y = |value: f32| center - value.clamp(-1.0, 1.0) as f64 * center;
+		let played = (column as f64) < progress * size.width;
+		let (peak_color, rms_color) = if played {
+			(theme::ACCENT_DIM, theme::ACCENT)
+		} else {
+			(theme::BACKGROUND_HIGHLIGHT1, theme::FOREGROUND_DIM)
+		};
+		ctx.stroke(
+			Line::new(
+				Point::new(x, y(max)),
+				Point::new(x, y(min).max(y(max) + 1.0)),
+			),
+			&env.get(peak_color),
+			1.0,
+		);
+		ctx.stroke(
+			Line::new(Point::new(x, y(rms)), Point::new(x, y(-rms))),
+			&env.get(rms_color),
+			1.0,
+		);
 	}
 }
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use druid::{
+	im,
 	keyboard_types::Key,
 	lens::{self, Field},
 	widget::{Flex, Label, List, Maybe, SizedBox, TextBox},
@@ -87,6 +88,7 @@ impl Widget<WData> for SearchBar {
 							.map(|name| (rand::random(), name.to_owned()))
 							.collect(),
 						title: track.title,
+						tags: im::HashMap::new(),
 					};
 					ctx.submit_command(
 						command::UI_TRACK_IMPORT_OPEN.with(TrackImport::Single(new_track)),
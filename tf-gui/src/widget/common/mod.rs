@@ -7,4 +7,5 @@ pub mod knob;
 pub mod parse_lazy;
 pub mod separator;
 pub mod smart_list;
+pub mod spectrum;
 pub mod stack;
@@ -0,0 +1,155 @@
+use druid::{kurbo::Rect, piet::RenderContext, widget::prelude::*, TimerToken};
+use tf_player::player::{AnalysisTap, Analyzer};
+
+use crate::theme;
+
+const BANDS: usize = 32;
+const MIN_FREQUENCY: f64 = 40.0;
+const MAX_FREQUENCY: f64 = 16000.0;
+// levels below this are drawn empty, in dB
+const FLOOR: f32 = -72.0;
+// how fast the bars fall back, in dB per refresh
+const FALL: f32 = 3.0;
+const METER_WIDTH: f64 = 6.0;
+
+// Spectrum of what is being played, followed by a VU meter for each channel.
+// The data tells whether something is playing, the levels fall back and the refreshes stop when not.
+pub struct Spectrum {
+	analyzer: Analyzer,
+	timer: TimerToken,
+	bands: [f32; BANDS],
+	rms: [f32; 2],
+	peak: [f32; 2],
+}
+
+impl Spectrum {
+	pub fn new(tap: AnalysisTap) -> Self {
+		Self {
+			analyzer: Analyzer::new(tap, 30.0, 4096),
+			timer: TimerToken::INVALID,
+			bands: [FLOOR; BANDS],
+			rms: [FLOOR; 2],
+			peak: [FLOOR; 2],
+		}
+	}
+
+	/// Set how many times per second the levels are refreshed.
+	pub fn with_rate(mut self, rate: f64) -> Self {
+		self.analyzer.set_rate(rate);
+		self
+	}
+
+	fn refresh(&mut self, playing: bool) {
+		let db = |x: f32| (20.0 * x.max(1e-9).log10()).max(FLOOR);
+		let fall = |shown: f32, level: f32| level.max(shown - FALL);
+
+		let mut levels = [FLOOR; BANDS];
+		let mut rms = [FLOOR; 2];
+		let mut peak = [FLOOR; 2];
+		if playing {
+			let analysis = self.analyzer.analyze();
+			// the bins are grouped into bands of equal width on a log scale
+			for (bin, &magnitude) in analysis.spectrum.iter().enumerate() {
+				let frequency = analysis.bin_frequency(bin);
+				if !(MIN_FREQUENCY..MAX_FREQUENCY).contains(&frequency) {
+					continue;
+				}
+				let band = (frequency / MIN_FREQUENCY).ln() / (MAX_FREQUENCY / MIN_FREQUENCY).ln();
+				let band = &mut levels[(band * BANDS as f64) as usize];
+				*band = band.max(magnitude);
+			}
+			rms = analysis.rms.map(db);
+			peak = analysis.peak.map(db);
+		}
+		for (shown, level) in self.bands.iter_mut().zip(levels) {
+			*shown = fall(*shown, level);
+		}
+		for c in 0..2 {
+			self.rms[c] = fall(self.rms[c], rms[c]);
+			self.peak[c] = fall(self.peak[c], peak[c]);
+		}
+	}
+
+	// Whether the levels have all fallen back to the floor
+	fn is_idle(&self) -> bool {
+		self.bands
+			.iter()
+			.chain(&self.rms)
+			.chain(&self.peak)
+			.all(|&level| level <= FLOOR)
+	}
+}
+
+// Height of a level in dB, from 0 to 1
+fn height(level: f32) -> f64 {
+	((level - FLOOR) / -FLOOR).clamp(0.0, 1.0) as f64
+}
+
+impl Widget<bool> for Spectrum {
+	fn event(&mut self, ctx: &mut EventCtx, event: &Event, playing: &mut bool, _env: &Env) {
+		if let Event::Timer(timer) = event {
+			if *timer == self.timer {
+				self.refresh(*playing);
+				ctx.request_paint();
+				self.timer = if *playing || !self.is_idle() {
+					ctx.request_timer(self.analyzer.interval())
+				} else {
+					TimerToken::INVALID
+				};
+			}
+		}
+	}
+
+	fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, playing: &bool, _env: &Env) {
+		if let LifeCycle::WidgetAdded = event {
+			if *playing {
+				self.timer = ctx.request_timer(self.analyzer.interval());
+			}
+		}
+	}
+
+	fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &bool, playing: &bool, _env: &Env) {
+		if *playing && self.timer == TimerToken::INVALID {
+			self.timer = ctx.request_timer(self.analyzer.interval());
+		}
+	}
+
+	fn layout(
+		&mut self,
+		_ctx: &mut LayoutCtx,
+		bc: &BoxConstraints,
+		_data: &bool,
+		_env: &Env,
+	) -> Size {
+		bc.constrain(Size::new(200.0, 48.0))
+	}
+
+	fn paint(&mut self, ctx: &mut PaintCtx, _data: &bool, env: &Env) {
+		let size = ctx.size();
+		let meters = 2.0 * (METER_WIDTH + 2.0);
+		ctx.fill(size.to_rect(), &env.get(theme::BACKGROUND_HIGHLIGHT0));
+
+		let band_width = (size.width - meters) / BANDS as f64;
+		for (i, &level) in self.bands.iter().enumerate() {
+			let x = i as f64 * band_width;
+			let top = size.height * (1.0 - height(level));
+			let bar = Rect::new(x + 1.0, top, x + band_width - 1.0, size.height);
+			ctx.fill(bar, &env.get(theme::ACCENT));
+		}
+
+		for c in 0..2 {
+			let x = size.width - meters + 2.0 + c as f64 * (METER_WIDTH + 2.0);
+			let rms = size.height * (1.0 - height(self.rms[c]));
+			let meter = Rect::new(x, rms, x + METER_WIDTH, size.height);
+			ctx.fill(meter, &env.get(theme::THEME_BLUE));
+			let peak = size.height * (1.0 - height(self.peak[c]));
+			let peak = Rect::new(x, peak, x + METER_WIDTH, peak + 1.0);
+			let color = if self.peak[c] >= 0.0 {
+				theme::THEME_RED
+			} else {
+				theme::FOREGROUND_DIM
+			};
+			ctx.fill(peak, &env.get(color));
+		}
+	}
+}
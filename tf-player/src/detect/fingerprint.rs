@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+// Like chromaprint, the chroma of overlapping windows is turned into 32 bits every hop
+const WINDOW: f64 = 0.37;
+const HOP: f64 = 0.125;
+const MIN_FREQUENCY: f64 = 28.0;
+const MAX_FREQUENCY: f64 = 3520.0;
+// The start of a track is enough to recognize it
+const MAX_HASHES: usize = (120.0 / HOP) as usize;
+// Copies can start a little earlier or later, after a longer or shorter silence or intro
+const MAX_OFFSET: usize = (10.0 / HOP) as usize;
+const MIN_OVERLAP: usize = (10.0 / HOP) as usize;
+// Unrelated tracks have about half of their bits in common, giving a similarity near 0
+pub const MATCH_SIMILARITY: f32 = 0.6;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fingerprint {
+	pub hashes: Vec<u32>,
+}
+
+impl Fingerprint {
+	// 1 for the same audio, 0 for unrelated audio, at the offset where they match best
+	pub fn similarity(&self, other: &Self) -> f32 {
+		let (a, b) = (&self.hashes, &other.hashes);
+		let mut best = 0.0f32;
+		for offset in -(MAX_OFFSET as isize)..=MAX_OFFSET as isize {
+			let (a, b) = if offset >= 0 {
+				(&a[..], b.get(offset as usize..).unwrap_or_default())
+			} else {
+				(a.get(-offset as usize..).unwrap_or_default(), &b[..])
+			};
+			let len = a.len().min(b.len());
+			if len < MIN_OVERLAP {
+				continue;
+			}
+			let errors = a
+				.iter()
+				.zip(b)
+				.map(|(a, b)| (a ^ b).count_ones())
+				.sum::<u32>();
+			let error_rate = errors as f32 / (32 * len) as f32;
+			best = best.max(1.0 - 2.0 * error_rate);
+		}
+		best
+	}
+
+	// The candidates with the same audio, best first
+	pub fn find_matches<'a, K>(
+		&self,
+		candidates: impl IntoIterator<Item = (K, &'a Fingerprint)>,
+	) -> Vec<(K, f32)> {
+		let mut matches = candidates
+			.into_iter()
+			.map(|(key, candidate)| (key, self.similarity(candidate)))
+			.filter(|(_, similarity)| *similarity >= MATCH_SIMILARITY)
+			.collect::<Vec<_>>();
+		matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+		matches
+	}
+}
+
+pub struct FingerprintBuilder {
+	hop_len: usize,
+	fft: Arc<dyn RealToComplex<f32>>,
+	window: Vec<f32>,
+	pending: Vec<f32>,
+	input: Vec<f32>,
+	output: Vec<Complex<f32>>,
+	bins: Vec<Option<(usize, f32)>>,
+	// the last chroma vectors, the hashes compare them with each other
+	chromas: Vec<[f32; 12]>,
+	hashes: Vec<u32>,
+}
+
+impl FingerprintBuilder {
+	pub fn new(sample_rate: f64) -> Self {
+		let window_len = (sample_rate * WINDOW) as usize;
+		let fft = RealFftPlanner::new().plan_fft_forward(window_len);
+		let bins = (0..fft.complex_len())
+			.map(|bin| {
+				let frequency = bin as f64 * sample_rate / window_len as f64;
+				if !(MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+					return None;
+				}
+				let pitch = 12.0 * (frequency / 440.0).log2() + 9.0;
+				let class = pitch.round().rem_euclid(12.0) as usize;
+				let weight = 1.0 - 2.0 * (pitch - pitch.round()).abs();
+				Some((class, weight as f32))
+			})
+			.collect();
+		Self {
+			hop_len: (sample_rate * HOP) as usize,
+			window: (0..window_len)
+				.map(|i| {
+					0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / window_len as f32).cos()
+				})
+				.collect(),
+			pending: vec![],
+			input: fft.make_input_vec(),
+			output: fft.make_output_vec(),
+			fft,
+			bins,
+			chromas: vec![],
+			hashes: vec![],
+		}
+	}
+
+	pub fn process(&mut self, frames: &[[f32; 2]]) {
+		if self.hashes.len() >= MAX_HASHES {
+			return;
+		}
+		self.pending
+			.extend(frames.iter().map(|frame| (frame[0] + frame[1]) / 2.0));
+		let window_len = self.window.len();
+		let mut start = 0;
+		while self.pending.len() - start >= window_len && self.hashes.len() < MAX_HASHES {
+			for (i, sample) in self.input.iter_mut().enumerate() {
+				*sample = self.pending[start + i] * self.window[i];
+			}
+			self.fft.process(&mut self.input, &mut self.output).ok();
+			let mut chroma = [0.0f32; 12];
+			for (bin, class) in self.output.iter().zip(&self.bins) {
+				if let Some((class, weight)) = class {
+					chroma[*class] += bin.norm_sqr() * weight;
+				}
+			}
+			// the level doesn't matter, only how the pitch classes compare
+			let norm = chroma.iter().map(|c| c * c).sum::<f32>().sqrt();
+			if norm > 1e-9 {
+				chroma.iter_mut().for_each(|c| *c /= norm);
+			}
+			self.push_chroma(chroma);
+			start += self.hop_len;
+		}
+		self.pending.drain(..start);
+	}
+
+	fn push_chroma(&mut self, chroma: [f32; 12]) {
+		const HISTORY: usize = 3;
+		self.chromas.push(chroma);
+		if self.chromas.len() > HISTORY {
+			self.chromas.remove(0);
+		}
+		if self.chromas.len() < HISTORY {
+			return;
+		}
+		let (now, before) = (&self.chromas[2], &self.chromas[0]);
+		let mut hash = 0u32;
+		for i in 0..12 {
+			// how the neighbouring pitch classes compare, and which ones rose
+			hash |= ((now[i] > now[(i + 1) % 12]) as u32) << i;
+			hash |= ((now[i] > before[i]) as u32) << (12 + i);
+		}
+		for i in 0..8 {
+			// pairs of pitch classes, against the next pair
+			let pair = now[i] + now[(i + 1) % 12];
+			let next = now[(i + 2) % 12] + now[(i + 3) % 12];
+			hash |= ((pair > next) as u32) << (24 + i);
+		}
+		self.hashes.push(hash);
+	}
+
+	// Nothing more is added past the start of the track
+	pub fn is_complete(&self) -> bool {
+		self.hashes.len() >= MAX_HASHES
+	}
+
+	pub fn build(self) -> Option<Fingerprint> {
+		(self.hashes.len() >= MIN_OVERLAP).then_some(Fingerprint {
+			hashes: self.hashes,
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	// A second of each chord, its notes picked from `seed`, as semitones from A4
+	fn song(seed: u32, sample_rate: f32, silence: f32, gain: f32) -> Vec<[f32; 2]> {
+		let mut state = seed;
+		let chords = (0..40)
+			.map(|_| {
+				[0; 3].map(|_| {
+					state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+					(state >> 16) as i32 % 24 - 12
+				})
+			})
+			.collect::<Vec<_>>();
+		let start = (silence * sample_rate) as usize;
+		let len = start + chords.len() * sample_rate as usize;
+		(0..len)
+			.map(|i| {
+				let Some(i) = i.checked_sub(start) else {
+					return [0.0; 2];
+				};
+				let t = i as f32 / sample_rate;
+				let s = chords[i / sample_rate as usize]
+					.iter()
+					.map(|&note| {
+						let frequency = 440.0 * 2f32.powf(note as f32 / 12.0);
+						(2.0 * std::f32::consts::PI * frequency * t).sin()
+					})
+					.sum::<f32>() * 0.2
+					* gain;
+				[s, s]
+			})
+			.collect()
+	}
+
+	fn fingerprint(audio: &[[f32; 2]], sample_rate: f64) -> Fingerprint {
+		let mut builder = FingerprintBuilder::new(sample_rate);
+		for chunk in audio.chunks(1000) {
+			builder.process(chunk);
+		}
+		builder.build().unwrap()
+	}
+
+	#[test]
+	fn test_similarity() {
+		let original = fingerprint(&song(1, 44100.0, 0.0, 1.0), 44100.0);
+		// another source, at another rate and level, after a silence
+		let copy = fingerprint(&song(1, 48000.0, 2.3, 0.5), 48000.0);
+		let other = fingerprint(&song(2, 44100.0, 0.0, 1.0), 44100.0);
+
+		assert_eq!(original.similarity(&original), 1.0);
+		let same = original.similarity(&copy);
+		assert!(same > MATCH_SIMILARITY, "{same}");
+		assert!(copy.similarity(&original) > MATCH_SIMILARITY);
+		let different = original.similarity(&other);
+		assert!(different < 0.3, "{different}");
+
+		let matches = original.find_matches([("other", &other), ("copy", &copy)]);
+		assert_eq!(matches.len(), 1);
+		assert_eq!(matches[0].0, "copy");
+
+		// too short to tell
+		let mut builder = FingerprintBuilder::new(44100.0);
+		builder.process(&song(1, 44100.0, 0.0, 1.0)[..44100 * 5]);
+		assert_eq!(builder.build(), None);
+	}
+}
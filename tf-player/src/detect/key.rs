@@ -0,0 +1,212 @@
+use std::{fmt, sync::Arc};
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+// About 0.2s, enough to tell the semitones apart in the bass
+const WINDOW: f64 = 0.17;
+// The pitches that are counted, from C2 to C7
+const MIN_FREQUENCY: f64 = 65.4;
+const MAX_FREQUENCY: f64 = 2093.0;
+// How much each pitch class is heard in the major and minor keys, from Krumhansl and Kessler
+const MAJOR: [f32; 12] = [
+	6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR: [f32; 12] = [
+	6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+const NAMES: [&str; 12] = [
+	"C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+	Major,
+	Minor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Key {
+	/// Pitch class of the tonic, 0 is C
+	pub tonic: u8,
+	pub mode: Mode,
+}
+
+impl fmt::Display for Key {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mode = match self.mode {
+			Mode::Major => "major",
+			Mode::Minor => "minor",
+		};
+		write!(f, "{} {mode}", NAMES[self.tonic as usize % 12])
+	}
+}
+
+// Estimates the key from the chroma of the whole track, how much of each pitch class is heard,
+// which is compared to the profile of every key.
+pub struct KeyDetector {
+	fft: Arc<dyn RealToComplex<f32>>,
+	window: Vec<f32>,
+	pending: Vec<f32>,
+	input: Vec<f32>,
+	output: Vec<Complex<f32>>,
+	// the pitch class of each frequency bin, and how close to it the bin is
+	bins: Vec<Option<(usize, f32)>>,
+	chroma: [f64; 12],
+}
+
+impl KeyDetector {
+	pub fn new(sample_rate: f64) -> Self {
+		let window_len = ((sample_rate * WINDOW) as usize).next_power_of_two();
+		let fft = RealFftPlanner::new().plan_fft_forward(window_len);
+		let bins = (0..fft.complex_len())
+			.map(|bin| {
+				let frequency = bin as f64 * sample_rate / window_len as f64;
+				if !(MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency) {
+					return None;
+				}
+				// semitones from C
+				let pitch = 12.0 * (frequency / 440.0).log2() + 9.0;
+				let class = pitch.round().rem_euclid(12.0) as usize;
+				let weight = 1.0 - 2.0 * (pitch - pitch.round()).abs();
+				Some((class, weight as f32))
+			})
+			.collect();
+		Self {
+			window: (0..window_len)
+				.map(|i| {
+					0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / window_len as f32).cos()
+				})
+				.collect(),
+			pending: vec![],
+			input: fft.make_input_vec(),
+			output: fft.make_output_vec(),
+			fft,
+			bins,
+			chroma: [0.0; 12],
+		}
+	}
+
+	pub fn process(&mut self, frames: &[[f32; 2]]) {
+		self.pending
+			.extend(frames.iter().map(|frame| (frame[0] + frame[1]) / 2.0));
+		let window_len = self.window.len();
+		let mut start = 0;
+		while self.pending.len() - start >= window_len {
+			for (i, sample) in self.input.iter_mut().enumerate() {
+				*sample = self.pending[start + i] * self.window[i];
+			}
+			self.fft.process(&mut self.input, &mut self.output).ok();
+			for (bin, class) in self.output.iter().zip(&self.bins) {
+				if let Some((class, weight)) = class {
+					self.chroma[*class] += (bin.norm() * weight) as f64;
+				}
+			}
+			start += window_len / 2;
+		}
+		self.pending.drain(..start);
+	}
+
+	// The best key, with how well the chroma matches it from 0 to 1
+	pub fn key(&self) -> Option<(Key, f32)> {
+		if self.chroma.iter().sum::<f64>() <= 0.0 {
+			return None;
+		}
+		let chroma = self.chroma.map(|c| c as f32);
+		(0..12)
+			.flat_map(|tonic| [(tonic, Mode::Major), (tonic, Mode::Minor)])
+			.map(|(tonic, mode)| {
+				let profile = match mode {
+					Mode::Major => &MAJOR,
+					Mode::Minor => &MINOR,
+				};
+				let rotated = (0..12)
+					.map(|i| profile[(i + 12 - tonic as usize) % 12])
+					.collect::<Vec<_>>();
+				(Key { tonic, mode }, correlation(&chroma, &rotated))
+			})
+			.max_by(|a, b| a.1.total_cmp(&b.1))
+			.map(|(key, r)| (key, r.max(0.0)))
+	}
+}
+
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+	let mean = |x: &[f32]| x.iter().sum::<f32>() / x.len() as f32;
+	let (mean_a, mean_b) = (mean(a), mean(b));
+	let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+	for (a, b) in a.iter().zip(b) {
+		cov += (a - mean_a) * (b - mean_b);
+		var_a += (a - mean_a) * (a - mean_a);
+		var_b += (b - mean_b) * (b - mean_b);
+	}
+	if var_a == 0.0 || var_b == 0.0 {
+		return 0.0;
+	}
+	cov / (var_a * var_b).sqrt()
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	// Each note is a tone with its first harmonics, given as semitones from A4
+	fn chords(chords: &[&[(i32, f32)]], sample_rate: f32) -> Vec<[f32; 2]> {
+		let chord_len = sample_rate as usize;
+		(0..chords.len() * chord_len)
+			.map(|i| {
+				let t = i as f32 / sample_rate;
+				let s = chords[i / chord_len]
+					.iter()
+					.map(|&(note, level)| {
+						let frequency = 440.0 * 2f32.powf(note as f32 / 12.0);
+						(1..=3)
+							.map(|harmonic| {
+								let phase = 2.0 * std::f32::consts::PI * frequency * t;
+								level * (phase * harmonic as f32).sin() / harmonic as f32
+							})
+							.sum::<f32>()
+					})
+					.sum::<f32>() * 0.1;
+				[s, s]
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_key() {
+		// i iv V i in A minor, with the E major chord of the harmonic minor
+		let a_minor = chords(
+			&[
+				&[(-12, 1.0), (-9, 0.7), (-5, 0.7)],
+				&[(-7, 1.0), (-4, 0.7), (0, 0.7)],
+				&[(-5, 1.0), (-1, 0.7), (2, 0.7)],
+				&[(-12, 1.0), (-9, 0.7), (-5, 0.7)],
+			],
+			44100.0,
+		);
+		// I IV V I in D major
+		let d_major = chords(
+			&[
+				&[(-7, 1.0), (-3, 0.7), (0, 0.7)],
+				&[(-2, 1.0), (2, 0.7), (5, 0.7)],
+				&[(0, 1.0), (4, 0.7), (7, 0.7)],
+				&[(-7, 1.0), (-3, 0.7), (0, 0.7)],
+			],
+			48000.0,
+		);
+		for (audio, sample_rate, expected) in
+			[(a_minor, 44100.0, "A minor"), (d_major, 48000.0, "D major")]
+		{
+			let mut detector = KeyDetector::new(sample_rate);
+			for chunk in audio.chunks(1000) {
+				detector.process(chunk);
+			}
+			let (key, confidence) = detector.key().unwrap();
+			assert_eq!(key.to_string(), expected);
+			assert!(confidence > 0.5, "{confidence}");
+		}
+
+		let mut detector = KeyDetector::new(44100.0);
+		detector.process(&[[0.0; 2]; 44100]);
+		assert_eq!(detector.key(), None);
+	}
+}
@@ -0,0 +1,169 @@
+use std::{ops::Range, time::Duration};
+
+use crossbeam_channel::{Receiver, Sender};
+use tracing::debug;
+
+use crate::{player::Resolve, Source, SourceError};
+
+mod fingerprint;
+pub use fingerprint::{Fingerprint, FingerprintBuilder, MATCH_SIMILARITY};
+
+mod key;
+pub use key::{Key, KeyDetector, Mode};
+
+mod silence;
+pub use silence::SilenceDetector;
+
+mod tempo;
+pub use tempo::{tempo_tag_value, TempoDetector, TEMPO_TAG};
+
+// Long mixes don't have a single tempo or key anyway
+const MAX_DURATION: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Detection {
+	pub bpm: Option<f32>,
+	/// The key, with how confident the detection is from 0 to 1
+	pub key: Option<(Key, f32)>,
+	pub fingerprint: Option<Fingerprint>,
+	/// The part of the track between its leading and trailing silence
+	pub audible: Option<Range<Duration>>,
+}
+
+// Reads the whole source, the tempo and key are only detected up to `MAX_DURATION`
+pub fn detect(source: &mut dyn Source, sample_rate: f64) -> Result<Detection, SourceError> {
+	let mut tempo = TempoDetector::new(sample_rate);
+	let mut key = KeyDetector::new(sample_rate);
+	let mut fingerprint = FingerprintBuilder::new(sample_rate);
+	let mut silence = SilenceDetector::new(sample_rate);
+	let mut buf = vec![[0.0; 2]; 4096];
+	let mut remaining = (MAX_DURATION.as_secs_f64() * sample_rate) as usize;
+	loop {
+		let n = match source.next(&mut buf) {
+			Err(SourceError::EndOfStream) => 0,
+			r => r?,
+		};
+		let analyzed = n.min(remaining);
+		tempo.process(&buf[..analyzed]);
+		key.process(&buf[..analyzed]);
+		fingerprint.process(&buf[..analyzed]);
+		silence.process(&buf[..n]);
+		remaining -= analyzed;
+		if n < buf.len() {
+			break;
+		}
+	}
+	Ok(Detection {
+		bpm: tempo.bpm(),
+		key: key.key(),
+		fingerprint: fingerprint.build(),
+		audible: silence.audible(),
+	})
+}
+
+// The key a track was pushed with, and what was detected
+pub type Detected = (String, anyhow::Result<Detection>);
+
+// Runs the detections one after the other on a background thread, in the order they were pushed.
+// The thread stops once the queue is dropped.
+pub struct DetectionQueue {
+	jobs: Sender<(String, Resolve)>,
+}
+
+impl DetectionQueue {
+	pub fn spawn() -> anyhow::Result<(Self, Receiver<Detected>)> {
+		let (jobs, job_receiver) = crossbeam_channel::unbounded::<(String, Resolve)>();
+		let (result_sender, results) = crossbeam_channel::unbounded();
+		std::thread::Builder::new()
+			.name("detection".to_owned())
+			.spawn(move || {
+				for (key, resolve) in job_receiver {
+					debug!("detecting the tempo and key of {key}");
+					let detection = resolve().and_then(|mut source| {
+						Ok(detect(&mut *source.signal, source.sample_rate)?)
+					});
+					if result_sender.send((key, detection)).is_err() {
+						break;
+					}
+				}
+			})?;
+		Ok((Self { jobs }, results))
+	}
+
+	pub fn push(&self, key: String, resolve: Resolve) {
+		self.jobs.send((key, resolve)).ok();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::{TrackInfo, TrackSource};
+
+	// A 440Hz tone that starts over on every beat of 100 BPM
+	struct Beats {
+		position: usize,
+		len: usize,
+	}
+
+	impl Source for Beats {
+		fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+			self.position = ((pos.as_secs_f64() * 44100.0).round() as usize).min(self.len);
+			Ok(Duration::from_secs_f64(self.position as f64 / 44100.0))
+		}
+
+		fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+			let n = buf.len().min(self.len - self.position);
+			for (i, frame) in buf[..n].iter_mut().enumerate() {
+				let t = (self.position + i) as f32 / 44100.0;
+				let since_beat = t % 0.6;
+				let s = 0.5
+					* (-since_beat * 10.0).exp()
+					* (2.0 * std::f32::consts::PI * 440.0 * t).sin();
+				*frame = [s, s];
+			}
+			self.position += n;
+			Ok(n)
+		}
+	}
+
+	#[test]
+	fn test_queue() {
+		let (queue, results) = DetectionQueue::spawn().unwrap();
+		queue.push(
+			"beats".to_owned(),
+			Box::new(|| {
+				Ok(TrackSource {
+					sample_rate: 44100.0,
+					signal: Box::new(Beats {
+						position: 0,
+						len: 44100 * 15,
+					}),
+					info: TrackInfo::default(),
+				})
+			}),
+		);
+		queue.push(
+			"missing".to_owned(),
+			Box::new(|| Err(anyhow::anyhow!("no such track"))),
+		);
+
+		let (key, detection) = results.recv().unwrap();
+		assert_eq!(key, "beats");
+		let detection = detection.unwrap();
+		assert!(
+			(detection.bpm.unwrap() - 100.0).abs() < 0.5,
+			"{detection:?}"
+		);
+		// a lone A is heard as the tonic
+		assert_eq!(detection.key.unwrap().0.tonic, 9);
+		assert!(detection.fingerprint.is_some());
+		assert_eq!(
+			detection.audible,
+			Some(Duration::ZERO..Duration::from_secs(15))
+		);
+		let (key, detection) = results.recv().unwrap();
+		assert_eq!(key, "missing");
+		assert!(detection.is_err());
+	}
+}
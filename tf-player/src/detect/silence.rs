@@ -0,0 +1,107 @@
+use std::{ops::Range, time::Duration};
+
+// Blocks of 10ms whose peak stays under -50dBFS are silent
+const BLOCK: f64 = 0.01;
+const THRESHOLD: f32 = 0.003;
+// Shorter silences are part of the track, like a pause before the first note
+const MIN_SILENCE: f64 = 0.5;
+
+// Finds where the audio starts and ends, leaving out the silence around it
+pub struct SilenceDetector {
+	sample_rate: f64,
+	block_len: usize,
+	position: usize,
+	// the peak of the block being read
+	peak: f32,
+	// the first and last frames of the audible blocks
+	audible: Option<Range<usize>>,
+}
+
+impl SilenceDetector {
+	pub fn new(sample_rate: f64) -> Self {
+		Self {
+			sample_rate,
+			block_len: ((sample_rate * BLOCK) as usize).max(1),
+			position: 0,
+			peak: 0.0,
+			audible: None,
+		}
+	}
+
+	pub fn process(&mut self, frames: &[[f32; 2]]) {
+		for frame in frames {
+			self.peak = self.peak.max(frame[0].abs()).max(frame[1].abs());
+			self.position += 1;
+			if self.position % self.block_len == 0 {
+				self.close_block();
+			}
+		}
+	}
+
+	fn close_block(&mut self) {
+		if self.peak > THRESHOLD {
+			let block = (self.position - 1) / self.block_len * self.block_len..self.position;
+			self.audible = Some(match &self.audible {
+				Some(audible) => audible.start..block.end,
+				None => block,
+			});
+		}
+		self.peak = 0.0;
+	}
+
+	/// The audible part of what was read, None if it is all silent. The silence at either end is
+	/// only left out when it is long enough.
+	pub fn audible(mut self) -> Option<Range<Duration>> {
+		if self.position % self.block_len != 0 {
+			self.close_block();
+		}
+		let audible = self.audible?;
+		let min_silence = (MIN_SILENCE * self.sample_rate) as usize;
+		let start = if audible.start >= min_silence {
+			audible.start
+		} else {
+			0
+		};
+		let end = if self.position - audible.end >= min_silence {
+			audible.end
+		} else {
+			self.position
+		};
+		let time = |frames: usize| Duration::from_secs_f64(frames as f64 / self.sample_rate);
+		Some(time(start)..time(end))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_silence() {
+		let mut detector = SilenceDetector::new(1000.0);
+		detector.process(&vec![[0.001, -0.001]; 3000]);
+		detector.process(&vec![[0.5, -0.5]; 5000]);
+		// a short pause in the middle is kept
+		detector.process(&vec![[0.0; 2]; 1000]);
+		detector.process(&vec![[0.5, -0.5]; 1000]);
+		detector.process(&vec![[0.0; 2]; 2345]);
+		assert_eq!(
+			detector.audible(),
+			Some(Duration::from_secs(3)..Duration::from_secs(10))
+		);
+
+		// too short to be left out
+		let mut detector = SilenceDetector::new(1000.0);
+		detector.process(&vec![[0.0; 2]; 200]);
+		detector.process(&vec![[0.5; 2]; 1000]);
+		detector.process(&vec![[0.0; 2]; 300]);
+		assert_eq!(
+			detector.audible(),
+			Some(Duration::ZERO..Duration::from_millis(1500))
+		);
+
+		let mut detector = SilenceDetector::new(1000.0);
+		detector.process(&vec![[0.0; 2]; 1000]);
+		assert_eq!(detector.audible(), None);
+	}
+}
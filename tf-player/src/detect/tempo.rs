@@ -0,0 +1,184 @@
+use std::sync::Arc;
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+// The onset envelope has a value every 10ms, taken over 40ms of audio
+const HOP: f64 = 0.01;
+const WINDOW_HOPS: usize = 4;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+// The beat is heard most readily around 120 BPM, this picks which multiple of it is reported.
+// The weight of a tempo falls with its distance to it, in octaves.
+const PREFERRED_BPM: f32 = 120.0;
+const PREFERENCE_WIDTH: f32 = 1.0;
+// Shorter envelopes don't hold enough beats to tell
+const MIN_HOPS: usize = 500;
+
+/// The tag the tempo of a track is kept in
+pub const TEMPO_TAG: &str = "tempo";
+
+/// The tempo as a tag value, tags go from 0 to 1 over the range of the tempos that are detected.
+pub fn tempo_tag_value(bpm: f32) -> f32 {
+	((bpm - MIN_BPM) / (MAX_BPM - MIN_BPM)).clamp(0.0, 1.0)
+}
+
+// Estimates the tempo from the autocorrelation of the onset envelope, the sum of the rises of the
+// log spectrum from one window to the next.
+pub struct TempoDetector {
+	hop_len: usize,
+	fps: f32,
+	fft: Arc<dyn RealToComplex<f32>>,
+	window: Vec<f32>,
+	pending: Vec<f32>,
+	input: Vec<f32>,
+	output: Vec<Complex<f32>>,
+	previous: Vec<f32>,
+	envelope: Vec<f32>,
+}
+
+impl TempoDetector {
+	pub fn new(sample_rate: f64) -> Self {
+		let hop_len = ((sample_rate * HOP).round() as usize).max(1);
+		let window_len = hop_len * WINDOW_HOPS;
+		let fft = RealFftPlanner::new().plan_fft_forward(window_len);
+		Self {
+			hop_len,
+			fps: (sample_rate / hop_len as f64) as f32,
+			window: (0..window_len)
+				.map(|i| {
+					0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / window_len as f32).cos()
+				})
+				.collect(),
+			pending: vec![],
+			input: fft.make_input_vec(),
+			previous: vec![0.0; fft.complex_len()],
+			output: fft.make_output_vec(),
+			fft,
+			envelope: vec![],
+		}
+	}
+
+	pub fn process(&mut self, frames: &[[f32; 2]]) {
+		self.pending
+			.extend(frames.iter().map(|frame| (frame[0] + frame[1]) / 2.0));
+		let window_len = self.window.len();
+		let mut start = 0;
+		while self.pending.len() - start >= window_len {
+			for (i, sample) in self.input.iter_mut().enumerate() {
+				*sample = self.pending[start + i] * self.window[i];
+			}
+			self.fft.process(&mut self.input, &mut self.output).ok();
+			let mut flux = 0.0;
+			for (bin, previous) in self.output.iter().zip(&mut self.previous) {
+				let magnitude = (1.0 + 100.0 * bin.norm()).ln();
+				flux += (magnitude - *previous).max(0.0);
+				*previous = magnitude;
+			}
+			self.envelope.push(flux);
+			start += self.hop_len;
+		}
+		self.pending.drain(..start);
+	}
+
+	pub fn bpm(&self) -> Option<f32> {
+		if self.envelope.len() < MIN_HOPS {
+			return None;
+		}
+		// only the rises above the local average are onsets
+		const AVERAGE_LEN: usize = 16;
+		let onsets = (0..self.envelope.len())
+			.map(|i| {
+				let around = &self.envelope
+					[i.saturating_sub(AVERAGE_LEN)..(i + AVERAGE_LEN).min(self.envelope.len())];
+				let average = around.iter().sum::<f32>() / around.len() as f32;
+				(self.envelope[i] - average).max(0.0)
+			})
+			.collect::<Vec<_>>();
+
+		let min_lag = (60.0 * self.fps / MAX_BPM).floor() as usize;
+		let max_lag = (60.0 * self.fps / MIN_BPM).ceil() as usize;
+		let correlation = |lag: usize| {
+			onsets
+				.iter()
+				.zip(&onsets[lag..])
+				.map(|(a, b)| a * b)
+				.sum::<f32>()
+				/ (onsets.len() - lag) as f32
+		};
+		let correlations = (min_lag - 1..=max_lag + 1)
+			.map(correlation)
+			.collect::<Vec<_>>();
+		let weighted = |i: usize| {
+			let bpm = 60.0 * self.fps / (min_lag - 1 + i) as f32;
+			let octaves = (bpm / PREFERRED_BPM).log2() / PREFERENCE_WIDTH;
+			correlations[i] * (-0.5 * octaves * octaves).exp()
+		};
+		let best =
+			(1..correlations.len() - 1).max_by(|&a, &b| weighted(a).total_cmp(&weighted(b)))?;
+		if correlations[best] <= 0.0 {
+			return None;
+		}
+
+		// the peak is placed between the lags from the ones around it
+		let (before, at, after) = (
+			correlations[best - 1],
+			correlations[best],
+			correlations[best + 1],
+		);
+		let curvature = before - 2.0 * at + after;
+		let offset = if curvature < 0.0 {
+			(0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+		} else {
+			0.0
+		};
+		let lag = (min_lag - 1 + best) as f32 + offset;
+		Some(60.0 * self.fps / lag)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	// Short 1kHz blips on every beat, over a quieter low tone
+	fn clicks(bpm: f32, sample_rate: f32, len: usize) -> Vec<[f32; 2]> {
+		let period = 60.0 / bpm;
+		(0..len)
+			.map(|i| {
+				let t = i as f32 / sample_rate;
+				let since_beat = t % period;
+				let click =
+					(-since_beat * 200.0).exp() * (2.0 * std::f32::consts::PI * 1000.0 * t).sin();
+				let tone = 0.1 * (2.0 * std::f32::consts::PI * 110.0 * t).sin();
+				let s = 0.5 * click + tone;
+				[s, s]
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_bpm() {
+		for (bpm, sample_rate) in [(120.0, 44100.0), (93.0, 48000.0), (140.0, 44100.0)] {
+			let mut detector = TempoDetector::new(sample_rate as f64);
+			let audio = clicks(bpm, sample_rate, 20 * sample_rate as usize);
+			for chunk in audio.chunks(1000) {
+				detector.process(chunk);
+			}
+			let detected = detector.bpm().unwrap();
+			assert!((detected - bpm).abs() < 0.5, "{bpm}: {detected}");
+		}
+
+		// not enough to tell
+		let mut detector = TempoDetector::new(44100.0);
+		detector.process(&clicks(120.0, 44100.0, 44100));
+		assert_eq!(detector.bpm(), None);
+	}
+
+	#[test]
+	fn test_tag_value() {
+		assert_eq!(tempo_tag_value(60.0), 0.0);
+		assert_eq!(tempo_tag_value(200.0), 1.0);
+		assert_eq!(tempo_tag_value(300.0), 1.0);
+		assert_eq!(tempo_tag_value(130.0), 0.5);
+	}
+}
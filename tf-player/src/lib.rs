@@ -1,3 +1,4 @@
+pub mod detect;
 pub mod player;
 pub mod util;
 
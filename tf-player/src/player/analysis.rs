@@ -0,0 +1,261 @@
+use std::{
+	sync::{
+		atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
+};
+
+use realfft::{num_complex::Complex, RealFftPlanner, RealToComplex};
+
+// Frames kept for the readers, more than the output buffer plus an analysis window
+const HISTORY: usize = 1 << 16;
+
+// Lets other threads look at what is being played without ever blocking the decoder.
+// The frames written to the output are copied into a ring of atomics, along with which of them
+// was being played at the time, so readers can work on the frames that are audible right now
+// rather than on those that are still waiting in the output buffer.
+#[derive(Clone)]
+pub struct AnalysisTap {
+	shared: Arc<Shared>,
+}
+
+struct Shared {
+	samples: Box<[[AtomicU32; 2]]>,
+	sample_rate: AtomicU64,
+	written: AtomicU64,
+	// the frame that was being played at `clock`, the output moves on from there while running
+	played: AtomicU64,
+	clock: AtomicU64,
+	running: AtomicBool,
+	start: Instant,
+}
+
+impl AnalysisTap {
+	pub(crate) fn new() -> Self {
+		Self {
+			shared: Arc::new(Shared {
+				samples: (0..HISTORY)
+					.map(|_| [AtomicU32::new(0), AtomicU32::new(0)])
+					.collect(),
+				sample_rate: AtomicU64::new(48000f64.to_bits()),
+				written: AtomicU64::new(0),
+				played: AtomicU64::new(0),
+				clock: AtomicU64::new(0),
+				running: AtomicBool::new(false),
+				start: Instant::now(),
+			}),
+		}
+	}
+
+	pub fn sample_rate(&self) -> f64 {
+		f64::from_bits(self.shared.sample_rate.load(Ordering::Relaxed))
+	}
+
+	pub(crate) fn set_sample_rate(&self, sample_rate: f64) {
+		self.shared
+			.sample_rate
+			.store(sample_rate.to_bits(), Ordering::Relaxed);
+	}
+
+	// Called with the frames written to the output, and how many were still buffered before them.
+	pub(crate) fn write(&self, frames: &[[f32; 2]], buffered: usize) {
+		let shared = &self.shared;
+		let written = shared.written.load(Ordering::Relaxed);
+		self.set_played(written.saturating_sub(buffered as u64));
+		for (i, frame) in frames.iter().enumerate() {
+			let slot = &shared.samples[(written as usize + i) % HISTORY];
+			slot[0].store(frame[0].to_bits(), Ordering::Relaxed);
+			slot[1].store(frame[1].to_bits(), Ordering::Relaxed);
+		}
+		shared
+			.written
+			.store(written + frames.len() as u64, Ordering::Release);
+	}
+
+	pub(crate) fn set_running(&self, running: bool) {
+		self.set_played(self.position());
+		self.shared.running.store(running, Ordering::Relaxed);
+	}
+
+	fn set_played(&self, played: u64) {
+		let clock = self.shared.start.elapsed().as_nanos() as u64;
+		self.shared.played.store(played, Ordering::Relaxed);
+		self.shared.clock.store(clock, Ordering::Relaxed);
+	}
+
+	// The frame being played right now. It can be past the written frames once the output runs dry.
+	fn position(&self) -> u64 {
+		let shared = &self.shared;
+		let played = shared.played.load(Ordering::Relaxed);
+		if !shared.running.load(Ordering::Relaxed) {
+			return played;
+		}
+		let clock = Duration::from_nanos(shared.clock.load(Ordering::Relaxed));
+		let elapsed = shared.start.elapsed().saturating_sub(clock);
+		played + (elapsed.as_secs_f64() * self.sample_rate()) as u64
+	}
+
+	// Frames that were never written or were overwritten since read as silence.
+	fn frame(&self, index: u64, written: u64) -> [f32; 2] {
+		if index >= written || index + (HISTORY as u64) <= written {
+			return [0.0; 2];
+		}
+		let slot = &self.shared.samples[index as usize % HISTORY];
+		[
+			f32::from_bits(slot[0].load(Ordering::Relaxed)),
+			f32::from_bits(slot[1].load(Ordering::Relaxed)),
+		]
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Analysis {
+	pub peak: [f32; 2],
+	pub rms: [f32; 2],
+	/// Magnitude of the frequency bins in dBFS, from 0Hz to half the sample rate
+	pub spectrum: Vec<f32>,
+	pub sample_rate: f64,
+}
+
+impl Analysis {
+	pub fn bin_frequency(&self, bin: usize) -> f64 {
+		bin as f64 * self.sample_rate / (2 * self.spectrum.len().saturating_sub(1)).max(1) as f64
+	}
+}
+
+// Computes the levels and spectrum of what is audible, meant to be called `rate` times per second.
+// The levels cover the last `1 / rate` seconds and the spectrum the last `fft_size` frames.
+pub struct Analyzer {
+	tap: AnalysisTap,
+	rate: f64,
+	fft: Arc<dyn RealToComplex<f32>>,
+	window: Vec<f32>,
+	input: Vec<f32>,
+	output: Vec<Complex<f32>>,
+}
+
+impl Analyzer {
+	pub fn new(tap: AnalysisTap, rate: f64, fft_size: usize) -> Self {
+		let fft = RealFftPlanner::new().plan_fft_forward(fft_size);
+		Self {
+			tap,
+			rate,
+			window: (0..fft_size)
+				.map(|i| {
+					0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / fft_size as f32).cos()
+				})
+				.collect(),
+			input: fft.make_input_vec(),
+			output: fft.make_output_vec(),
+			fft,
+		}
+	}
+
+	pub fn set_rate(&mut self, rate: f64) {
+		self.rate = rate;
+	}
+
+	pub fn interval(&self) -> Duration {
+		Duration::from_secs_f64(1.0 / self.rate)
+	}
+
+	pub fn analyze(&mut self) -> Analysis {
+		let sample_rate = self.tap.sample_rate();
+		let written = self.tap.shared.written.load(Ordering::Acquire);
+		let position = self.tap.position();
+		let frame = |i: usize, len: usize| {
+			self.tap
+				.frame((position + i as u64).wrapping_sub(len as u64), written)
+		};
+
+		let len = ((sample_rate / self.rate) as usize).clamp(1, HISTORY / 2);
+		let (mut peak, mut power) = ([0.0f32; 2], [0.0f32; 2]);
+		for i in 0..len {
+			let frame = frame(i, len);
+			for c in 0..2 {
+				peak[c] = peak[c].max(frame[c].abs());
+				power[c] += frame[c] * frame[c];
+			}
+		}
+
+		let fft_size = self.input.len();
+		for (i, sample) in self.input.iter_mut().enumerate() {
+			let frame = frame(i, fft_size);
+			*sample = (frame[0] + frame[1]) / 2.0 * self.window[i];
+		}
+		self.fft.process(&mut self.input, &mut self.output).ok();
+		// a full scale sine reads 0dB
+		let scale = 2.0 / self.window.iter().sum::<f32>();
+		let spectrum = self
+			.output
+			.iter()
+			.map(|bin| 20.0 * (bin.norm() * scale).max(1e-9).log10())
+			.collect();
+
+		Analysis {
+			peak,
+			rms: power.map(|p| (p / len as f32).sqrt()),
+			spectrum,
+			sample_rate,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn sine(freq: f32, len: usize) -> Vec<[f32; 2]> {
+		(0..len)
+			.map(|i| {
+				let s = 0.5 * (2.0 * std::f32::consts::PI * freq * i as f32 / 48000.0).sin();
+				[s, s]
+			})
+			.collect()
+	}
+
+	fn tap() -> AnalysisTap {
+		let tap = AnalysisTap::new();
+		tap.set_sample_rate(48000.0);
+		tap
+	}
+
+	#[test]
+	fn test_levels() {
+		let tap = tap();
+		tap.write(&sine(1000.0, 48000), 0);
+		let mut analyzer = Analyzer::new(tap.clone(), 10.0, 4096);
+		// the whole second was played
+		tap.set_played(48000);
+
+		let analysis = analyzer.analyze();
+		for c in 0..2 {
+			assert!((analysis.peak[c] - 0.5).abs() < 0.01, "{analysis:?}");
+			assert!((analysis.rms[c] - 0.5 / 2f32.sqrt()).abs() < 0.01);
+		}
+		let loudest = (0..analysis.spectrum.len())
+			.max_by(|&a, &b| analysis.spectrum[a].total_cmp(&analysis.spectrum[b]))
+			.unwrap();
+		assert!((analysis.bin_frequency(loudest) - 1000.0).abs() < 12.0);
+		assert!((analysis.spectrum[loudest] - 20.0 * 0.5f32.log10()).abs() < 1.5);
+	}
+
+	#[test]
+	fn test_delay() {
+		let tap = tap();
+		tap.set_running(true);
+		tap.write(&vec![[0.0; 2]; 24000], 0);
+		// the silence is still in the output buffer when the sine is written after it
+		tap.write(&sine(1000.0, 24000), 24000);
+		let mut analyzer = Analyzer::new(tap.clone(), 10.0, 1024);
+		assert!(analyzer.analyze().peak[0] < 0.01);
+
+		tap.set_running(false);
+		tap.set_played(30000);
+		assert!(analyzer.analyze().peak[0] > 0.4);
+		// the output ran dry
+		tap.set_played(100000);
+		assert_eq!(analyzer.analyze().peak, [0.0; 2]);
+	}
+}
@@ -9,7 +9,7 @@ use std::{
 use anyhow::{anyhow, Result};
 use parking_lot::RwLock;
 
-use super::Command;
+use super::{effect::Effect, AnalysisTap, Command, Crossfade, Normalization, SpeedMode, Upcoming};
 use crate::TrackSource;
 
 #[derive(Clone)]
@@ -17,6 +17,7 @@ pub struct Controller {
 	sender: crossbeam_channel::Sender<Command>,
 	state: Arc<RwLock<super::State>>,
 	nb_queued: Arc<AtomicUsize>,
+	tap: AnalysisTap,
 }
 
 impl std::fmt::Debug for Controller {
@@ -30,22 +31,27 @@ impl Controller {
 		state: Arc<RwLock<super::State>>,
 		sender: crossbeam_channel::Sender<Command>,
 		nb_queued: Arc<AtomicUsize>,
+		tap: AnalysisTap,
 	) -> Result<Self> {
 		Ok(Self {
 			state,
 			sender,
 			nb_queued,
+			tap,
 		})
 	}
 
-	pub fn clear(&self) {
-		self.sender.send(Command::Clear).unwrap();
+	pub fn clear(&self) -> Result<()> {
+		self.sender
+			.send(Command::Clear)
+			.map_err(|_| anyhow!("failed to clear"))?;
+		Ok(())
 	}
 
 	pub fn queue_track(&self, source: TrackSource) -> Result<()> {
-		if let Err(e) = self.sender.send(Command::QueueTrack(source)) {
-			panic!("{:?}", e);
-		}
+		self.sender
+			.send(Command::QueueTrack(source))
+			.map_err(|_| anyhow!("failed to queue track"))?;
 		Ok(())
 	}
 
@@ -78,17 +84,158 @@ impl Controller {
 	}
 
 	pub fn seek(&self, position: Duration) -> Result<()> {
-		self.sender.send(Command::Seek(position)).unwrap();
+		self.sender
+			.send(Command::Seek(position))
+			.map_err(|_| anyhow!("failed to seek"))?;
+		Ok(())
+	}
+
+	pub fn set_loop(&self, start: Duration, end: Duration) -> Result<()> {
+		self.sender
+			.send(Command::SetLoop { start, end })
+			.map_err(|_| anyhow!("failed to set the loop"))?;
+		Ok(())
+	}
+
+	pub fn clear_loop(&self) -> Result<()> {
+		self.sender
+			.send(Command::ClearLoop)
+			.map_err(|_| anyhow!("failed to clear the loop"))?;
+		Ok(())
+	}
+
+	pub fn jump_to_cue(&self, n: usize) -> Result<()> {
+		self.sender
+			.send(Command::JumpToCue(n))
+			.map_err(|_| anyhow!("failed to jump to the cue"))?;
+		Ok(())
+	}
+
+	pub fn set_cues(&self, cues: Vec<Duration>) -> Result<()> {
+		self.sender
+			.send(Command::SetCues(cues))
+			.map_err(|_| anyhow!("failed to set the cues"))?;
 		Ok(())
 	}
 
 	pub fn skip(&self) -> Result<()> {
-		self.sender.send(Command::Skip).unwrap();
+		self.sender
+			.send(Command::Skip)
+			.map_err(|_| anyhow!("failed to skip"))?;
 		Ok(())
 	}
 
 	pub fn set_volume(&self, volume: f32) -> Result<()> {
-		self.sender.send(Command::SetVolume(volume)).unwrap();
+		self.sender
+			.send(Command::SetVolume(volume))
+			.map_err(|_| anyhow!("failed to set volume"))?;
+		Ok(())
+	}
+
+	pub fn set_crossfade(&self, crossfade: Crossfade) -> Result<()> {
+		self.sender
+			.send(Command::SetCrossfade(crossfade))
+			.map_err(|_| anyhow!("failed to set crossfade"))?;
+		Ok(())
+	}
+
+	pub fn set_normalization(&self, normalization: Normalization) -> Result<()> {
+		self.sender
+			.send(Command::SetNormalization(normalization))
+			.map_err(|_| anyhow!("failed to set normalization"))?;
+		Ok(())
+	}
+
+	pub fn insert_effect(&self, index: usize, effect: Box<dyn Effect>) -> Result<()> {
+		self.sender
+			.send(Command::InsertEffect(index, effect))
+			.map_err(|_| anyhow!("failed to insert effect"))?;
+		Ok(())
+	}
+
+	pub fn replace_effect(&self, index: usize, effect: Box<dyn Effect>) -> Result<()> {
+		self.sender
+			.send(Command::ReplaceEffect(index, effect))
+			.map_err(|_| anyhow!("failed to replace effect"))?;
+		Ok(())
+	}
+
+	pub fn remove_effect(&self, index: usize) -> Result<()> {
+		self.sender
+			.send(Command::RemoveEffect(index))
+			.map_err(|_| anyhow!("failed to remove effect"))?;
+		Ok(())
+	}
+
+	pub fn clear_effects(&self) -> Result<()> {
+		self.sender
+			.send(Command::ClearEffects)
+			.map_err(|_| anyhow!("failed to clear effects"))?;
+		Ok(())
+	}
+
+	pub fn set_output_device(&self, device: Option<String>) -> Result<()> {
+		self.sender
+			.send(Command::SetOutputDevice(device))
+			.map_err(|_| anyhow!("failed to set the output device"))?;
+		Ok(())
+	}
+
+	pub fn set_speed(&self, speed: f32) -> Result<()> {
+		self.sender
+			.send(Command::SetSpeed(speed))
+			.map_err(|_| anyhow!("failed to set the speed"))?;
+		Ok(())
+	}
+
+	pub fn set_speed_mode(&self, mode: SpeedMode) -> Result<()> {
+		self.sender
+			.send(Command::SetSpeedMode(mode))
+			.map_err(|_| anyhow!("failed to set the speed mode"))?;
+		Ok(())
+	}
+
+	pub fn set_upcoming(&self, upcoming: Vec<Upcoming>) -> Result<()> {
+		self.sender
+			.send(Command::SetUpcoming(upcoming))
+			.map_err(|_| anyhow!("failed to set the upcoming tracks"))?;
+		Ok(())
+	}
+
+	pub fn set_lookahead(&self, lookahead: usize) -> Result<()> {
+		self.sender
+			.send(Command::SetLookahead(lookahead))
+			.map_err(|_| anyhow!("failed to set the lookahead"))?;
+		Ok(())
+	}
+
+	/// Queues a track given to `set_upcoming`, it is played as soon as it is ready.
+	pub fn queue_upcoming(&self, key: &str) -> Result<()> {
+		self.sender
+			.send(Command::QueueUpcoming(key.to_owned()))
+			.map_err(|_| anyhow!("failed to queue the upcoming track"))?;
+		Ok(())
+	}
+
+	pub fn set_ramp(&self, ramp: Duration) -> Result<()> {
+		self.sender
+			.send(Command::SetRamp(ramp))
+			.map_err(|_| anyhow!("failed to set the ramp"))?;
+		Ok(())
+	}
+
+	/// Fades out over `duration`, then pauses
+	pub fn fade_out(&self, duration: Duration) -> Result<()> {
+		self.sender
+			.send(Command::FadeOut(duration))
+			.map_err(|_| anyhow!("failed to fade out"))?;
+		Ok(())
+	}
+
+	pub fn cancel_fade_out(&self) -> Result<()> {
+		self.sender
+			.send(Command::CancelFadeOut)
+			.map_err(|_| anyhow!("failed to cancel the fade out"))?;
 		Ok(())
 	}
 
@@ -99,4 +246,9 @@ impl Controller {
 	pub fn nb_queued(&self) -> usize {
 		self.nb_queued.load(atomic::Ordering::Relaxed)
 	}
+
+	/// Gives access to the audio being played, see `Analyzer`.
+	pub fn analysis_tap(&self) -> AnalysisTap {
+		self.tap.clone()
+	}
 }
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+
+// Smooths the jumps of the output, like when seeking or skipping. The last frames are held back,
+// so that when the audio jumps they can be faded out before what comes next is faded in.
+pub struct Declicker {
+	len: usize,
+	held: VecDeque<[f32; 2]>,
+	// frames left to fade in
+	fade_in: usize,
+	// whether some of the held frames weren't flushed
+	pending: bool,
+}
+
+impl Declicker {
+	// `len` is the length of the ramps, and of the delay
+	pub fn new(len: usize) -> Self {
+		Self {
+			len,
+			held: VecDeque::from(vec![[0.0; 2]; len]),
+			fade_in: len,
+			pending: false,
+		}
+	}
+
+	pub fn delay(&self) -> usize {
+		self.len
+	}
+
+	// Delays the frames by the length of the ramps
+	pub fn process(&mut self, frames: &mut [[f32; 2]]) {
+		for frame in frames {
+			let mut input = *frame;
+			if self.fade_in > 0 {
+				let gain = (self.len + 1 - self.fade_in) as f32 / (self.len + 1) as f32;
+				input = [input[0] * gain, input[1] * gain];
+				self.fade_in -= 1;
+			}
+			self.held.push_back(input);
+			*frame = self.held.pop_front().unwrap_or(input);
+		}
+		self.pending |= !frames.is_empty();
+	}
+
+	// The audio jumps: the frames held back fade out, and the next ones fade in
+	pub fn cut(&mut self) {
+		let len = self.held.len();
+		for (i, frame) in self.held.iter_mut().enumerate() {
+			let gain = (len - i) as f32 / (len + 1) as f32;
+			*frame = [frame[0] * gain, frame[1] * gain];
+		}
+		self.fade_in = self.len;
+	}
+
+	// Takes the frames held back once nothing else comes, they fade out like when cutting
+	pub fn flush(&mut self) -> Vec<[f32; 2]> {
+		if !self.pending {
+			return vec![];
+		}
+		self.pending = false;
+		self.cut();
+		let flushed = self.held.drain(..).collect();
+		self.held.resize(self.len, [0.0; 2]);
+		flushed
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Duration;
+
+	use super::*;
+	use crate::{player::renderer::Renderer, Source, SourceError, TrackInfo, TrackSource};
+
+	struct Sine {
+		position: usize,
+	}
+
+	impl Source for Sine {
+		fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+			self.position = (pos.as_secs_f64() * 48000.0) as usize;
+			Ok(Duration::from_secs_f64(self.position as f64 / 48000.0))
+		}
+
+		fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+			for frame in buf.iter_mut() {
+				let t = self.position as f64 / 48000.0;
+				*frame = [0.5 * (2.0 * std::f64::consts::PI * 440.0 * t).sin() as f32; 2];
+				self.position += 1;
+			}
+			Ok(buf.len())
+		}
+	}
+
+	fn max_step(frames: &[[f32; 2]]) -> f32 {
+		frames
+			.windows(2)
+			.map(|w| (w[1][0] - w[0][0]).abs())
+			.fold(0.0, f32::max)
+	}
+
+	#[test]
+	fn test_cut() {
+		let mut declicker = Declicker::new(100);
+		let mut out = vec![[1.0; 2]; 1000];
+		declicker.process(&mut out);
+		// jumping to the opposite level
+		declicker.cut();
+		let mut next = vec![[-1.0; 2]; 1000];
+		declicker.process(&mut next);
+		out.extend(next);
+		out.extend(declicker.flush());
+
+		assert_eq!(out.len(), 2100);
+		assert!(out[..100].iter().all(|f| f[0] == 0.0));
+		assert!(max_step(&out) < 0.02, "{}", max_step(&out));
+		assert!(out.last().unwrap()[0].abs() < 0.02);
+		assert!(declicker.flush().is_empty());
+	}
+
+	// Like the player does around seeks and skips
+	#[test]
+	fn test_seek_and_skip() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.queue(TrackSource {
+			sample_rate: 48000.0,
+			signal: Box::new(Sine { position: 0 }),
+			info: TrackInfo {
+				duration: Duration::from_secs(60),
+				..Default::default()
+			},
+		});
+		let mut declicker = Declicker::new(480);
+		let mut out = vec![];
+		let mut render = |renderer: &mut Renderer, declicker: &mut Declicker| {
+			let mut buf = vec![[0.0; 2]; 960];
+			let n = renderer.render(&mut buf, &mut vec![]);
+			declicker.process(&mut buf[..n]);
+			out.extend_from_slice(&buf[..n]);
+		};
+
+		render(&mut renderer, &mut declicker);
+		// lands in the middle of a period
+		renderer.seek(Duration::from_nanos(30_001_234_567)).unwrap();
+		declicker.cut();
+		render(&mut renderer, &mut declicker);
+		renderer.skip();
+		declicker.cut();
+		render(&mut renderer, &mut declicker);
+		out.extend(declicker.flush());
+
+		assert_eq!(out.len(), 2 * 960 + 480);
+		assert!(out.iter().any(|f| f[0].abs() > 0.4));
+		// a 440Hz sine at 0.5 never moves by more than 0.03 from one frame to the next
+		assert!(max_step(&out) < 0.04, "{}", max_step(&out));
+		assert!(out.last().unwrap()[0].abs() < 0.01);
+	}
+}
@@ -0,0 +1,51 @@
+use super::{Biquad, Effect};
+
+const FREQUENCY: f64 = 100.0;
+const Q: f64 = std::f64::consts::FRAC_1_SQRT_2;
+
+// A low shelf, boosting (or cutting) everything under about 100Hz
+#[derive(Debug)]
+pub struct BassBoost {
+	gain_db: f32,
+	filters: Option<[Biquad; 2]>,
+}
+
+impl BassBoost {
+	pub fn new(gain_db: f32) -> Self {
+		Self {
+			gain_db,
+			filters: None,
+		}
+	}
+}
+
+impl Effect for BassBoost {
+	fn prepare(&mut self, sample_rate: f64) {
+		let filter = Biquad::low_shelf(sample_rate, FREQUENCY, Q, self.gain_db as f64);
+		self.filters = Some([filter.clone(), filter]);
+	}
+
+	fn process(&mut self, frames: &mut [[f32; 2]]) {
+		let Some(filters) = &mut self.filters else {
+			return;
+		};
+		for frame in frames {
+			for (sample, filter) in frame.iter_mut().zip(filters.iter_mut()) {
+				*sample = filter.process(*sample as f64) as f32;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::player::effect::test::{db, gain};
+
+	#[test]
+	fn test_shelf() {
+		let mut bass = BassBoost::new(6.0);
+		assert!((db(gain(&mut bass, 30.0)) - 6.0).abs() < 0.5);
+		assert!(db(gain(&mut bass, 5000.0)).abs() < 0.1);
+	}
+}
@@ -0,0 +1,85 @@
+use std::f64::consts::PI;
+
+// Direct form I biquad, `a` is normalized so that a0 == 1.
+// The RBJ cookbook gives the coefficients of the usual filter shapes.
+#[derive(Debug, Clone)]
+pub(crate) struct Biquad {
+	b: [f64; 3],
+	a: [f64; 2],
+	x: [f64; 2],
+	y: [f64; 2],
+}
+
+impl Biquad {
+	pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+		Self {
+			b,
+			a,
+			x: [0.0; 2],
+			y: [0.0; 2],
+		}
+	}
+
+	pub fn peak(sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> Self {
+		let (a, cos, alpha) = rbj(sample_rate, frequency, q, gain_db);
+		Self::normalized(
+			[1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+			[1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+		)
+	}
+
+	pub fn low_shelf(sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> Self {
+		let (a, cos, alpha) = rbj(sample_rate, frequency, q, gain_db);
+		let s = 2.0 * a.sqrt() * alpha;
+		Self::normalized(
+			[
+				a * ((a + 1.0) - (a - 1.0) * cos + s),
+				2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+				a * ((a + 1.0) - (a - 1.0) * cos - s),
+			],
+			[
+				(a + 1.0) + (a - 1.0) * cos + s,
+				-2.0 * ((a - 1.0) + (a + 1.0) * cos),
+				(a + 1.0) + (a - 1.0) * cos - s,
+			],
+		)
+	}
+
+	pub fn high_shelf(sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> Self {
+		let (a, cos, alpha) = rbj(sample_rate, frequency, q, gain_db);
+		let s = 2.0 * a.sqrt() * alpha;
+		Self::normalized(
+			[
+				a * ((a + 1.0) + (a - 1.0) * cos + s),
+				-2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+				a * ((a + 1.0) + (a - 1.0) * cos - s),
+			],
+			[
+				(a + 1.0) - (a - 1.0) * cos + s,
+				2.0 * ((a - 1.0) - (a + 1.0) * cos),
+				(a + 1.0) - (a - 1.0) * cos - s,
+			],
+		)
+	}
+
+	fn normalized(b: [f64; 3], a: [f64; 3]) -> Self {
+		Self::new(
+			[b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+			[a[1] / a[0], a[2] / a[0]],
+		)
+	}
+
+	pub fn process(&mut self, x: f64) -> f64 {
+		let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+			- self.a[0] * self.y[0]
+			- self.a[1] * self.y[1];
+		self.x = [x, self.x[0]];
+		self.y = [y, self.y[0]];
+		y
+	}
+}
+
+fn rbj(sample_rate: f64, frequency: f64, q: f64, gain_db: f64) -> (f64, f64, f64) {
+	let w0 = 2.0 * PI * frequency / sample_rate;
+	(10f64.powf(gain_db / 40.0), w0.cos(), w0.sin() / (2.0 * q))
+}
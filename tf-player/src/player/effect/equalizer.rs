@@ -0,0 +1,163 @@
+use super::{Biquad, Effect};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BandKind {
+	Peak,
+	LowShelf,
+	HighShelf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+	pub kind: BandKind,
+	pub frequency: f32,
+	pub q: f32,
+	pub gain_db: f32,
+}
+
+impl Band {
+	fn filter(&self, sample_rate: f64) -> Biquad {
+		let (frequency, q, gain_db) = (self.frequency as f64, self.q as f64, self.gain_db as f64);
+		match self.kind {
+			BandKind::Peak => Biquad::peak(sample_rate, frequency, q, gain_db),
+			BandKind::LowShelf => Biquad::low_shelf(sample_rate, frequency, q, gain_db),
+			BandKind::HighShelf => Biquad::high_shelf(sample_rate, frequency, q, gain_db),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EqPreset {
+	Flat,
+	Rock,
+	Pop,
+	Jazz,
+	Classical,
+	Electronic,
+	Vocal,
+}
+
+// Centers of the bands used by the presets, an octave apart
+const PRESET_FREQUENCIES: [f32; 10] = [
+	31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+impl EqPreset {
+	fn gains(&self) -> [f32; 10] {
+		match self {
+			EqPreset::Flat => [0.0; 10],
+			EqPreset::Rock => [4.5, 3.5, 2.0, -0.5, -1.5, -1.0, 1.0, 2.5, 3.5, 4.0],
+			EqPreset::Pop => [-1.0, 0.0, 1.5, 3.0, 3.5, 2.5, 1.0, 0.0, -0.5, -1.0],
+			EqPreset::Jazz => [3.0, 2.0, 1.0, 1.5, -1.0, -1.0, 0.0, 1.0, 2.0, 2.5],
+			EqPreset::Classical => [3.5, 3.0, 2.0, 1.0, -0.5, -0.5, 0.0, 1.5, 2.5, 3.0],
+			EqPreset::Electronic => [5.0, 4.0, 1.5, 0.0, -1.5, 1.0, 0.5, 1.5, 3.5, 4.5],
+			EqPreset::Vocal => [-2.0, -1.5, -0.5, 1.0, 3.0, 3.5, 3.0, 1.5, 0.0, -1.0],
+		}
+	}
+
+	pub fn bands(&self) -> Vec<Band> {
+		let last = PRESET_FREQUENCIES.len() - 1;
+		PRESET_FREQUENCIES
+			.iter()
+			.zip(self.gains())
+			.enumerate()
+			.map(|(i, (&frequency, gain_db))| {
+				let kind = match i {
+					0 => BandKind::LowShelf,
+					_ if i == last => BandKind::HighShelf,
+					_ => BandKind::Peak,
+				};
+				Band {
+					kind,
+					frequency,
+					// shelves resonate around their corner with a higher Q
+					q: match kind {
+						BandKind::Peak => std::f32::consts::SQRT_2,
+						_ => std::f32::consts::FRAC_1_SQRT_2,
+					},
+					gain_db,
+				}
+			})
+			.collect()
+	}
+}
+
+// A parametric equalizer: its bands are applied one after the other
+#[derive(Debug)]
+pub struct Equalizer {
+	bands: Vec<Band>,
+	filters: Vec<[Biquad; 2]>,
+}
+
+impl Equalizer {
+	pub fn new(bands: Vec<Band>) -> Self {
+		Self {
+			bands,
+			filters: vec![],
+		}
+	}
+
+	pub fn from_preset(preset: EqPreset) -> Self {
+		Self::new(preset.bands())
+	}
+
+	pub fn bands(&self) -> &[Band] {
+		&self.bands
+	}
+}
+
+impl Effect for Equalizer {
+	fn prepare(&mut self, sample_rate: f64) {
+		self.filters = self
+			.bands
+			.iter()
+			// flat bands don't change anything
+			.filter(|band| band.gain_db != 0.0)
+			.map(|band| {
+				let filter = band.filter(sample_rate);
+				[filter.clone(), filter]
+			})
+			.collect();
+	}
+
+	fn process(&mut self, frames: &mut [[f32; 2]]) {
+		for frame in frames {
+			for (c, sample) in frame.iter_mut().enumerate() {
+				let mut x = *sample as f64;
+				for filters in &mut self.filters {
+					x = filters[c].process(x);
+				}
+				*sample = x as f32;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::player::effect::test::{db, gain};
+
+	#[test]
+	fn test_peak() {
+		let mut eq = Equalizer::new(vec![Band {
+			kind: BandKind::Peak,
+			frequency: 1000.0,
+			q: 1.0,
+			gain_db: 6.0,
+		}]);
+		assert!((db(gain(&mut eq, 1000.0)) - 6.0).abs() < 0.1);
+		assert!(db(gain(&mut eq, 50.0)).abs() < 0.2);
+		assert!(db(gain(&mut eq, 15000.0)).abs() < 0.2);
+	}
+
+	#[test]
+	fn test_presets() {
+		let mut flat = Equalizer::from_preset(EqPreset::Flat);
+		assert_eq!(gain(&mut flat, 440.0), 1.0);
+
+		let mut rock = Equalizer::from_preset(EqPreset::Rock);
+		assert!(db(gain(&mut rock, 20.0)) > 3.0);
+		assert!(db(gain(&mut rock, 500.0)) < 0.0);
+	}
+}
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use super::Effect;
+
+// Keeps the signal under `threshold` by looking ahead of it: the gain is already down
+// when a peak comes out, and slowly recovers afterwards.
+#[derive(Debug)]
+pub struct Limiter {
+	threshold: f32,
+	// delayed frames, along with the gain each of them needs
+	delay: VecDeque<([f32; 2], f32)>,
+	release: f32,
+	gain: f32,
+}
+
+impl Limiter {
+	pub fn new(threshold: f32) -> Self {
+		Self {
+			threshold,
+			delay: VecDeque::new(),
+			release: 1.0,
+			gain: 1.0,
+		}
+	}
+
+	pub fn set_threshold(&mut self, threshold: f32) {
+		self.threshold = threshold;
+	}
+}
+
+impl Effect for Limiter {
+	fn prepare(&mut self, sample_rate: f64) {
+		let lookahead = (sample_rate * 0.0015) as usize;
+		self.delay = VecDeque::from(vec![([0.0; 2], 1.0); lookahead]);
+		self.release = 1.0 - (-1.0 / (sample_rate * 0.1)).exp() as f32;
+		self.gain = 1.0;
+	}
+
+	fn process(&mut self, frames: &mut [[f32; 2]]) {
+		for frame in frames {
+			let peak = frame[0].abs().max(frame[1].abs());
+			let needed = if peak > self.threshold {
+				self.threshold / peak
+			} else {
+				1.0
+			};
+			self.delay.push_back((*frame, needed));
+			let target = self.delay.iter().map(|(_, g)| *g).fold(1.0, f32::min);
+			let (delayed, _) = self.delay.pop_front().unwrap();
+			if target < self.gain {
+				self.gain = target;
+			} else {
+				self.gain += (target - self.gain) * self.release;
+			}
+			*frame = [delayed[0] * self.gain, delayed[1] * self.gain];
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::player::effect::test::{sine, SAMPLE_RATE};
+
+	#[test]
+	fn test_ceiling() {
+		let mut limiter = Limiter::new(0.5);
+		limiter.prepare(SAMPLE_RATE);
+		let mut frames = sine(440.0, 2.0);
+		limiter.process(&mut frames);
+		assert!(frames
+			.iter()
+			.all(|f| f[0].abs() <= 0.5 && f[1].abs() <= 0.5));
+		// the signal is not just silenced
+		assert!(frames.iter().any(|f| f[0].abs() > 0.45));
+	}
+
+	#[test]
+	fn test_transparent() {
+		let mut limiter = Limiter::new(0.5);
+		limiter.prepare(SAMPLE_RATE);
+		let input = sine(440.0, 0.4);
+		let mut frames = input.clone();
+		limiter.process(&mut frames);
+		let latency = (SAMPLE_RATE * 0.0015) as usize;
+		assert_eq!(frames[latency..], input[..input.len() - latency]);
+	}
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+
+mod biquad;
+pub(crate) use biquad::Biquad;
+
+mod equalizer;
+pub use equalizer::{Band, BandKind, EqPreset, Equalizer};
+
+mod bass;
+pub use bass::BassBoost;
+
+mod limiter;
+pub use limiter::Limiter;
+
+/// A processing stage applied to the output of the player, before the volume.
+pub trait Effect: Send + fmt::Debug {
+	/// Called before the first block is processed.
+	fn prepare(&mut self, sample_rate: f64);
+
+	fn process(&mut self, frames: &mut [[f32; 2]]);
+}
+
+// The effects are applied in order
+#[derive(Debug)]
+pub struct EffectChain {
+	sample_rate: f64,
+	effects: Vec<Box<dyn Effect>>,
+}
+
+impl EffectChain {
+	pub fn new(sample_rate: f64) -> Self {
+		Self {
+			sample_rate,
+			effects: vec![],
+		}
+	}
+
+	pub fn set_sample_rate(&mut self, sample_rate: f64) {
+		self.sample_rate = sample_rate;
+		for effect in &mut self.effects {
+			effect.prepare(sample_rate);
+		}
+	}
+
+	pub fn insert(&mut self, index: usize, mut effect: Box<dyn Effect>) -> anyhow::Result<()> {
+		if index > self.effects.len() {
+			return Err(anyhow::anyhow!("no effect slot at {index}"));
+		}
+		effect.prepare(self.sample_rate);
+		self.effects.insert(index, effect);
+		Ok(())
+	}
+
+	pub fn replace(&mut self, index: usize, mut effect: Box<dyn Effect>) -> anyhow::Result<()> {
+		let slot = self
+			.effects
+			.get_mut(index)
+			.ok_or(anyhow::anyhow!("no effect at {index}"))?;
+		effect.prepare(self.sample_rate);
+		*slot = effect;
+		Ok(())
+	}
+
+	pub fn remove(&mut self, index: usize) -> anyhow::Result<Box<dyn Effect>> {
+		if index >= self.effects.len() {
+			return Err(anyhow::anyhow!("no effect at {index}"));
+		}
+		Ok(self.effects.remove(index))
+	}
+
+	pub fn clear(&mut self) {
+		self.effects.clear();
+	}
+
+	pub fn process(&mut self, frames: &mut [[f32; 2]]) {
+		for effect in &mut self.effects {
+			effect.process(frames);
+		}
+	}
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+	use super::*;
+
+	pub const SAMPLE_RATE: f64 = 48000.0;
+
+	pub fn sine(frequency: f64, amplitude: f32) -> Vec<[f32; 2]> {
+		(0..SAMPLE_RATE as usize)
+			.map(|i| {
+				let s = amplitude
+					* (2.0 * std::f64::consts::PI * frequency * i as f64 / SAMPLE_RATE).sin()
+						as f32;
+				[s, s]
+			})
+			.collect()
+	}
+
+	// Ratio between the output and input levels of a sine, once the filters have settled
+	pub fn gain(effect: &mut dyn Effect, frequency: f64) -> f32 {
+		let input = sine(frequency, 0.1);
+		let mut output = input.clone();
+		effect.prepare(SAMPLE_RATE);
+		effect.process(&mut output);
+		let rms = |frames: &[[f32; 2]]| {
+			(frames.iter().map(|f| f[0] * f[0]).sum::<f32>() / frames.len() as f32).sqrt()
+		};
+		let settled = input.len() / 2;
+		rms(&output[settled..]) / rms(&input[settled..])
+	}
+
+	pub fn db(gain: f32) -> f32 {
+		20.0 * gain.log10()
+	}
+
+	#[test]
+	fn test_chain_order() {
+		let mut chain = EffectChain::new(SAMPLE_RATE);
+		chain.insert(0, Box::new(BassBoost::new(6.0))).unwrap();
+		chain.insert(0, Box::new(Limiter::new(0.5))).unwrap();
+		assert!(chain.insert(3, Box::new(Limiter::new(0.5))).is_err());
+
+		// boosting after the limiter pushes the peaks back over it
+		let mut frames = sine(50.0, 0.9);
+		chain.process(&mut frames);
+		assert!(frames.iter().any(|f| f[0] > 0.6));
+
+		let limiter = chain.remove(0).unwrap();
+		chain.insert(1, limiter).unwrap();
+		let mut frames = sine(50.0, 0.9);
+		chain.process(&mut frames);
+		assert!(frames.iter().all(|f| f[0] <= 0.5));
+	}
+}
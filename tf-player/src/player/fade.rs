@@ -0,0 +1,34 @@
+use std::{f32::consts::FRAC_PI_2, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FadeCurve {
+	Linear,
+	// keeps the perceived loudness constant when mixing uncorrelated tracks
+	EqualPower,
+}
+
+impl FadeCurve {
+	/// Returns the gains of the outgoing and incoming signals at `t` (from 0 to 1) in the fade.
+	pub fn gains(&self, t: f32) -> (f32, f32) {
+		let t = t.clamp(0.0, 1.0);
+		match self {
+			FadeCurve::Linear => (1.0 - t, t),
+			FadeCurve::EqualPower => ((t * FRAC_PI_2).cos(), (t * FRAC_PI_2).sin()),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crossfade {
+	pub duration: Duration,
+	pub curve: FadeCurve,
+}
+
+impl Default for Crossfade {
+	fn default() -> Self {
+		Self {
+			duration: Duration::ZERO,
+			curve: FadeCurve::EqualPower,
+		}
+	}
+}
@@ -0,0 +1,222 @@
+use std::f64::consts::PI;
+
+use super::effect::Biquad;
+
+// Loudness measurement as specified by ITU-R BS.1770 / EBU R128.
+
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+const TARGET: f32 = -18.0;
+const MAX_GAIN: f32 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+	/// Integrated loudness, in LUFS
+	pub integrated: f32,
+	/// True peak, in dBTP
+	pub true_peak: f32,
+}
+
+impl Loudness {
+	/// The gain to apply to reach the target loudness, as a factor
+	pub fn gain(&self) -> f32 {
+		let db = (TARGET - self.integrated).min(MAX_GAIN);
+		10f32.powf(db / 20.0)
+	}
+
+	/// Averages the loudness of several tracks, as if they were played one after the other.
+	pub fn combine<'a>(loudnesses: impl IntoIterator<Item = &'a Loudness>) -> Option<Loudness> {
+		let (mut power, mut true_peak, mut n) = (0.0, f32::NEG_INFINITY, 0);
+		for l in loudnesses {
+			power += 10f64.powf(l.integrated as f64 / 10.0);
+			true_peak = true_peak.max(l.true_peak);
+			n += 1;
+		}
+		(n > 0).then(|| Loudness {
+			integrated: (10.0 * (power / n as f64).log10()) as f32,
+			true_peak,
+		})
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Normalization {
+	#[default]
+	Off,
+	Track,
+	// use the loudness of the whole playlist, preserving the differences between its tracks
+	Album,
+}
+
+pub struct LoudnessMeter {
+	filters: [[Biquad; 2]; 2],
+	sub_block_len: usize,
+	sub_block: (f64, usize),
+	// energy of each 100ms sub-block, 4 of them make up a gating block
+	sub_blocks: Vec<f64>,
+	true_peak: TruePeak,
+}
+
+impl LoudnessMeter {
+	pub fn new(sample_rate: f64) -> Self {
+		let k_weighting = [k_shelf(sample_rate), k_high_pass(sample_rate)];
+		Self {
+			filters: [k_weighting.clone(), k_weighting],
+			sub_block_len: (sample_rate * 0.1) as usize,
+			sub_block: (0.0, 0),
+			sub_blocks: vec![],
+			true_peak: TruePeak::new(),
+		}
+	}
+
+	pub fn process(&mut self, frames: &[[f32; 2]]) {
+		for frame in frames {
+			self.true_peak.process(frame);
+			for (c, &sample) in frame.iter().enumerate() {
+				let [shelf, pass] = &mut self.filters[c];
+				let y = pass.process(shelf.process(sample as f64));
+				self.sub_block.0 += y * y;
+			}
+			self.sub_block.1 += 1;
+			if self.sub_block.1 == self.sub_block_len {
+				self.sub_blocks.push(self.sub_block.0);
+				self.sub_block = (0.0, 0);
+			}
+		}
+	}
+
+	/// Returns None if less than one gating block was measured.
+	pub fn loudness(&self) -> Option<Loudness> {
+		let block_len = (4 * self.sub_block_len) as f64;
+		let blocks = self
+			.sub_blocks
+			.windows(4)
+			.map(|w| w.iter().sum::<f64>() / block_len)
+			.collect::<Vec<_>>();
+		let loudness = |z: f64| -0.691 + 10.0 * z.log10();
+		let mean = |blocks: &mut dyn Iterator<Item = &f64>| {
+			let (sum, n) = blocks.fold((0.0, 0), |(sum, n), z| (sum + z, n + 1));
+			(n > 0).then(|| sum / n as f64)
+		};
+
+		let gated = mean(&mut blocks.iter().filter(|&&z| loudness(z) > ABSOLUTE_GATE))?;
+		let threshold = loudness(gated) + RELATIVE_GATE;
+		let integrated = mean(
+			&mut blocks
+				.iter()
+				.filter(|&&z| loudness(z) > ABSOLUTE_GATE && loudness(z) > threshold),
+		)?;
+		Some(Loudness {
+			integrated: loudness(integrated) as f32,
+			true_peak: 20.0 * self.true_peak.peak.log10(),
+		})
+	}
+}
+
+// Coefficients of the K-weighting filter stages, for any sample rate
+fn k_shelf(sample_rate: f64) -> Biquad {
+	let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+	let k = (PI * f0 / sample_rate).tan();
+	let vh = 10f64.powf(gain / 20.0);
+	let vb = vh.powf(0.4996667741545416);
+	let a0 = 1.0 + k / q + k * k;
+	Biquad::new(
+		[
+			(vh + vb * k / q + k * k) / a0,
+			2.0 * (k * k - vh) / a0,
+			(vh - vb * k / q + k * k) / a0,
+		],
+		[2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+	)
+}
+
+fn k_high_pass(sample_rate: f64) -> Biquad {
+	let (f0, q) = (38.13547087602444, 0.5003270373238773);
+	let k = (PI * f0 / sample_rate).tan();
+	let a0 = 1.0 + k / q + k * k;
+	Biquad::new(
+		[1.0, -2.0, 1.0],
+		[2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+	)
+}
+
+const OVERSAMPLING: usize = 4;
+const TAPS: usize = 12;
+
+// Estimates the peak of the reconstructed signal by oversampling it
+struct TruePeak {
+	phases: [[f32; TAPS]; OVERSAMPLING],
+	history: [[f32; TAPS]; 2],
+	peak: f32,
+}
+
+impl TruePeak {
+	fn new() -> Self {
+		let mut phases = [[0.0; TAPS]; OVERSAMPLING];
+		for (p, phase) in phases.iter_mut().enumerate() {
+			for (k, tap) in phase.iter_mut().enumerate() {
+				let t = k as f64 - (TAPS / 2) as f64 + p as f64 / OVERSAMPLING as f64;
+				let sinc = if t == 0.0 {
+					1.0
+				} else {
+					(PI * t).sin() / (PI * t)
+				};
+				let window = 0.5 + 0.5 * (PI * t / (TAPS / 2 + 1) as f64).cos();
+				*tap = (sinc * window) as f32;
+			}
+		}
+		Self {
+			phases,
+			history: [[0.0; TAPS]; 2],
+			peak: 0.0,
+		}
+	}
+
+	fn process(&mut self, frame: &[f32; 2]) {
+		for (c, &sample) in frame.iter().enumerate() {
+			let history = &mut self.history[c];
+			history.copy_within(1.., 0);
+			history[TAPS - 1] = sample;
+			for phase in &self.phases {
+				let y: f32 = phase
+					.iter()
+					.rev()
+					.zip(history.iter())
+					.map(|(h, x)| h * x)
+					.sum();
+				self.peak = self.peak.max(y.abs());
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_sine() {
+		let sample_rate = 48000.0;
+		let amplitude = 10f32.powf(-23.0 / 20.0);
+		let frames = (0..sample_rate as usize * 10)
+			.map(|i| {
+				let s = amplitude
+					* (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / sample_rate as f32).sin();
+				[s, s]
+			})
+			.collect::<Vec<_>>();
+
+		let mut meter = LoudnessMeter::new(sample_rate);
+		meter.process(&frames);
+		let loudness = meter.loudness().unwrap();
+		assert!((loudness.integrated + 23.0).abs() < 0.2, "{loudness:?}");
+		assert!((loudness.true_peak + 23.0).abs() < 0.2, "{loudness:?}");
+	}
+
+	#[test]
+	fn test_gating() {
+		let mut meter = LoudnessMeter::new(44100.0);
+		meter.process(&vec![[0.0; 2]; 44100]);
+		assert_eq!(meter.loudness(), None);
+	}
+}
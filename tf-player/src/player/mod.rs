@@ -1,6 +1,6 @@
 use std::{
-	collections::VecDeque,
-	ops::DerefMut,
+	ops::{DerefMut, Range},
+	panic::{self, AssertUnwindSafe},
 	sync::{
 		atomic::{self, AtomicUsize},
 		Arc,
@@ -8,23 +8,51 @@ use std::{
 	time::Duration,
 };
 
-use cpal::{traits::StreamTrait, StreamConfig};
 use crossbeam_channel::{Receiver, Sender};
 use parking_lot::RwLock;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
-use crate::{SourceError, TrackSource};
+use crate::{detect::Fingerprint, SourceError, TrackInfo, TrackSource};
 
 pub mod sink;
+use sink::{Backend, Sink};
 pub mod state;
 pub use state::State;
 
 mod resampler;
-use resampler::Resampler;
+
+mod renderer;
+use renderer::{RenderEvent, Renderer};
+
+mod fade;
+pub use fade::{Crossfade, FadeCurve};
+
+mod loudness;
+pub use loudness::{Loudness, LoudnessMeter, Normalization};
+
+pub mod effect;
+use effect::{Effect, EffectChain, Limiter};
+
+mod stretch;
+pub use stretch::SpeedMode;
+use stretch::Stretcher;
+
+mod waveform;
+pub use waveform::{Bucket, Waveform, WaveformBuilder, WAVEFORM_LEN};
+
+mod analysis;
+pub use analysis::{Analysis, AnalysisTap, Analyzer};
+
+mod prefetch;
+use prefetch::Prefetcher;
+pub use prefetch::{Readiness, Resolve, Upcoming, DEFAULT_LOOKAHEAD};
 
 mod controller;
 pub use controller::Controller;
 
+mod declick;
+use declick::Declicker;
+
 #[derive(Debug)]
 pub enum Command {
 	Clear,
@@ -33,31 +61,136 @@ pub enum Command {
 	Pause,
 	Seek(Duration),
 	Skip,
+	/// Plays the current track between two positions over and over, without any gap.
+	/// If it is already past the end, it goes back to the start right away.
+	/// The loop is cleared when the track changes.
+	SetLoop {
+		start: Duration,
+		end: Duration,
+	},
+	ClearLoop,
+	/// Seeks the current track to one of the cues of its `TrackInfo`.
+	JumpToCue(usize),
+	/// Replaces the cues of the current track, like when the user marks a new one.
+	SetCues(Vec<Duration>),
 	SetVolume(f32),
+	SetCrossfade(Crossfade),
+	SetNormalization(Normalization),
+	InsertEffect(usize, Box<dyn Effect>),
+	ReplaceEffect(usize, Box<dyn Effect>),
+	RemoveEffect(usize),
+	ClearEffects,
+	/// Moves the output to another device, None being the default one.
+	SetOutputDevice(Option<String>),
+	/// Plays faster or slower, between 0.5 and 2 times the normal speed.
+	SetSpeed(f32),
+	SetSpeedMode(SpeedMode),
+	/// The tracks likely to be queued next, in order. The first ones are prefetched, and
+	/// the prefetching of the tracks that aren't listed anymore is cancelled.
+	SetUpcoming(Vec<Upcoming>),
+	/// How many upcoming tracks are prefetched
+	SetLookahead(usize),
+	/// Queues an upcoming track, once it is ready.
+	QueueUpcoming(String),
+	/// How long the gain ramps around pauses, seeks and skips last, zero turns them off.
+	SetRamp(Duration),
+	/// Fades the output out over the given duration, then pauses, like for a sleep timer.
+	/// The fade is cancelled when pausing or clearing, the volume is then restored.
+	FadeOut(Duration),
+	CancelFadeOut,
 }
 
 pub enum Event {
 	StateChanged(State),
+	/// The current track is over. When crossfading, this is sent as soon as the next track starts
+	/// fading in, and the state reports the incoming track from then on.
 	TrackEnd,
+	/// The loudness of the current track, measured while playing it. Sent just before its `TrackEnd`,
+	/// only for tracks which didn't have a known loudness and were played in full, without seeking
+	/// and without crossfading into the next one.
+	Loudness(Loudness),
+	/// The waveform of the current track, under the same conditions as `Loudness`.
+	Waveform(Waveform),
+	/// The fingerprint of the current track, for tracks that aren't `fingerprinted`. Sent once
+	/// enough of its start was played, even if it is seeked or skipped afterwards.
+	Fingerprint(Fingerprint),
+	/// The output device went away, the player moved to the default one.
+	OutputDeviceLost,
+	/// The source of a track failed. The track is skipped: a `TrackEnd` follows if it was playing,
+	/// and the next queued track starts.
+	SourceError {
+		track: TrackInfo,
+		error: SourceError,
+	},
+	/// A track queued with `QueueUpcoming` is ready, it was handed over to the renderer.
+	UpcomingQueued(String),
+	/// A track queued with `QueueUpcoming` failed to resolve or isn't upcoming, it was dropped.
+	UpcomingFailed {
+		key: String,
+		error: SourceError,
+	},
+	/// The decoder thread recovered from a crash, the queue was cleared.
+	DecoderRestarted,
+	/// The source can't keep up, playback is held until it has loaded enough, from 0 to 1.
+	/// Sent as the progress changes, and with 1.0 once playback resumes.
+	Buffering(f32),
+}
+
+// Ceiling of the limiter when normalizing, in dBTP
+const NORMALIZATION_CEILING: f32 = -1.0;
+
+const SPEED_RANGE: std::ops::RangeInclusive<f64> = 0.5..=2.0;
+
+pub const DEFAULT_RAMP: Duration = Duration::from_millis(10);
+
+fn ramp_frames(ramp: Duration, sample_rate: f64) -> usize {
+	(ramp.as_secs_f64() * sample_rate) as usize
+}
+
+fn limiter_threshold(normalization: Normalization) -> f32 {
+	match normalization {
+		Normalization::Off => 1.0,
+		_ => 10f32.powf(NORMALIZATION_CEILING / 20.0),
+	}
 }
 
 pub struct Player {
 	receiver: crossbeam_channel::Receiver<Command>,
-	config: StreamConfig,
+	sample_rate: f64,
 	state: Arc<RwLock<State>>,
-	source_queue: VecDeque<TrackSource>,
-	nb_queued: Arc<AtomicUsize>, // invariant: nb_queued == source_queue.len()
-	source: Option<TrackSource>,
-	resampler: Option<Resampler>,
-	audio_sink: rtrb::Producer<f32>,
-	stream: cpal::Stream,
+	nb_queued: Arc<AtomicUsize>, // invariant: nb_queued == renderer.nb_queued()
+	renderer: Renderer,
+	buffer: Vec<[f32; 2]>,
+	effects: EffectChain,
+	// catches the peaks pushed over the top by normalization
+	limiter: Limiter,
+	speed: f64,
+	speed_mode: SpeedMode,
+	stretcher: Stretcher,
+	prefetcher: Prefetcher,
+	sink: Box<dyn Sink>,
+	tap: AnalysisTap,
+	// the output device went away and the player couldn't move to the default one
+	output_lost: bool,
 	event_sender: Sender<Event>,
 	last_report: Duration,
 	volume: f32,
+	// what was last reported of the source waiting for the network
+	buffering: Option<f32>,
+	ramp: Duration,
+	// fades the output around seeks and skips
+	declicker: Declicker,
+	// frames left in the fade out before pausing, and its length
+	fade_out: Option<(usize, usize)>,
 }
 
 impl Player {
 	pub fn spawn() -> anyhow::Result<(Controller, Receiver<Event>)> {
+		Self::spawn_with(sink::Cpal::default())
+	}
+
+	/// Spawns a player that sends its output to `backend`.
+	pub fn spawn_with<B: Backend>(backend: B) -> anyhow::Result<(Controller, Receiver<Event>)> {
 		let (to_player, from_controller) = crossbeam_channel::unbounded();
 
 		let player_state = Arc::new(RwLock::new(State::Idle));
@@ -66,35 +199,71 @@ impl Player {
 
 		let (event_sender, event_receiver) = crossbeam_channel::unbounded();
 
+		let (opened_sender, opened) = crossbeam_channel::bounded(1);
+
+		let tap = AnalysisTap::new();
+		let decoder_tap = tap.clone();
+
 		let decoder_player_state = player_state.clone();
 		let decoder_nb_queued = nb_queued.clone();
 		std::thread::Builder::new()
 			.name("decoder".to_owned())
 			.spawn(move || {
-				let (stream, config, audio_sink) = sink::AudioSink::new().unwrap();
+				let sink = match backend.open() {
+					Ok(mut sink) => {
+						opened_sender.send(Ok(())).ok();
+						sink
+					}
+					Err(e) => {
+						opened_sender.send(Err(e)).ok();
+						return;
+					}
+				};
 				debug!("launched decoder thread");
+				let sample_rate = sink.sample_rate() as f64;
+				sink.set_ramp(ramp_frames(DEFAULT_RAMP, sample_rate));
+				decoder_tap.set_sample_rate(sample_rate);
 				let mut player = Player {
 					receiver: from_controller,
-					config,
+					sample_rate,
+					renderer: Renderer::new(sample_rate),
+					buffer: vec![[0.0; 2]; sink::BUFFER_FRAMES],
+					effects: EffectChain::new(sample_rate),
+					limiter: {
+						let mut limiter = Limiter::new(limiter_threshold(Normalization::default()));
+						limiter.prepare(sample_rate);
+						limiter
+					},
+					speed: 1.0,
+					speed_mode: SpeedMode::default(),
+					stretcher: Stretcher::new(sample_rate),
+					prefetcher: Prefetcher::new(),
 					state: decoder_player_state,
-					source_queue: VecDeque::new(),
 					nb_queued: decoder_nb_queued,
-					source: None,
-					resampler: None,
-					audio_sink,
-					stream,
+					sink: Box::new(sink),
+					tap: decoder_tap,
+					output_lost: false,
 					event_sender,
 					last_report: Duration::from_secs(0),
 					volume: 1.0,
+					buffering: None,
+					ramp: DEFAULT_RAMP,
+					declicker: Declicker::new(ramp_frames(DEFAULT_RAMP, sample_rate)),
+					fade_out: None,
 				};
 				loop {
-					player.process();
+					let result = panic::catch_unwind(AssertUnwindSafe(|| player.process()));
+					if result.is_err() {
+						error!("the decoder crashed, restarting it");
+						player.reset();
+						player.emit(Event::DecoderRestarted);
+					}
 				}
-			})
-			.unwrap();
+			})?;
+		opened.recv()??;
 
 		Ok((
-			Controller::new(player_state, to_player, nb_queued)?,
+			Controller::new(player_state, to_player, nb_queued, tap)?,
 			event_receiver,
 		))
 	}
@@ -103,133 +272,458 @@ impl Player {
 		while let Ok(command) = self.receiver.try_recv() {
 			match command {
 				Command::Clear => {
-					self.source_queue.clear();
-					self.nb_queued.store(0, atomic::Ordering::Relaxed);
-					self.source = None;
-					self.resampler = None;
+					self.renderer.clear();
+					self.stretcher.clear();
+					self.declicker.cut();
+					self.fade_out = None;
+					self.prefetcher.unqueue_all();
 					*self.state.write() = State::Idle;
 				}
 				Command::QueueTrack(source) => {
-					self.source_queue.push_back(source);
-					self.nb_queued
-						.store(self.source_queue.len(), atomic::Ordering::Relaxed);
-					self.next_source();
+					self.renderer.queue(source);
 				}
 				Command::Play => {
+					self.fade_out = None;
 					self.state.write().play().ok();
-					self.stream.play().unwrap();
-					self.event_sender
-						.send(Event::StateChanged(self.state.read().clone()))
-						.unwrap();
+					if let Err(e) = self.sink.play() {
+						error!("{e:?}");
+					}
+					self.tap.set_running(true);
+					self.emit(Event::StateChanged(self.state.read().clone()));
 				}
 				Command::Pause => {
-					self.state.write().pause().ok();
-					self.stream.pause().unwrap();
-					self.event_sender
-						.send(Event::StateChanged(self.state.read().clone()))
-						.unwrap();
+					self.pause();
 				}
 				Command::Seek(position) => {
-					self.state.write().seek(position).ok();
-					match self.source.as_mut().map(|s| s.signal.seek(position)) {
-						Some(Err(e)) => error!("{e:?}"),
-						None => error!("tried to seek but there is no source"),
-						_ => {}
-					}
+					self.seek(position);
 				}
 				Command::Skip => {
 					*self.state.write() = State::Idle;
-					self.next_source();
+					self.renderer.skip();
+					self.stretcher.clear();
+					self.declicker.cut();
+				}
+				Command::SetLoop { start, end } => {
+					self.set_loop(Some(start..end));
+				}
+				Command::ClearLoop => {
+					self.set_loop(None);
+				}
+				Command::JumpToCue(n) => {
+					let cue = self
+						.state
+						.read()
+						.current_track()
+						.and_then(|track| track.cues.get(n).copied());
+					match cue {
+						Some(cue) => self.seek(cue),
+						None => error!("the current track has no cue {n}"),
+					}
+				}
+				Command::SetCues(cues) => {
+					if let State::Playing(playing) = self.state.write().deref_mut() {
+						playing.track.cues = cues;
+					}
+					self.emit(Event::StateChanged(self.state.read().clone()));
 				}
 				Command::SetVolume(v) => {
 					self.volume = v;
 				}
+				Command::SetCrossfade(crossfade) => {
+					self.renderer.set_crossfade(crossfade);
+				}
+				Command::SetNormalization(normalization) => {
+					self.renderer.set_normalization(normalization);
+					self.limiter.set_threshold(limiter_threshold(normalization));
+				}
+				Command::InsertEffect(index, effect) => {
+					if let Err(e) = self.effects.insert(index, effect) {
+						error!("{e:?}");
+					}
+				}
+				Command::ReplaceEffect(index, effect) => {
+					if let Err(e) = self.effects.replace(index, effect) {
+						error!("{e:?}");
+					}
+				}
+				Command::RemoveEffect(index) => {
+					if let Err(e) = self.effects.remove(index) {
+						error!("{e:?}");
+					}
+				}
+				Command::ClearEffects => {
+					self.effects.clear();
+				}
+				Command::SetOutputDevice(device) => {
+					if let Err(e) = self.set_output_device(device.as_deref()) {
+						error!("{e:?}");
+					}
+				}
+				Command::SetSpeed(speed) => {
+					let speed = (speed as f64).clamp(*SPEED_RANGE.start(), *SPEED_RANGE.end());
+					self.set_speed(speed, self.speed_mode);
+				}
+				Command::SetSpeedMode(mode) => {
+					self.set_speed(self.speed, mode);
+				}
+				Command::SetUpcoming(upcoming) => {
+					self.prefetcher.set_upcoming(upcoming);
+				}
+				Command::SetLookahead(lookahead) => {
+					self.prefetcher.set_lookahead(lookahead);
+				}
+				Command::QueueUpcoming(key) => {
+					if !self.prefetcher.queue(&key) {
+						error!("{key} isn't upcoming");
+						self.emit(Event::UpcomingFailed {
+							key,
+							error: SourceError::General("the track isn't upcoming".into()),
+						});
+					}
+				}
+				Command::SetRamp(ramp) => {
+					self.set_ramp(ramp);
+				}
+				Command::FadeOut(duration) => {
+					let len = ((duration.as_secs_f64() * self.sample_rate) as usize).max(1);
+					self.fade_out = Some((len, len));
+				}
+				Command::CancelFadeOut => {
+					self.fade_out = None;
+				}
 			}
+			self.update_nb_queued();
 		}
 	}
 
-	pub fn next_source(&mut self) {
-		if *self.state.read() != State::Idle {
+	// Hands the upcoming tracks that were queued over to the renderer as they become ready.
+	fn queue_prefetched(&mut self) {
+		let prefetched = self.prefetcher.take_queued();
+		if prefetched.is_empty() {
 			return;
 		}
-		if let Some(mut source) = self.source_queue.pop_front() {
-			let source_sample_rate = source.sample_rate;
-			let mut resampler =
-				Resampler::new((self.config.sample_rate.0 as f64) / source_sample_rate).unwrap();
-			resampler.process(&mut source).ok();
-			self.resampler = Some(resampler);
-			self.state.write().set_track(source.info.clone());
-			self.source = Some(source);
-			self.nb_queued
-				.store(self.source_queue.len(), atomic::Ordering::Relaxed);
+		for (key, result) in prefetched {
+			match result {
+				Ok(source) => {
+					self.renderer.queue(source);
+					self.emit(Event::UpcomingQueued(key));
+				}
+				Err(error) => {
+					warn!("skipping upcoming track {key}: {error}");
+					self.emit(Event::UpcomingFailed {
+						key,
+						error: SourceError::General(error.into()),
+					});
+				}
+			}
 		}
+		self.update_nb_queued();
+	}
+
+	fn update_nb_queued(&self) {
+		self.nb_queued.store(
+			self.renderer.nb_queued() + self.prefetcher.nb_waiting(),
+			atomic::Ordering::Relaxed,
+		);
 	}
 
 	pub fn process(&mut self) {
 		self.process_events();
+		self.queue_prefetched();
 
-		self.next_source();
+		if let State::Playing(playing) = self.state.write().deref_mut() {
+			playing.upcoming = self.prefetcher.readiness();
+			playing.buffered = self.renderer.buffered();
+		}
 
-		let offset = match *self.state.read() {
-			State::Playing(state::Playing {
-				paused: false,
-				offset,
-				..
-			}) => offset,
-			_ => {
-				std::thread::sleep(Duration::from_millis(100));
-				return;
+		if self.sink.device_lost() && !self.output_lost {
+			warn!("the output device went away, moving to the default one");
+			self.output_lost = true;
+			match self.set_output_device(None) {
+				Ok(()) => {
+					self.emit(Event::OutputDeviceLost);
+				}
+				Err(e) => error!("{e:?}"),
 			}
-		};
+		}
+
+		let paused = matches!(
+			*self.state.read(),
+			State::Playing(state::Playing { paused: true, .. })
+		);
+		if paused {
+			std::thread::sleep(Duration::from_millis(100));
+			return;
+		}
+		if self.renderer.is_idle() && self.stretcher.available() == 0 {
+			// the queue ended before the fade out did, what plays next isn't faded
+			self.fade_out = None;
+			self.flush();
+			std::thread::sleep(Duration::from_millis(100));
+			return;
+		}
+
+		// nothing is rendered while the source waits, which holds the clock
+		let buffering = self.renderer.buffering();
+		self.set_buffering(buffering);
+		if buffering.is_some() {
+			self.flush();
+			std::thread::sleep(Duration::from_millis(50));
+			return;
+		}
+
+		let missing_data = self.sink.available().min(self.buffer.len());
 
-		let resampler = self.resampler.as_mut().unwrap();
-		let source = self.source.as_mut().unwrap();
+		if missing_data > 256 {
+			let nb_rendered = if self.stretcher.is_active() {
+				self.stretch(missing_data)
+			} else {
+				self.render(missing_data)
+			};
 
-		let missing_data = self.audio_sink.slots();
+			self.effects.process(&mut self.buffer[..nb_rendered]);
+			self.limiter.process(&mut self.buffer[..nb_rendered]);
 
-		if missing_data > 512 {
-			if self.last_report.as_millis().abs_diff(offset.as_millis()) > 1000 {
-				self.event_sender
-					.send(Event::StateChanged(self.state.read().clone()))
-					.unwrap();
-				self.last_report = offset;
+			for frame in &mut self.buffer[..nb_rendered] {
+				let mut gain = self.volume;
+				if let Some((left, len)) = &mut self.fade_out {
+					gain *= *left as f32 / *len as f32;
+					*left = left.saturating_sub(1);
+				}
+				*frame = [frame[0] * gain, frame[1] * gain];
+			}
+			self.declicker.process(&mut self.buffer[..nb_rendered]);
+			let buffered = sink::BUFFER_FRAMES.saturating_sub(self.sink.available());
+			self.tap.write(&self.buffer[..nb_rendered], buffered);
+			if let Err(e) = self.sink.write(&self.buffer[..nb_rendered]) {
+				error!("{e:?}");
+			}
+			if let Some((0, _)) = self.fade_out {
+				self.pause();
 			}
 
-			for _ in 0..(missing_data / 2) {
-				if resampler.i >= resampler.out_buf[0].len() {
-					match resampler.process(source) {
-						Err(SourceError::EndOfStream) => {
-							self.resampler = None;
-							self.source = None;
-							*self.state.write() = State::Idle;
-							self.next_source();
-							self.event_sender.send(Event::TrackEnd).unwrap();
-							return;
-						}
-						Err(e) => {
-							panic!("{e:?}");
-						}
-						Ok(()) => match self.state.write().deref_mut() {
-							State::Playing(state::Playing { offset, .. }) => {
-								*offset += Duration::from_secs_f64(
-									resampler.out_buf[0].len() as f64
-										/ self.config.sample_rate.0 as f64,
-								);
-							}
-							_ => {}
-						},
-					}
+			if let Some(waveform) = self.renderer.waveform() {
+				if let State::Playing(playing) = self.state.write().deref_mut() {
+					playing.track.waveform = Some(Arc::new(waveform));
+				}
+			}
+			if let Some(&offset) = self.state.read().current_time() {
+				if self.last_report.as_millis().abs_diff(offset.as_millis()) > 1000 {
+					self.emit(Event::StateChanged(self.state.read().clone()));
+					self.last_report = offset;
 				}
-				self.audio_sink
-					.push(resampler.out_buf[0][resampler.i] * self.volume)
-					.unwrap();
-				self.audio_sink
-					.push(resampler.out_buf[1][resampler.i] * self.volume)
-					.unwrap();
-				resampler.i += 1;
 			}
 		} else {
 			std::thread::sleep(Duration::from_millis(100));
 		}
 	}
+
+	// Renders the first `len` frames of the buffer, returns how many were written.
+	fn render(&mut self, len: usize) -> usize {
+		let mut events = vec![];
+		let nb_rendered = self.renderer.render(&mut self.buffer[..len], &mut events);
+		self.update_nb_queued();
+
+		let mut last = 0;
+		for (at, event) in events {
+			self.advance(at - last);
+			last = at;
+			match event {
+				RenderEvent::TrackStart(info) => self.state.write().set_track(info),
+				RenderEvent::Loudness(loudness) => {
+					self.emit(Event::Loudness(loudness));
+				}
+				RenderEvent::Waveform(waveform) => {
+					self.emit(Event::Waveform(waveform));
+				}
+				RenderEvent::Fingerprint(fingerprint) => {
+					self.emit(Event::Fingerprint(fingerprint));
+				}
+				RenderEvent::TrackEnd => {
+					*self.state.write() = State::Idle;
+					self.emit(Event::TrackEnd);
+				}
+				RenderEvent::SourceError(track, error) => {
+					warn!("skipping track: {error}");
+					self.emit(Event::SourceError { track, error });
+				}
+				RenderEvent::Looped(position) => {
+					self.state.write().seek(position).ok();
+				}
+			}
+		}
+		self.advance(nb_rendered - last);
+		nb_rendered
+	}
+
+	fn pause(&mut self) {
+		self.fade_out = None;
+		self.state.write().pause().ok();
+		if let Err(e) = self.sink.pause() {
+			error!("{e:?}");
+		}
+		self.tap.set_running(false);
+		self.emit(Event::StateChanged(self.state.read().clone()));
+	}
+
+	fn seek(&mut self, position: Duration) {
+		match self.renderer.seek(position) {
+			Ok(landed) => {
+				self.state.write().seek(landed).ok();
+			}
+			Err(e) => error!("{e:?}"),
+		}
+		self.stretcher.clear();
+		self.declicker.cut();
+		self.emit(Event::StateChanged(self.state.read().clone()));
+	}
+
+	// Writes out what the declicker holds back once nothing follows it, faded out
+	fn flush(&mut self) {
+		if self.sink.available() < self.declicker.delay() {
+			return;
+		}
+		let tail = self.declicker.flush();
+		if tail.is_empty() {
+			return;
+		}
+		let buffered = sink::BUFFER_FRAMES.saturating_sub(self.sink.available());
+		self.tap.write(&tail, buffered);
+		if let Err(e) = self.sink.write(&tail) {
+			error!("{e:?}");
+		}
+	}
+
+	fn set_ramp(&mut self, ramp: Duration) {
+		self.ramp = ramp;
+		self.flush();
+		let frames = ramp_frames(ramp, self.sample_rate);
+		self.declicker = Declicker::new(frames);
+		self.sink.set_ramp(frames);
+	}
+
+	fn set_loop(&mut self, range: Option<Range<Duration>>) {
+		let landed = match self.renderer.set_loop(range.clone()) {
+			Ok(landed) => landed,
+			Err(e) => {
+				error!("{e:?}");
+				return;
+			}
+		};
+		if let State::Playing(playing) = self.state.write().deref_mut() {
+			playing.looping = range;
+		}
+		// the renderer went back to the start of the loop, as it was read past its end
+		if let Some(landed) = landed {
+			self.state.write().seek(landed).ok();
+			self.stretcher.clear();
+			self.declicker.cut();
+		}
+		self.emit(Event::StateChanged(self.state.read().clone()));
+	}
+
+	// Renders through the time-stretcher until it has `len` frames ready, or the queue is over.
+	fn stretch(&mut self, len: usize) -> usize {
+		while self.stretcher.available() < len {
+			let missing = len - self.stretcher.available();
+			let needed = ((missing as f64 * self.speed) as usize).clamp(256, self.buffer.len());
+			let nb_rendered = self.render(needed);
+			self.stretcher.push(&self.buffer[..nb_rendered]);
+			if nb_rendered < needed {
+				self.stretcher.flush();
+				break;
+			}
+		}
+		self.stretcher.pull(&mut self.buffer[..len])
+	}
+
+	// Varispeed goes through the resamplers of the renderer, the pitch is preserved by the stretcher.
+	fn set_speed(&mut self, speed: f64, mode: SpeedMode) {
+		self.speed = speed;
+		self.speed_mode = mode;
+		let (varispeed, stretch) = match mode {
+			SpeedMode::Varispeed => (speed, 1.0),
+			SpeedMode::PreservePitch => (1.0, speed),
+		};
+		if let Err(e) = self.renderer.set_speed(varispeed) {
+			error!("{e:?}");
+		}
+		self.stretcher.set_speed(stretch);
+	}
+
+	fn set_buffering(&mut self, buffering: Option<f32>) {
+		if buffering == self.buffering {
+			return;
+		}
+		if buffering.is_some() != self.buffering.is_some() {
+			debug!("buffering: {buffering:?}");
+			self.tap.set_running(buffering.is_none());
+		}
+		self.buffering = buffering;
+		if let State::Playing(playing) = self.state.write().deref_mut() {
+			playing.buffering = buffering;
+		}
+		self.emit(Event::Buffering(buffering.unwrap_or(1.0)));
+		self.emit(Event::StateChanged(self.state.read().clone()));
+	}
+
+	fn emit(&self, event: Event) {
+		if self.event_sender.send(event).is_err() {
+			debug!("nobody is listening to the player events");
+		}
+	}
+
+	// Drops everything that was playing, after a crash
+	fn reset(&mut self) {
+		self.renderer.clear();
+		self.stretcher.clear();
+		self.prefetcher.clear();
+		*self.state.write() = State::Idle;
+		self.nb_queued.store(0, atomic::Ordering::Relaxed);
+		self.buffering = None;
+	}
+
+	// The stream is re-created, so playback resumes from what was last heard.
+	fn set_output_device(&mut self, device: Option<&str>) -> anyhow::Result<()> {
+		let unplayed = sink::BUFFER_FRAMES.saturating_sub(self.sink.available());
+		let unplayed = Duration::from_secs_f64(unplayed as f64 * self.speed / self.sample_rate);
+		self.sink.set_device(device)?;
+		self.output_lost = false;
+
+		let sample_rate = self.sink.sample_rate() as f64;
+		if sample_rate != self.sample_rate {
+			self.sample_rate = sample_rate;
+			self.renderer
+				.set_sample_rate(sample_rate)
+				.map_err(|e| anyhow::anyhow!("{e:?}"))?;
+			self.effects.set_sample_rate(sample_rate);
+			self.limiter.prepare(sample_rate);
+			self.tap.set_sample_rate(sample_rate);
+			self.stretcher = Stretcher::new(sample_rate);
+			self.set_speed(self.speed, self.speed_mode);
+		}
+		self.set_ramp(self.ramp);
+
+		let position = self.state.read().current_time().copied();
+		if let Some(position) = position {
+			let position = position.saturating_sub(unplayed);
+			match self.renderer.seek(position) {
+				Ok(landed) => {
+					self.state.write().seek(landed).ok();
+				}
+				Err(e) => error!("{e:?}"),
+			}
+			self.stretcher.clear();
+		}
+		Ok(())
+	}
+
+	// Moves the playback position forward by `nb_frames` rendered frames, which cover more or less
+	// of the source when playing at varispeed.
+	fn advance(&mut self, nb_frames: usize) {
+		let duration = nb_frames as f64 * self.renderer.speed() / self.sample_rate;
+		if let State::Playing(state::Playing { offset, .. }) = self.state.write().deref_mut() {
+			*offset += Duration::from_secs_f64(duration);
+		}
+	}
 }
@@ -0,0 +1,427 @@
+use std::{fmt, ops::Range, sync::Arc, time::Duration};
+
+use parking_lot::{Condvar, Mutex};
+use tracing::{debug, warn};
+
+use crate::{Source, SourceError, TrackSource};
+
+// Audio read ahead of time, enough to ride out the first network hiccups
+const PREBUFFER: Duration = Duration::from_secs(5);
+
+pub const DEFAULT_LOOKAHEAD: usize = 2;
+
+pub type Resolve = Box<dyn FnOnce() -> anyhow::Result<TrackSource> + Send>;
+
+/// A track that is likely to be queued soon. `resolve` creates its source, and `key` identifies
+/// it from one call to `SetUpcoming` to the next.
+pub struct Upcoming {
+	pub key: String,
+	pub resolve: Resolve,
+}
+
+impl fmt::Debug for Upcoming {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Upcoming").field("key", &self.key).finish()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Readiness {
+	/// Past the lookahead, nothing was done yet
+	Waiting,
+	Resolving,
+	/// Reading the first seconds of the track, with the fraction read so far
+	Buffering(f32),
+	Ready,
+	Failed(String),
+}
+
+// Gets the upcoming tracks ready one after the other on a background thread: their source is
+// resolved and their first seconds are read, so they start without a gap once queued.
+// Only the first `lookahead` tracks are prepared, the others wait for their turn, and the tracks
+// that are no longer upcoming are dropped, even halfway through.
+pub(crate) struct Prefetcher {
+	shared: Arc<Shared>,
+}
+
+struct Shared {
+	inner: Mutex<Inner>,
+	condvar: Condvar,
+}
+
+struct Inner {
+	lookahead: usize,
+	entries: Vec<Entry>,
+	next_id: u64,
+	stopped: bool,
+}
+
+struct Entry {
+	id: u64,
+	key: String,
+	// taken once the work on the entry starts
+	resolve: Option<Resolve>,
+	readiness: Readiness,
+	source: Option<TrackSource>,
+	// asked for by the player, it is handed over as soon as it is ready
+	queued: bool,
+}
+
+impl Prefetcher {
+	pub fn new() -> Self {
+		let shared = Arc::new(Shared {
+			inner: Mutex::new(Inner {
+				lookahead: DEFAULT_LOOKAHEAD,
+				entries: vec![],
+				next_id: 0,
+				stopped: false,
+			}),
+			condvar: Condvar::new(),
+		});
+		let worker = shared.clone();
+		std::thread::Builder::new()
+			.name("prefetcher".to_owned())
+			.spawn(move || worker.run())
+			.unwrap();
+		Self { shared }
+	}
+
+	pub fn set_lookahead(&self, lookahead: usize) {
+		self.shared.inner.lock().lookahead = lookahead;
+		self.shared.condvar.notify_one();
+	}
+
+	// Replaces the upcoming tracks. The work already done is kept for the keys that are still there.
+	pub fn set_upcoming(&self, upcoming: Vec<Upcoming>) {
+		let mut inner = self.shared.inner.lock();
+		let mut old = std::mem::take(&mut inner.entries);
+		for Upcoming { key, resolve } in upcoming {
+			if let Some(i) = old.iter().position(|e| e.key == key) {
+				inner.entries.push(old.remove(i));
+			} else {
+				let id = inner.next_id;
+				inner.next_id += 1;
+				inner.entries.push(Entry {
+					id,
+					key,
+					resolve: Some(resolve),
+					readiness: Readiness::Waiting,
+					source: None,
+					queued: false,
+				});
+			}
+		}
+		// the player is still waiting for those
+		inner.entries.extend(old.into_iter().filter(|e| e.queued));
+		drop(inner);
+		self.shared.condvar.notify_one();
+	}
+
+	// Returns false if there is no such track
+	pub fn queue(&self, key: &str) -> bool {
+		let mut inner = self.shared.inner.lock();
+		let Some(entry) = inner.entries.iter_mut().find(|e| e.key == key) else {
+			return false;
+		};
+		entry.queued = true;
+		drop(inner);
+		self.shared.condvar.notify_one();
+		true
+	}
+
+	// Forgets that tracks were queued, their prefetching goes on if they are still upcoming.
+	pub fn unqueue_all(&self) {
+		for entry in &mut self.shared.inner.lock().entries {
+			entry.queued = false;
+		}
+	}
+
+	pub fn clear(&self) {
+		self.shared.inner.lock().entries.clear();
+	}
+
+	// The queued tracks that became ready, or failed, in the order they were given
+	pub fn take_queued(&self) -> Vec<(String, Result<TrackSource, String>)> {
+		let mut inner = self.shared.inner.lock();
+		let mut taken = vec![];
+		inner.entries.retain_mut(|entry| {
+			if !entry.queued {
+				return true;
+			}
+			match (&entry.readiness, entry.source.take()) {
+				(Readiness::Ready, Some(source)) => {
+					taken.push((entry.key.clone(), Ok(source)));
+					false
+				}
+				(Readiness::Failed(e), _) => {
+					taken.push((entry.key.clone(), Err(e.clone())));
+					false
+				}
+				_ => true,
+			}
+		});
+		taken
+	}
+
+	// Tracks that were queued but aren't ready yet
+	pub fn nb_waiting(&self) -> usize {
+		self.shared
+			.inner
+			.lock()
+			.entries
+			.iter()
+			.filter(|e| e.queued)
+			.count()
+	}
+
+	pub fn readiness(&self) -> Vec<(String, Readiness)> {
+		self.shared
+			.inner
+			.lock()
+			.entries
+			.iter()
+			.filter(|e| !e.queued)
+			.map(|e| (e.key.clone(), e.readiness.clone()))
+			.collect()
+	}
+}
+
+impl Drop for Prefetcher {
+	fn drop(&mut self) {
+		self.shared.inner.lock().stopped = true;
+		self.shared.condvar.notify_one();
+	}
+}
+
+impl Shared {
+	fn run(&self) {
+		loop {
+			let (id, resolve) = {
+				let mut inner = self.inner.lock();
+				loop {
+					if inner.stopped {
+						return;
+					}
+					let lookahead = inner.lookahead;
+					let next = inner
+						.entries
+						.iter_mut()
+						.enumerate()
+						.find(|(i, e)| e.resolve.is_some() && (*i < lookahead || e.queued));
+					if let Some((_, entry)) = next {
+						entry.readiness = Readiness::Resolving;
+						break (entry.id, entry.resolve.take().unwrap());
+					}
+					self.condvar.wait(&mut inner);
+				}
+			};
+
+			let result = resolve()
+				.map_err(|e| e.to_string())
+				.and_then(|source| self.prebuffer(id, source).map_err(|e| e.to_string()));
+			let mut inner = self.inner.lock();
+			// otherwise it was cancelled
+			if let Some(entry) = inner.entries.iter_mut().find(|e| e.id == id) {
+				match result {
+					Ok(Some(source)) => {
+						debug!("{} is ready", entry.key);
+						entry.source = Some(source);
+						entry.readiness = Readiness::Ready;
+					}
+					Ok(None) => {}
+					Err(e) => {
+						warn!("failed to prefetch {}: {e}", entry.key);
+						entry.readiness = Readiness::Failed(e);
+					}
+				}
+			}
+		}
+	}
+
+	// Reads the first seconds of the source, unless the entry goes away in the meantime.
+	fn prebuffer(&self, id: u64, source: TrackSource) -> Result<Option<TrackSource>, SourceError> {
+		let TrackSource {
+			sample_rate,
+			mut signal,
+			info,
+		} = source;
+		let len = (PREBUFFER.as_secs_f64() * sample_rate) as usize;
+		let mut frames = Vec::with_capacity(len);
+		let mut buf = vec![[0.0; 2]; 4096];
+		let mut ended = false;
+		while frames.len() < len && !ended {
+			{
+				let mut inner = self.inner.lock();
+				let Some(entry) = inner.entries.iter_mut().find(|e| e.id == id) else {
+					return Ok(None);
+				};
+				entry.readiness = Readiness::Buffering(frames.len() as f32 / len as f32);
+			}
+			let want = buf.len().min(len - frames.len());
+			let read = match signal.next(&mut buf[..want]) {
+				Err(SourceError::EndOfStream) => 0,
+				r => r?,
+			};
+			frames.extend_from_slice(&buf[..read]);
+			ended = read < want;
+		}
+		Ok(Some(TrackSource {
+			sample_rate,
+			signal: Box::new(Prebuffered {
+				frames,
+				position: 0,
+				ended,
+				inner: signal,
+			}),
+			info,
+		}))
+	}
+}
+
+// Plays the frames read ahead of time before going on with the source.
+struct Prebuffered {
+	frames: Vec<[f32; 2]>,
+	position: usize,
+	ended: bool,
+	inner: Box<dyn Source>,
+}
+
+impl Source for Prebuffered {
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+		self.frames = vec![];
+		self.position = 0;
+		self.ended = false;
+		self.inner.seek(pos)
+	}
+
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+		let n = buf.len().min(self.frames.len() - self.position);
+		buf[..n].copy_from_slice(&self.frames[self.position..self.position + n]);
+		self.position += n;
+		if n == buf.len() || self.ended {
+			return Ok(n);
+		}
+		match self.inner.next(&mut buf[n..]) {
+			Ok(read) => Ok(n + read),
+			Err(SourceError::EndOfStream) if n > 0 => Ok(n),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn buffering(&self) -> Option<f32> {
+		if self.position < self.frames.len() || self.ended {
+			return None;
+		}
+		self.inner.buffering()
+	}
+
+	fn buffered(&self) -> Vec<Range<Duration>> {
+		self.inner.buffered()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::Instant;
+
+	use super::*;
+	use crate::TrackInfo;
+
+	// Counts up from 0, one value per frame
+	struct Ramp {
+		position: usize,
+		len: usize,
+	}
+
+	impl Source for Ramp {
+		fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+			self.position = (pos.as_secs_f64() * 1000.0) as usize;
+			Ok(pos)
+		}
+
+		fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+			let n = buf.len().min(self.len.saturating_sub(self.position));
+			for frame in &mut buf[..n] {
+				*frame = [self.position as f32; 2];
+				self.position += 1;
+			}
+			Ok(n)
+		}
+	}
+
+	fn upcoming(key: &str, len: usize) -> Upcoming {
+		Upcoming {
+			key: key.to_owned(),
+			resolve: Box::new(move || {
+				Ok(TrackSource {
+					sample_rate: 1000.0,
+					signal: Box::new(Ramp { position: 0, len }),
+					info: TrackInfo::default(),
+				})
+			}),
+		}
+	}
+
+	fn wait_queued(prefetcher: &Prefetcher) -> Vec<(String, Result<TrackSource, String>)> {
+		let deadline = Instant::now() + Duration::from_secs(5);
+		loop {
+			let taken = prefetcher.take_queued();
+			if !taken.is_empty() {
+				return taken;
+			}
+			assert!(Instant::now() < deadline, "nothing became ready");
+			std::thread::sleep(Duration::from_millis(10));
+		}
+	}
+
+	#[test]
+	fn test_prebuffered() {
+		let prefetcher = Prefetcher::new();
+		prefetcher.set_upcoming(vec![upcoming("a", 8000)]);
+		assert!(prefetcher.queue("a"));
+		let (key, source) = wait_queued(&prefetcher).pop().unwrap();
+		assert_eq!(key, "a");
+
+		// the prebuffered frames are followed by the rest of the source
+		let mut signal = source.unwrap().signal;
+		let mut buf = vec![[0.0; 2]; 3000];
+		let mut read = vec![];
+		loop {
+			let n = signal.next(&mut buf).unwrap();
+			read.extend(buf[..n].iter().map(|f| f[0] as usize));
+			if n < buf.len() {
+				break;
+			}
+		}
+		assert_eq!(read, (0..8000).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_lookahead() {
+		let prefetcher = Prefetcher::new();
+		prefetcher.set_lookahead(1);
+		prefetcher.set_upcoming(vec![upcoming("a", 100), upcoming("b", 100)]);
+		let deadline = Instant::now() + Duration::from_secs(5);
+		while prefetcher.readiness()[0].1 != Readiness::Ready {
+			assert!(Instant::now() < deadline);
+			std::thread::sleep(Duration::from_millis(10));
+		}
+		assert_eq!(prefetcher.readiness()[1].1, Readiness::Waiting);
+
+		// "a" was dropped from the queue, "b" is now first
+		prefetcher.set_upcoming(vec![
+			upcoming("b", 100),
+			Upcoming {
+				key: String::from("c"),
+				resolve: Box::new(|| Err(anyhow::anyhow!("not found"))),
+			},
+		]);
+		prefetcher.set_lookahead(2);
+		assert!(prefetcher.queue("c"));
+		let (key, error) = wait_queued(&prefetcher).pop().unwrap();
+		assert_eq!(key, "c");
+		assert_eq!(error.err().unwrap(), "not found");
+		assert_eq!(prefetcher.readiness().len(), 1);
+		assert_eq!(prefetcher.readiness()[0].0, "b");
+	}
+}
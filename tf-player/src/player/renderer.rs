@@ -0,0 +1,936 @@
+use std::{collections::VecDeque, ops::Range, time::Duration};
+
+use super::{
+	fade::Crossfade,
+	loudness::{Loudness, LoudnessMeter, Normalization},
+	resampler::Resampler,
+	waveform::{Waveform, WaveformBuilder},
+};
+use crate::{
+	detect::{Fingerprint, FingerprintBuilder},
+	SourceError, TrackInfo, TrackSource,
+};
+
+#[derive(Debug)]
+pub enum RenderEvent {
+	TrackStart(TrackInfo),
+	// the loudness of a track that was read from start to end, sent just before its TrackEnd
+	Loudness(Loudness),
+	// same for the waveform
+	Waveform(Waveform),
+	// the start of a track, sent as soon as enough of it was read
+	Fingerprint(Fingerprint),
+	TrackEnd,
+	SourceError(TrackInfo, SourceError),
+	// the loop went back to its start, landing at that position
+	Looped(Duration),
+}
+
+// Reads the queued sources back to back through a single resampler, so that tracks with the
+// same sample rate follow each other without any gap.
+// The resampler is only recreated when the sample rate changes.
+// When a crossfade is configured, the next track starts on its own deck before the current one ends,
+// and the current track is considered over as soon as the crossfade starts.
+// The loudness of tracks is measured as they are read, and their normalization gain applied at the same point.
+// Varispeed is done by the resamplers: tracks are resampled to `sample_rate / speed`, so the rendered
+// frames cover `speed` times their duration of the sources.
+// Loops are done as the sources are read: at the end of the loop the source is seeked back to its
+// start, and the resampler goes on without noticing.
+pub struct Renderer {
+	sample_rate: f64,
+	speed: f64,
+	queue: VecDeque<TrackSource>,
+	deck: Option<Deck>,
+	crossfade: Crossfade,
+	fade: Option<Fade>,
+	normalization: Normalization,
+}
+
+// The outgoing track of a crossfade
+struct Fade {
+	deck: Deck,
+	len: usize,
+	pos: usize,
+}
+
+impl Renderer {
+	pub fn new(sample_rate: f64) -> Self {
+		Self {
+			sample_rate,
+			speed: 1.0,
+			queue: VecDeque::new(),
+			deck: None,
+			crossfade: Crossfade::default(),
+			fade: None,
+			normalization: Normalization::default(),
+		}
+	}
+
+	pub fn queue(&mut self, source: TrackSource) {
+		self.queue.push_back(source);
+	}
+
+	pub fn nb_queued(&self) -> usize {
+		self.queue.len()
+	}
+
+	pub fn is_idle(&self) -> bool {
+		self.deck.is_none() && self.fade.is_none() && self.queue.is_empty()
+	}
+
+	pub fn set_crossfade(&mut self, crossfade: Crossfade) {
+		self.crossfade = crossfade;
+	}
+
+	pub fn set_normalization(&mut self, normalization: Normalization) {
+		self.normalization = normalization;
+		let fade = self.fade.as_mut().map(|fade| &mut fade.deck);
+		for deck in self.deck.iter_mut().chain(fade) {
+			deck.set_normalization(normalization);
+		}
+	}
+
+	// The current track is kept, the caller should seek it back to what was last heard.
+	pub fn set_sample_rate(&mut self, sample_rate: f64) -> Result<(), SourceError> {
+		self.sample_rate = sample_rate;
+		self.fade = None;
+		let rate = self.rate();
+		match &mut self.deck {
+			Some(deck) => deck.set_sample_rate(rate),
+			None => Ok(()),
+		}
+	}
+
+	pub fn speed(&self) -> f64 {
+		self.speed
+	}
+
+	// Changes the playback speed of the tracks being played, without interrupting them.
+	pub fn set_speed(&mut self, speed: f64) -> Result<(), SourceError> {
+		self.speed = speed;
+		let rate = self.rate();
+		let fade = self.fade.as_mut().map(|fade| &mut fade.deck);
+		for deck in self.deck.iter_mut().chain(fade) {
+			deck.set_rate(rate)?;
+		}
+		Ok(())
+	}
+
+	// The sample rate the sources are resampled to
+	fn rate(&self) -> f64 {
+		self.sample_rate / self.speed
+	}
+
+	pub fn clear(&mut self) {
+		self.queue.clear();
+		self.skip();
+	}
+
+	// Stops the current track, the next render starts the following one.
+	pub fn skip(&mut self) {
+		self.deck = None;
+		self.fade = None;
+	}
+
+	// Returns the position the current track landed at
+	pub fn seek(&mut self, position: Duration) -> Result<Duration, SourceError> {
+		self.fade = None;
+		self.deck
+			.as_mut()
+			.ok_or(SourceError::General("there is no source to seek".into()))?
+			.seek(position)
+	}
+
+	// Loops the current track between the two positions, until the loop is cleared or the track changes.
+	// If the track was already read past the end of the loop, it goes back to its start right away
+	// and the position it landed at is returned.
+	pub fn set_loop(
+		&mut self,
+		range: Option<Range<Duration>>,
+	) -> Result<Option<Duration>, SourceError> {
+		let deck = self
+			.deck
+			.as_mut()
+			.ok_or(SourceError::General("there is no source to loop".into()))?;
+		deck.set_loop(range.clone());
+		let behind = matches!(&deck.looping, Some(l) if deck.position > l.end);
+		match range {
+			Some(range) if behind => self.seek(range.start).map(Some),
+			_ => Ok(None),
+		}
+	}
+
+	// Whether the next render would wait for the source of the current track, or the next one to start
+	pub fn buffering(&self) -> Option<f32> {
+		match &self.deck {
+			Some(deck) => deck.source.as_ref()?.signal.buffering(),
+			None => self.queue.front()?.signal.buffering(),
+		}
+	}
+
+	pub fn buffered(&self) -> Vec<Range<Duration>> {
+		self.deck
+			.as_ref()
+			.and_then(|deck| deck.source.as_ref())
+			.map(|source| source.signal.buffered())
+			.unwrap_or_default()
+	}
+
+	// The waveform of the current track as read so far, if it is being computed and changed since the last call.
+	pub fn waveform(&mut self) -> Option<Waveform> {
+		self.deck.as_mut()?.waveform.as_mut()?.snapshot()
+	}
+
+	/// Fills `out` and returns the number of frames written, which is only less than `out.len()`
+	/// when there is nothing left to play. `events` receives what happened along with the frame at which it did.
+	/// A track whose source fails is skipped, reported with a `SourceError` followed by its `TrackEnd`.
+	pub fn render(
+		&mut self,
+		out: &mut [[f32; 2]],
+		events: &mut Vec<(usize, RenderEvent)>,
+	) -> usize {
+		let rate = self.rate();
+		let mut i = 0;
+		while i < out.len() {
+			let Some(deck) = &mut self.deck else {
+				if let Some(source) = self.queue.pop_front() {
+					let info = source.info.clone();
+					match Deck::new(source, rate, self.normalization) {
+						Ok(deck) => {
+							events.push((i, RenderEvent::TrackStart(info)));
+							self.deck = Some(deck);
+						}
+						Err(error) => events.push((i, RenderEvent::SourceError(info, error))),
+					}
+					continue;
+				}
+				// nothing queued, let the outgoing track of the crossfade finish on its own
+				match &mut self.fade {
+					Some(fade) => match fade.deck.next_frame(None, &mut |_| {}) {
+						Ok(Some(frame)) => {
+							let (gain, _) = self
+								.crossfade
+								.curve
+								.gains(fade.pos as f32 / fade.len as f32);
+							fade.pos += 1;
+							out[i] = [frame[0] * gain, frame[1] * gain];
+							i += 1;
+						}
+						Ok(None) => self.fade = None,
+						Err(error) => {
+							events.push((i, RenderEvent::SourceError(fade.deck.info(), error)));
+							self.fade = None;
+						}
+					},
+					None => break,
+				}
+				continue;
+			};
+
+			if self.fade.is_none() {
+				if let (Some(remaining), Some(_)) = (deck.remaining(), self.queue.front()) {
+					if !self.crossfade.duration.is_zero() && remaining <= self.crossfade.duration {
+						let len = (remaining.as_secs_f64() * rate) as usize;
+						let source = self.queue.pop_front().unwrap();
+						let info = source.info.clone();
+						let incoming = match Deck::new(source, rate, self.normalization) {
+							Ok(deck) => deck,
+							Err(error) => {
+								events.push((i, RenderEvent::SourceError(info, error)));
+								continue;
+							}
+						};
+						// the end of the outgoing track is read after its TrackEnd, it wouldn't be measured in full
+						deck.meter = None;
+						if let Some(fingerprint) = deck.fingerprint.take().and_then(|f| f.build()) {
+							events.push((i, RenderEvent::Fingerprint(fingerprint)));
+						}
+						events.push((i, RenderEvent::TrackEnd));
+						events.push((i, RenderEvent::TrackStart(info)));
+						let outgoing = std::mem::replace(deck, incoming);
+						self.fade = Some(Fade {
+							deck: outgoing,
+							len: len.max(1),
+							pos: 0,
+						});
+					}
+				}
+			}
+
+			let mut frame =
+				match deck.next_frame(Some(&mut self.queue), &mut |e| events.push((i, e))) {
+					Ok(Some(frame)) => frame,
+					Ok(None) => {
+						self.deck = None;
+						continue;
+					}
+					Err(error) => {
+						// what was read before the error already happened
+						for (_, event) in deck.pending.drain(..) {
+							events.push((i, event));
+						}
+						events.push((i, RenderEvent::SourceError(deck.info(), error)));
+						events.push((i, RenderEvent::TrackEnd));
+						self.deck = None;
+						continue;
+					}
+				};
+			if let Some(fade) = &mut self.fade {
+				let (gain_out, gain_in) = self
+					.crossfade
+					.curve
+					.gains(fade.pos as f32 / fade.len as f32);
+				let outgoing = match fade.deck.next_frame(None, &mut |_| {}) {
+					Ok(frame) => frame.unwrap_or_default(),
+					Err(error) => {
+						events.push((i, RenderEvent::SourceError(fade.deck.info(), error)));
+						fade.pos = fade.len;
+						[0.0; 2]
+					}
+				};
+				frame = [
+					frame[0] * gain_in + outgoing[0] * gain_out,
+					frame[1] * gain_in + outgoing[1] * gain_out,
+				];
+				fade.pos += 1;
+				if fade.pos >= fade.len {
+					self.fade = None;
+				}
+			}
+			out[i] = frame;
+			i += 1;
+		}
+		i
+	}
+}
+
+// A source being played through its resampler
+struct Deck {
+	source: Option<TrackSource>,
+	resampler: Resampler,
+	// frames read from the current source
+	position: usize,
+	// events that happen within the resampler's output buffer, with their position in it
+	pending: VecDeque<(usize, RenderEvent)>,
+	// the source ended, the resampler still has to be flushed
+	tail: bool,
+	// only measures sources whose loudness is unknown, and is dropped when seeking
+	meter: Option<LoudnessMeter>,
+	// same for the waveform, which is kept when seeking to show what was read
+	waveform: Option<WaveformBuilder>,
+	// only for sources that weren't fingerprinted, what was read from the start is kept when seeking
+	fingerprint: Option<FingerprintBuilder>,
+	normalization: Normalization,
+	gain: f32,
+	// the frames of the current source that are looped
+	looping: Option<Range<usize>>,
+}
+
+impl Deck {
+	fn new(
+		source: TrackSource,
+		sample_rate: f64,
+		normalization: Normalization,
+	) -> Result<Self, SourceError> {
+		let mut deck = Self {
+			resampler: Resampler::new(sample_rate / source.sample_rate)
+				.map_err(|e| SourceError::General(e.into()))?,
+			source: None,
+			position: 0,
+			pending: VecDeque::new(),
+			tail: false,
+			meter: None,
+			waveform: None,
+			fingerprint: None,
+			normalization,
+			gain: 1.0,
+			looping: None,
+		};
+		deck.start(source);
+		Ok(deck)
+	}
+
+	fn start(&mut self, source: TrackSource) {
+		self.position = 0;
+		self.looping = None;
+		self.meter = source
+			.info
+			.loudness
+			.is_none()
+			.then(|| LoudnessMeter::new(source.sample_rate));
+		self.waveform = source
+			.info
+			.waveform
+			.is_none()
+			.then(|| WaveformBuilder::new(source.sample_rate, source.info.duration));
+		self.fingerprint =
+			(!source.info.fingerprinted).then(|| FingerprintBuilder::new(source.sample_rate));
+		self.source = Some(source);
+		self.set_normalization(self.normalization);
+	}
+
+	fn set_normalization(&mut self, normalization: Normalization) {
+		self.normalization = normalization;
+		let info = self.source.as_ref().map(|source| &source.info);
+		let loudness = match normalization {
+			Normalization::Off => None,
+			Normalization::Track => info.and_then(|info| info.loudness),
+			Normalization::Album => info.and_then(|info| info.album_loudness.or(info.loudness)),
+		};
+		self.gain = loudness.map_or(1.0, |l| l.gain());
+	}
+
+	fn info(&self) -> TrackInfo {
+		self.source
+			.as_ref()
+			.map(|source| source.info.clone())
+			.unwrap_or_default()
+	}
+
+	// None while looping, the track doesn't end then
+	fn remaining(&self) -> Option<Duration> {
+		if self.looping.is_some() {
+			return None;
+		}
+		let source = self.source.as_ref()?;
+		// part of what was read is still waiting in the resampler
+		let ahead =
+			(self.resampler.out_buf[0].len() - self.resampler.i) as f64 / self.resampler.ratio;
+		let position = (self.position as f64 - ahead).max(0.0) / source.sample_rate;
+		let position = Duration::from_secs_f64(position);
+		Some(source.info.duration.saturating_sub(position))
+	}
+
+	fn set_loop(&mut self, range: Option<Range<Duration>>) {
+		let Some(source) = &self.source else {
+			return;
+		};
+		let frame = |t: Duration| (t.as_secs_f64() * source.sample_rate).round() as usize;
+		self.looping = range
+			.map(|range| frame(range.start)..frame(range.end))
+			.filter(|range| !range.is_empty());
+	}
+
+	fn set_sample_rate(&mut self, sample_rate: f64) -> Result<(), SourceError> {
+		if let Some(source) = &self.source {
+			self.resampler = Resampler::new(sample_rate / source.sample_rate)
+				.map_err(|e| SourceError::General(e.into()))?;
+			self.pending.clear();
+		}
+		Ok(())
+	}
+
+	fn set_rate(&mut self, sample_rate: f64) -> Result<(), SourceError> {
+		if let Some(source) = &self.source {
+			self.resampler
+				.set_ratio(sample_rate / source.sample_rate)
+				.map_err(|e| SourceError::General(e.into()))?;
+		}
+		Ok(())
+	}
+
+	fn seek(&mut self, position: Duration) -> Result<Duration, SourceError> {
+		let source = self
+			.source
+			.as_mut()
+			.ok_or(SourceError::General("the source has ended".into()))?;
+		let position = source.signal.seek(position)?;
+		self.position = (position.as_secs_f64() * source.sample_rate).round() as usize;
+		self.meter = None;
+		if let Some(waveform) = &mut self.waveform {
+			waveform.seek(self.position);
+		}
+		// drop what was resampled from the previous position
+		self.resampler.i = self.resampler.out_buf[0].len();
+		self.pending.clear();
+		self.finish_fingerprint(0);
+		Ok(position)
+	}
+
+	// Returns the next output frame, or None once the deck is drained.
+	// If `chain` is given, the deck moves on to the next queued source when the current one ends
+	// and has the same sample rate.
+	fn next_frame(
+		&mut self,
+		mut chain: Option<&mut VecDeque<TrackSource>>,
+		on_event: &mut dyn FnMut(RenderEvent),
+	) -> Result<Option<[f32; 2]>, SourceError> {
+		while self.resampler.is_empty() {
+			if !self.refill(chain.as_deref_mut())? {
+				return Ok(None);
+			}
+		}
+		while matches!(self.pending.front(), Some((at, _)) if *at <= self.resampler.i) {
+			let (_, event) = self.pending.pop_front().unwrap();
+			on_event(event);
+		}
+		let i = self.resampler.i;
+		self.resampler.i += 1;
+		Ok(Some([
+			self.resampler.out_buf[0][i],
+			self.resampler.out_buf[1][i],
+		]))
+	}
+
+	// Feeds the resampler with the next chunk of the source.
+	// Returns false once the resampler has been drained.
+	fn refill(&mut self, chain: Option<&mut VecDeque<TrackSource>>) -> Result<bool, SourceError> {
+		let in_len = self.resampler.input_frames_next();
+		if self.source.is_none() {
+			if !self.tail {
+				return Ok(false);
+			}
+			self.tail = false;
+			self.resampler.source_buf[..in_len].fill([0.0; 2]);
+			self.resampler.process();
+			return Ok(true);
+		}
+
+		let mut filled = 0;
+		let mut chain = chain;
+		while let Some(source) = &mut self.source {
+			// a loop is only read up to its end
+			let until_loop = self
+				.looping
+				.as_ref()
+				.filter(|looping| self.position <= looping.end)
+				.map(|looping| looping.end - self.position);
+			let len = until_loop.map_or(in_len, |n| in_len.min(filled + n));
+			let read = match source
+				.signal
+				.next(&mut self.resampler.source_buf[filled..len])
+			{
+				Err(SourceError::EndOfStream) => 0,
+				r => r?,
+			};
+			let frames = &mut self.resampler.source_buf[filled..filled + read];
+			if let Some(meter) = &mut self.meter {
+				meter.process(frames);
+			}
+			if let Some(waveform) = &mut self.waveform {
+				waveform.process(frames);
+			}
+			if let Some(fingerprint) = &mut self.fingerprint {
+				fingerprint.process(frames);
+			}
+			for frame in frames {
+				*frame = [frame[0] * self.gain, frame[1] * self.gain];
+			}
+			filled += read;
+			self.position += read;
+			let at = (filled as f64 * self.resampler.ratio) as usize;
+			if self.fingerprint.as_ref().map_or(false, |f| f.is_complete()) {
+				self.finish_fingerprint(at);
+			}
+			if filled == in_len {
+				break;
+			}
+			if let Some(looping) = self.looping.clone().filter(|_| until_loop == Some(read)) {
+				let start = Duration::from_secs_f64(looping.start as f64 / source.sample_rate);
+				let landed = source.signal.seek(start)?;
+				self.position = (landed.as_secs_f64() * source.sample_rate).round() as usize;
+				// it would never get to the end again
+				if self.position >= looping.end {
+					self.looping = None;
+				}
+				self.meter = None;
+				if let Some(waveform) = &mut self.waveform {
+					waveform.seek(self.position);
+				}
+				self.finish_fingerprint(at);
+				self.pending.push_back((at, RenderEvent::Looped(landed)));
+				continue;
+			}
+			if let Some(loudness) = self.meter.take().and_then(|m| m.loudness()) {
+				self.pending
+					.push_back((at, RenderEvent::Loudness(loudness)));
+			}
+			if let Some(waveform) = self.waveform.take().filter(|w| w.is_complete()) {
+				self.pending
+					.push_back((at, RenderEvent::Waveform(waveform.finish())));
+			}
+			self.finish_fingerprint(at);
+			self.pending.push_back((at, RenderEvent::TrackEnd));
+			let sample_rate = source.sample_rate;
+			match chain.as_deref_mut() {
+				Some(queue) if queue.front().map(|next| next.sample_rate) == Some(sample_rate) => {
+					let next = queue.pop_front().unwrap();
+					self.pending
+						.push_back((at, RenderEvent::TrackStart(next.info.clone())));
+					self.start(next);
+				}
+				// the resampler is flushed with silence, then recreated for the next source
+				_ => {
+					self.source = None;
+					self.tail = true;
+				}
+			}
+		}
+		self.resampler.source_buf[filled..in_len].fill([0.0; 2]);
+		self.resampler.process();
+		let last = self.resampler.out_buf[0].len().saturating_sub(1);
+		for (at, _) in &mut self.pending {
+			*at = (*at).min(last);
+		}
+		Ok(true)
+	}
+
+	// Stops fingerprinting, what was read from the start is reported if it's long enough
+	fn finish_fingerprint(&mut self, at: usize) {
+		if let Some(fingerprint) = self.fingerprint.take().and_then(|f| f.build()) {
+			self.pending
+				.push_back((at, RenderEvent::Fingerprint(fingerprint)));
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::Source;
+
+	struct Constant {
+		value: f32,
+		remaining: usize,
+	}
+
+	impl Source for Constant {
+		fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+			Ok(pos)
+		}
+
+		fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+			let n = buf.len().min(self.remaining);
+			buf[..n].fill([self.value; 2]);
+			self.remaining -= n;
+			Ok(n)
+		}
+	}
+
+	// Fails instead of reading past `remaining` frames
+	struct Failing {
+		remaining: usize,
+	}
+
+	impl Source for Failing {
+		fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+			Ok(pos)
+		}
+
+		fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+			if buf.len() > self.remaining {
+				return Err(SourceError::General("connection lost".into()));
+			}
+			buf.fill([0.5; 2]);
+			self.remaining -= buf.len();
+			Ok(buf.len())
+		}
+	}
+
+	// A 100Hz sine, at 48kHz
+	struct Sine {
+		position: usize,
+		len: usize,
+	}
+
+	impl Source for Sine {
+		fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+			self.position = ((pos.as_secs_f64() * 48000.0).round() as usize).min(self.len);
+			Ok(Duration::from_secs_f64(self.position as f64 / 48000.0))
+		}
+
+		fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+			let n = buf.len().min(self.len - self.position);
+			for (i, frame) in buf[..n].iter_mut().enumerate() {
+				let t = (self.position + i) as f32 / 48000.0;
+				*frame = [0.5 * (2.0 * std::f32::consts::PI * 100.0 * t).sin(); 2];
+			}
+			self.position += n;
+			Ok(n)
+		}
+	}
+
+	fn constant(sample_rate: f64, value: f32, len: usize) -> TrackSource {
+		TrackSource {
+			sample_rate,
+			signal: Box::new(Constant {
+				value,
+				remaining: len,
+			}),
+			info: TrackInfo {
+				duration: Duration::from_secs_f64(len as f64 / sample_rate),
+				..Default::default()
+			},
+		}
+	}
+
+	fn render_all(renderer: &mut Renderer) -> (Vec<[f32; 2]>, Vec<(usize, RenderEvent)>) {
+		let mut rendered = vec![];
+		let mut events = vec![];
+		let mut buf = vec![[0.0; 2]; 300];
+		loop {
+			let mut chunk_events = vec![];
+			let n = renderer.render(&mut buf, &mut chunk_events);
+			events.extend(
+				chunk_events
+					.into_iter()
+					.map(|(at, e)| (rendered.len() + at, e)),
+			);
+			rendered.extend_from_slice(&buf[..n]);
+			if n < buf.len() {
+				return (rendered, events);
+			}
+		}
+	}
+
+	#[test]
+	fn test_gapless() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.queue(constant(48000.0, 0.5, 10000));
+		renderer.queue(constant(48000.0, 0.5, 7777));
+
+		let (rendered, events) = render_all(&mut renderer);
+
+		let audible = |f: &[f32; 2]| f[0] > 0.25 && f[1] > 0.25;
+		let start = rendered.iter().position(audible).unwrap();
+		let end = rendered.iter().rposition(audible).unwrap();
+		assert!(rendered[start..=end].iter().all(audible));
+		assert!((end - start + 1).abs_diff(17777) < 8);
+
+		let events = events
+			.into_iter()
+			.filter(|(_, e)| !matches!(e, RenderEvent::Waveform(_)))
+			.collect::<Vec<_>>();
+		let kinds = events
+			.iter()
+			.map(|(_, e)| matches!(e, RenderEvent::TrackStart(_)))
+			.collect::<Vec<_>>();
+		assert_eq!(kinds, [true, false, true, false]);
+		assert!(events[1].0.abs_diff(10000) < 300);
+		assert_eq!(events[1].0, events[2].0);
+		assert!(renderer.is_idle());
+	}
+
+	#[test]
+	fn test_varispeed() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.set_speed(2.0).unwrap();
+		renderer.queue(constant(44100.0, 0.5, 44100));
+		renderer.queue(constant(44100.0, 0.5, 44100));
+
+		// 1.5s of the sources
+		let mut rendered = vec![[0.0; 2]; 36000];
+		assert_eq!(renderer.render(&mut rendered, &mut vec![]), 36000);
+		// slowing down in the middle of the second track
+		renderer.set_speed(0.5).unwrap();
+		let (rest, _) = render_all(&mut renderer);
+		rendered.extend(rest);
+
+		// what was already resampled when the speed changed still plays at the previous speed
+		let len = rendered.iter().filter(|f| f[0] > 0.25).count();
+		assert!(len.abs_diff(36000 + 48000) < 2000, "{len}");
+	}
+
+	#[test]
+	fn test_crossfade() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.set_crossfade(Crossfade {
+			duration: Duration::from_millis(500),
+			curve: crate::player::FadeCurve::Linear,
+		});
+		renderer.queue(constant(48000.0, 1.0, 48000));
+		renderer.queue(constant(44100.0, -1.0, 44100));
+
+		let (rendered, events) = render_all(&mut renderer);
+		let events = events
+			.into_iter()
+			.filter(|(_, e)| !matches!(e, RenderEvent::Loudness(_) | RenderEvent::Waveform(_)))
+			.collect::<Vec<_>>();
+
+		// the second track starts fading in half a second before the end of the first one
+		let (start, _) = events[1];
+		assert!(start.abs_diff(24000) < 300);
+		assert!(matches!(events[1].1, RenderEvent::TrackEnd));
+		assert!(matches!(events[2], (at, RenderEvent::TrackStart(_)) if at == start));
+		let latency = 200;
+		assert!((rendered[start - latency][0] - 1.0).abs() < 0.01);
+		assert!(rendered[start + 12000][0].abs() < 0.05);
+		assert!((rendered[48000 + latency][0] + 1.0).abs() < 0.01);
+		// both tracks play in full
+		assert!((rendered.len() as i64 - 72000).abs() < 1000);
+	}
+
+	#[test]
+	fn test_fingerprint() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.queue(constant(48000.0, 0.5, 48000 * 15));
+		let mut known = constant(48000.0, 0.5, 48000 * 15);
+		known.info.fingerprinted = true;
+		renderer.queue(known);
+
+		let (_, events) = render_all(&mut renderer);
+		let fingerprints = events
+			.iter()
+			.filter(|(_, e)| matches!(e, RenderEvent::Fingerprint(_)))
+			.count();
+		assert_eq!(fingerprints, 1);
+	}
+
+	#[test]
+	fn test_crossfade_loudness() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.set_crossfade(Crossfade {
+			duration: Duration::from_millis(500),
+			curve: crate::player::FadeCurve::Linear,
+		});
+		for _ in 0..2 {
+			renderer.queue(TrackSource {
+				sample_rate: 48000.0,
+				signal: Box::new(Sine {
+					position: 0,
+					len: 96000,
+				}),
+				info: TrackInfo {
+					duration: Duration::from_secs(2),
+					..Default::default()
+				},
+			});
+		}
+
+		// the first track ends as the crossfade starts, before it was read in full
+		let (_, events) = render_all(&mut renderer);
+		let kinds = events
+			.iter()
+			.filter_map(|(_, e)| match e {
+				RenderEvent::Loudness(_) => Some("loudness"),
+				RenderEvent::TrackEnd => Some("end"),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(kinds, ["end", "loudness", "end"]);
+	}
+
+	#[test]
+	fn test_normalization() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.set_normalization(Normalization::Track);
+		let mut source = constant(48000.0, 0.5, 48000);
+		// 20dB louder than the target
+		source.info.loudness = Some(Loudness {
+			integrated: 2.0,
+			true_peak: 0.0,
+		});
+		renderer.queue(source);
+
+		let (rendered, _) = render_all(&mut renderer);
+		assert!((rendered[24000][0] - 0.05).abs() < 0.001);
+	}
+
+	#[test]
+	fn test_loop() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.queue(TrackSource {
+			sample_rate: 48000.0,
+			signal: Box::new(Sine {
+				position: 0,
+				len: 96000,
+			}),
+			info: TrackInfo {
+				duration: Duration::from_secs(2),
+				..Default::default()
+			},
+		});
+		let mut events = vec![];
+		renderer.render(&mut vec![[0.0; 2]; 12000], &mut events);
+		// 50 periods of the sine, it loops without a click
+		renderer
+			.set_loop(Some(Duration::from_millis(500)..Duration::from_secs(1)))
+			.unwrap();
+
+		// it goes on past the end of the track
+		let mut rendered = vec![[0.0; 2]; 144000];
+		assert_eq!(renderer.render(&mut rendered, &mut events), 144000);
+		let loops = events
+			.iter()
+			.filter_map(|(_, e)| match e {
+				RenderEvent::Looped(landed) => Some(*landed),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		assert_eq!(loops, [Duration::from_millis(500); 5]);
+		let max_step = rendered
+			.windows(2)
+			.map(|w| (w[1][0] - w[0][0]).abs())
+			.fold(0.0, f32::max);
+		assert!(max_step < 0.01, "{max_step}");
+	}
+
+	#[test]
+	fn test_loop_behind() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.queue(TrackSource {
+			sample_rate: 48000.0,
+			signal: Box::new(Sine {
+				position: 0,
+				len: 96000,
+			}),
+			info: TrackInfo {
+				duration: Duration::from_secs(2),
+				..Default::default()
+			},
+		});
+		let mut events = vec![];
+		renderer.render(&mut vec![[0.0; 2]; 60000], &mut events);
+		// already read past the end of the loop
+		let landed = renderer
+			.set_loop(Some(Duration::from_millis(500)..Duration::from_secs(1)))
+			.unwrap();
+		assert_eq!(landed, Some(Duration::from_millis(500)));
+
+		events.clear();
+		renderer.render(&mut vec![[0.0; 2]; 36000], &mut events);
+		let loops = events
+			.iter()
+			.filter(|(_, e)| matches!(e, RenderEvent::Looped(_)))
+			.count();
+		assert_eq!(loops, 1);
+	}
+
+	#[test]
+	fn test_source_error() {
+		let mut renderer = Renderer::new(48000.0);
+		renderer.queue(TrackSource {
+			sample_rate: 48000.0,
+			signal: Box::new(Failing { remaining: 5000 }),
+			info: TrackInfo {
+				duration: Duration::from_secs(10),
+				..Default::default()
+			},
+		});
+		renderer.queue(constant(48000.0, -0.5, 10000));
+
+		let (rendered, events) = render_all(&mut renderer);
+
+		// the failing track is skipped and the next one plays in full
+		let kinds = events
+			.iter()
+			.map(|(_, e)| match e {
+				RenderEvent::TrackStart(_) => "start",
+				RenderEvent::TrackEnd => "end",
+				RenderEvent::SourceError(info, _) => {
+					assert_eq!(info.duration, Duration::from_secs(10));
+					"error"
+				}
+				RenderEvent::Loudness(_) => "loudness",
+				RenderEvent::Waveform(_) => "waveform",
+				RenderEvent::Fingerprint(_) => "fingerprint",
+				RenderEvent::Looped(_) => "looped",
+			})
+			.collect::<Vec<_>>();
+		// only the track that was read in full has its waveform
+		assert_eq!(kinds, ["start", "error", "end", "start", "waveform", "end"]);
+		let next = rendered.iter().filter(|f| f[0] < -0.25).count();
+		assert!(next.abs_diff(10000) < 8);
+		assert!(renderer.is_idle());
+	}
+}
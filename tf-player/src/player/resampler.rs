@@ -1,21 +1,21 @@
 use anyhow::Result;
 use rubato::{InterpolationParameters, Resampler as _, SincFixedOut};
 
-use crate::{SourceError, TrackSource};
-
 pub struct Resampler {
 	pub resampler: SincFixedOut<f32>,
 	pub source_buf: Vec<[f32; 2]>,
 	pub in_buf: Vec<Vec<f32>>,
 	pub out_buf: Vec<Vec<f32>>,
 	pub i: usize,
+	pub ratio: f64,
 }
 
 impl Resampler {
 	pub fn new(ratio: f64) -> Result<Self> {
 		let resampler = SincFixedOut::new(
 			ratio,
-			2.0,
+			// room for the playback speed to go from 0.5x to 2x after the creation
+			4.0,
 			InterpolationParameters {
 				sinc_len: 256,
 				f_cutoff: 0.95,
@@ -28,18 +28,31 @@ impl Resampler {
 		)
 		.unwrap();
 
+		let out_buf = resampler.output_buffer_allocate();
 		Ok(Self {
 			source_buf: vec![[0.0; 2]; resampler.input_frames_max()],
 			in_buf: resampler.input_buffer_allocate(),
-			out_buf: resampler.output_buffer_allocate(),
+			i: out_buf[0].len(),
+			out_buf,
 			resampler,
-			i: 0,
+			ratio,
 		})
 	}
 
-	pub fn process(&mut self, source: &mut TrackSource) -> Result<(), SourceError> {
+	// Changes the ratio without dropping what is being resampled
+	pub fn set_ratio(&mut self, ratio: f64) -> Result<()> {
+		self.resampler.set_resample_ratio(ratio)?;
+		self.ratio = ratio;
+		Ok(())
+	}
+
+	pub fn input_frames_next(&self) -> usize {
+		self.resampler.input_frames_next()
+	}
+
+	// Resamples the first `input_frames_next()` frames of `source_buf` into `out_buf`.
+	pub fn process(&mut self) {
 		let in_len = self.resampler.input_frames_next();
-		source.signal.next(&mut self.source_buf[..in_len])?;
 
 		self.in_buf[0].clear();
 		self.in_buf[1].clear();
@@ -54,6 +67,9 @@ impl Resampler {
 			.process_into_buffer(&self.in_buf, &mut self.out_buf, Some(&[true, true]))
 			.unwrap();
 		self.i = 0;
-		Ok(())
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.i >= self.out_buf[0].len()
 	}
 }
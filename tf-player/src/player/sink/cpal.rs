@@ -0,0 +1,322 @@
+use std::{
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use cpal::{
+	traits::{DeviceTrait, HostTrait, StreamTrait},
+	Device, SampleFormat, SampleRate, Stream, StreamConfig, StreamError,
+};
+use rtrb::{Consumer, Producer};
+use tracing::{debug, error, warn};
+
+use super::{Backend, Sink, BUFFER_FRAMES};
+
+/// A system output device, the default one if `device` is None or can't be found.
+#[derive(Debug, Clone, Default)]
+pub struct Cpal {
+	pub device: Option<String>,
+}
+
+pub struct CpalSink {
+	stream: Stream,
+	config: StreamConfig,
+	producer: Producer<f32>,
+	playing: bool,
+	// set from the audio thread when the device goes away
+	lost: Arc<AtomicBool>,
+	fade: Arc<Fade>,
+}
+
+// Shared with the audio thread, which ramps the output down before the stream is paused
+// and back up once it plays
+#[derive(Default)]
+struct Fade {
+	playing: AtomicBool,
+	frames: AtomicUsize,
+}
+
+// The audio thread may only get to the end of the ramp on its next callback
+const FADE_MARGIN: Duration = Duration::from_millis(20);
+
+/// Lists the names of the available output devices.
+pub fn output_devices() -> Result<Vec<String>> {
+	Ok(cpal::default_host()
+		.output_devices()?
+		.filter_map(|device| device.name().ok())
+		.collect())
+}
+
+fn find_device(name: Option<&str>) -> Result<Device> {
+	let host = cpal::default_host();
+	if let Some(name) = name {
+		match host
+			.output_devices()?
+			.find(|device| device.name().ok().as_deref() == Some(name))
+		{
+			Some(device) => return Ok(device),
+			None => warn!("output device {name:?} not found, using the default one"),
+		}
+	}
+	host.default_output_device()
+		.ok_or(anyhow!("no output device available"))
+}
+
+// The output is f32, on a stereo config of the device if it has one. The player resamples to
+// whatever rate it runs at.
+fn output_config(device: &Device) -> Result<StreamConfig> {
+	let rate = device.default_output_config()?.sample_rate().0;
+	let configs = device
+		.supported_output_configs()?
+		.filter(|c| c.sample_format() == SampleFormat::F32)
+		.collect::<Vec<_>>();
+	let best = configs
+		.iter()
+		.min_by_key(|c| {
+			let supports_rate = (c.min_sample_rate().0..=c.max_sample_rate().0).contains(&rate);
+			(c.channels() != 2, !supports_rate)
+		})
+		.ok_or(anyhow!("the output device has no f32 config"))?;
+	let rate = rate.clamp(best.min_sample_rate().0, best.max_sample_rate().0);
+	Ok(best.clone().with_sample_rate(SampleRate(rate)).config())
+}
+
+impl Backend for Cpal {
+	type Sink = CpalSink;
+
+	fn open(self) -> Result<CpalSink> {
+		CpalSink::new(self.device.as_deref(), 0)
+	}
+}
+
+impl CpalSink {
+	fn new(device: Option<&str>, ramp: usize) -> Result<Self> {
+		let device = find_device(device)?;
+
+		let config = output_config(&device)?;
+		let channels = config.channels as usize;
+
+		let (producer, mut consumer) = rtrb::RingBuffer::new(BUFFER_FRAMES * 2);
+
+		let lost = Arc::new(AtomicBool::new(false));
+		let stream_lost = lost.clone();
+		let fade = Arc::new(Fade::default());
+		fade.frames.store(ramp, Ordering::Relaxed);
+		let stream_fade = fade.clone();
+		let mut gain = 0.0;
+		let stream = device.build_output_stream(
+			&config.clone(),
+			move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+				process(&mut consumer, data, channels, &stream_fade, &mut gain);
+			},
+			move |err| {
+				error!("{err:?}");
+				if let StreamError::DeviceNotAvailable = err {
+					stream_lost.store(true, Ordering::Relaxed);
+				}
+			},
+		)?;
+
+		debug!(
+			"created stream for the audio driver {:?}... {:?}",
+			device.name(),
+			config
+		);
+
+		stream.pause()?;
+
+		Ok(CpalSink {
+			stream,
+			config,
+			producer,
+			playing: false,
+			lost,
+			fade,
+		})
+	}
+}
+
+impl Sink for CpalSink {
+	fn sample_rate(&self) -> u32 {
+		self.config.sample_rate.0
+	}
+
+	fn available(&mut self) -> usize {
+		self.producer.slots() / 2
+	}
+
+	fn write(&mut self, frames: &[[f32; 2]]) -> Result<()> {
+		for frame in frames {
+			self.producer.push(frame[0])?;
+			self.producer.push(frame[1])?;
+		}
+		Ok(())
+	}
+
+	fn play(&mut self) -> Result<()> {
+		self.fade.playing.store(true, Ordering::Relaxed);
+		self.stream.play()?;
+		self.playing = true;
+		Ok(())
+	}
+
+	// The stream is only paused once the output faded out
+	fn pause(&mut self) -> Result<()> {
+		self.fade.playing.store(false, Ordering::Relaxed);
+		if self.playing {
+			let frames = self.fade.frames.load(Ordering::Relaxed);
+			let ramp = frames as f64 / self.sample_rate() as f64;
+			std::thread::sleep(Duration::from_secs_f64(ramp) + FADE_MARGIN);
+		}
+		self.stream.pause()?;
+		self.playing = false;
+		Ok(())
+	}
+
+	fn set_ramp(&mut self, frames: usize) {
+		self.fade.frames.store(frames, Ordering::Relaxed);
+	}
+
+	fn set_device(&mut self, device: Option<&str>) -> Result<()> {
+		let playing = self.playing;
+		let ramp = self.fade.frames.load(Ordering::Relaxed);
+		*self = CpalSink::new(device, ramp)?;
+		if playing {
+			self.play()?;
+		}
+		Ok(())
+	}
+
+	fn device_lost(&self) -> bool {
+		self.lost.load(Ordering::Relaxed)
+	}
+}
+
+// Ramps `gain` towards 1 while playing and towards 0 while paused. Nothing is read once it is
+// silent, so that the output picks up where it faded out.
+fn process(
+	consumer: &mut Consumer<f32>,
+	data: &mut [f32],
+	channels: usize,
+	fade: &Fade,
+	gain: &mut f32,
+) {
+	let playing = fade.playing.load(Ordering::Relaxed);
+	let target = if playing { 1.0 } else { 0.0 };
+	let ramping = *gain != target;
+	if !ramping && !playing {
+		data.fill(0.0);
+		return;
+	}
+	if !ramping && channels == 2 {
+		let available = consumer.slots().min(data.len());
+		let chunk = consumer.read_chunk(available).unwrap();
+		let (first, second) = chunk.as_slices();
+		data[..first.len()].copy_from_slice(first);
+		data[first.len()..available].copy_from_slice(second);
+		chunk.commit_all();
+		data[available..].fill(0.0);
+		return;
+	}
+	let step = 1.0 / (fade.frames.load(Ordering::Relaxed) + 1) as f32;
+	let mut frames = data.chunks_exact_mut(channels);
+	for frame in &mut frames {
+		if ramping {
+			*gain = if playing {
+				(*gain + step).min(1.0)
+			} else {
+				(*gain - step).max(0.0)
+			};
+		}
+		if *gain == 0.0 {
+			frame.fill(0.0);
+			continue;
+		}
+		let left = consumer.pop().unwrap_or(0.0) * *gain;
+		let right = consumer.pop().unwrap_or(0.0) * *gain;
+		write_frame(frame, left, right);
+	}
+	frames.into_remainder().fill(0.0);
+}
+
+// Stereo goes to the first two channels of the device, a mono one gets both mixed
+fn write_frame(frame: &mut [f32], left: f32, right: f32) {
+	match frame {
+		[] => {}
+		[mono] => *mono = (left + right) / 2.0,
+		[l, r, rest @ ..] => {
+			*l = left;
+			*r = right;
+			rest.fill(0.0);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn max_step(samples: &[f32]) -> f32 {
+		samples
+			.windows(2)
+			.map(|w| (w[1] - w[0]).abs())
+			.fold(0.0, f32::max)
+	}
+
+	#[test]
+	fn test_fade() {
+		let (mut producer, mut consumer) = rtrb::RingBuffer::new(4000);
+		while producer.push(1.0).is_ok() {}
+		let fade = Fade::default();
+		fade.frames.store(100, Ordering::Relaxed);
+		let mut gain = 0.0;
+		let mut out = vec![];
+
+		fade.playing.store(true, Ordering::Relaxed);
+		let mut data = vec![0.0; 400];
+		process(&mut consumer, &mut data, 2, &fade, &mut gain);
+		out.extend_from_slice(&data);
+		assert_eq!(gain, 1.0);
+
+		fade.playing.store(false, Ordering::Relaxed);
+		process(&mut consumer, &mut data, 2, &fade, &mut gain);
+		out.extend_from_slice(&data);
+		assert_eq!(gain, 0.0);
+		assert!(max_step(&out) < 0.011, "{}", max_step(&out));
+		assert_eq!(*out.last().unwrap(), 0.0);
+
+		// what comes after the fade out is kept for later
+		let slots = consumer.slots();
+		process(&mut consumer, &mut data, 2, &fade, &mut gain);
+		assert!(data.iter().all(|s| *s == 0.0));
+		assert_eq!(consumer.slots(), slots);
+	}
+
+	#[test]
+	fn test_channels() {
+		let (mut producer, mut consumer) = rtrb::RingBuffer::new(64);
+		for _ in 0..16 {
+			producer.push(0.5).unwrap();
+			producer.push(-0.5).unwrap();
+		}
+		let fade = Fade::default();
+		fade.playing.store(true, Ordering::Relaxed);
+		let mut gain = 1.0;
+
+		let mut data = vec![1.0; 4 * 6];
+		process(&mut consumer, &mut data, 6, &fade, &mut gain);
+		for frame in data.chunks(6) {
+			assert_eq!(frame, [0.5, -0.5, 0.0, 0.0, 0.0, 0.0]);
+		}
+
+		let mut data = vec![1.0; 4];
+		process(&mut consumer, &mut data, 1, &fade, &mut gain);
+		assert_eq!(data, [0.0; 4]);
+		// one stereo frame is read for each frame of the device
+		assert_eq!(consumer.slots(), 32 - 2 * 4 - 2 * 4);
+	}
+}
@@ -0,0 +1,49 @@
+use anyhow::{anyhow, Result};
+
+mod cpal;
+pub use self::cpal::{output_devices, Cpal, CpalSink};
+
+mod null;
+pub use null::{Null, NullSink};
+
+mod wav;
+pub use wav::{Wav, WavSink};
+
+/// Number of frames the player renders ahead of the output
+pub const BUFFER_FRAMES: usize = 22050;
+
+/// Where the player sends its output.
+/// The sink is opened on the decoder thread, as some of them can't be moved across threads.
+pub trait Backend: Send + 'static {
+	type Sink: Sink;
+
+	fn open(self) -> Result<Self::Sink>;
+}
+
+pub trait Sink {
+	fn sample_rate(&self) -> u32;
+
+	/// Returns how many frames can be written right now.
+	fn available(&mut self) -> usize;
+
+	fn write(&mut self, frames: &[[f32; 2]]) -> Result<()>;
+
+	fn play(&mut self) -> Result<()>;
+
+	fn pause(&mut self) -> Result<()>;
+
+	/// How many frames the output takes to fade out when paused, and back in when played.
+	/// Outputs that are never heard don't need to fade.
+	fn set_ramp(&mut self, _frames: usize) {}
+
+	/// Moves the output to another device, None being the default one.
+	/// What was written but not played yet is dropped.
+	fn set_device(&mut self, _device: Option<&str>) -> Result<()> {
+		Err(anyhow!("this output has no devices"))
+	}
+
+	/// Returns true once the device went away, the player then moves to the default one.
+	fn device_lost(&self) -> bool {
+		false
+	}
+}
@@ -0,0 +1,72 @@
+use std::time::Instant;
+
+use anyhow::Result;
+
+use super::{Backend, Sink, BUFFER_FRAMES};
+
+/// Discards the output. When `realtime` is false, it takes it as fast as the player can render it.
+pub struct Null {
+	pub sample_rate: u32,
+	pub realtime: bool,
+}
+
+pub struct NullSink {
+	sample_rate: u32,
+	realtime: bool,
+	playing: bool,
+	// frames that would still be waiting to be played
+	buffered: f64,
+	last: Instant,
+}
+
+impl Backend for Null {
+	type Sink = NullSink;
+
+	fn open(self) -> Result<NullSink> {
+		Ok(NullSink {
+			sample_rate: self.sample_rate,
+			realtime: self.realtime,
+			playing: false,
+			buffered: 0.0,
+			last: Instant::now(),
+		})
+	}
+}
+
+impl Sink for NullSink {
+	fn sample_rate(&self) -> u32 {
+		self.sample_rate
+	}
+
+	fn available(&mut self) -> usize {
+		if !self.realtime {
+			return BUFFER_FRAMES;
+		}
+		let now = Instant::now();
+		if self.playing {
+			let played = (now - self.last).as_secs_f64() * self.sample_rate as f64;
+			self.buffered = (self.buffered - played).max(0.0);
+		}
+		self.last = now;
+		BUFFER_FRAMES - self.buffered.ceil() as usize
+	}
+
+	fn write(&mut self, frames: &[[f32; 2]]) -> Result<()> {
+		if self.realtime {
+			self.buffered += frames.len() as f64;
+		}
+		Ok(())
+	}
+
+	fn play(&mut self) -> Result<()> {
+		self.available();
+		self.playing = true;
+		Ok(())
+	}
+
+	fn pause(&mut self) -> Result<()> {
+		self.available();
+		self.playing = false;
+		Ok(())
+	}
+}
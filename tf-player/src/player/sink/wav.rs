@@ -0,0 +1,62 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use anyhow::Result;
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use super::{Backend, Sink, BUFFER_FRAMES};
+
+/// Renders the output to a 32-bit float WAV file, as fast as possible.
+pub struct Wav {
+	pub path: PathBuf,
+	pub sample_rate: u32,
+}
+
+pub struct WavSink {
+	writer: WavWriter<BufWriter<File>>,
+	sample_rate: u32,
+}
+
+impl Backend for Wav {
+	type Sink = WavSink;
+
+	fn open(self) -> Result<WavSink> {
+		let spec = WavSpec {
+			channels: 2,
+			sample_rate: self.sample_rate,
+			bits_per_sample: 32,
+			sample_format: SampleFormat::Float,
+		};
+		Ok(WavSink {
+			writer: WavWriter::create(self.path, spec)?,
+			sample_rate: self.sample_rate,
+		})
+	}
+}
+
+impl Sink for WavSink {
+	fn sample_rate(&self) -> u32 {
+		self.sample_rate
+	}
+
+	fn available(&mut self) -> usize {
+		BUFFER_FRAMES
+	}
+
+	fn write(&mut self, frames: &[[f32; 2]]) -> Result<()> {
+		for frame in frames {
+			self.writer.write_sample(frame[0])?;
+			self.writer.write_sample(frame[1])?;
+		}
+		// keeps the header up to date, the player thread is never joined
+		self.writer.flush()?;
+		Ok(())
+	}
+
+	fn play(&mut self) -> Result<()> {
+		Ok(())
+	}
+
+	fn pause(&mut self) -> Result<()> {
+		Ok(())
+	}
+}
@@ -1,7 +1,8 @@
-use std::time::Duration;
+use std::{ops::Range, time::Duration};
 
 use anyhow::{anyhow, Result};
 
+use super::Readiness;
 use crate::TrackInfo;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -16,6 +17,14 @@ pub struct Playing {
 	pub track: TrackInfo,
 	pub offset: Duration,
 	pub paused: bool,
+	/// How far along the prefetching of the upcoming tracks is, by key
+	pub upcoming: Vec<(String, Readiness)>,
+	/// Some while the track waits for its source, the offset doesn't move in the meantime
+	pub buffering: Option<f32>,
+	/// The parts of the track that are loaded, for streamed tracks
+	pub buffered: Vec<Range<Duration>>,
+	/// The part of the track that plays over and over, if any
+	pub looping: Option<Range<Duration>>,
 }
 
 impl Default for State {
@@ -51,6 +60,10 @@ impl State {
 			track,
 			offset: Duration::ZERO,
 			paused: false,
+			upcoming: vec![],
+			buffering: None,
+			buffered: vec![],
+			looping: None,
 		});
 	}
 
@@ -0,0 +1,220 @@
+use std::{collections::VecDeque, f32::consts::PI};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SpeedMode {
+	// changes the tempo and the pitch together, like a turntable
+	Varispeed,
+	#[default]
+	PreservePitch,
+}
+
+// Length of the segments that are overlap-added, in seconds
+const SEGMENT_LEN: f64 = 0.04;
+
+// Changes the tempo without changing the pitch, with WSOLA (waveform similarity overlap-add).
+// Windowed segments of the input are overlap-added every half segment in the output, while being
+// taken every `speed` half segments in the input. Each one is moved by up to a quarter segment
+// around its nominal position, to the place that best continues the previous one.
+pub struct Stretcher {
+	speed: f64,
+	window: Vec<f32>,
+	tolerance: usize,
+	input: Vec<[f32; 2]>,
+	// nominal start of the next segment in `input`
+	position: f64,
+	// where the previous segment would naturally continue in `input`
+	continuation: Option<usize>,
+	// the second half of the previous windowed segment, waiting for the next one
+	tail: Vec<[f32; 2]>,
+	output: VecDeque<[f32; 2]>,
+}
+
+impl Stretcher {
+	pub fn new(sample_rate: f64) -> Self {
+		let len = (sample_rate * SEGMENT_LEN) as usize & !1;
+		Self {
+			speed: 1.0,
+			window: (0..len)
+				.map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / len as f32).cos())
+				.collect(),
+			tolerance: len / 4,
+			input: vec![],
+			position: 0.0,
+			continuation: None,
+			tail: vec![[0.0; 2]; len / 2],
+			output: VecDeque::new(),
+		}
+	}
+
+	// At a speed of 1, the audio doesn't go through the stretcher.
+	pub fn is_active(&self) -> bool {
+		self.speed != 1.0
+	}
+
+	pub fn set_speed(&mut self, speed: f64) {
+		if (speed == 1.0) != (self.speed == 1.0) {
+			self.clear();
+		}
+		self.speed = speed;
+	}
+
+	pub fn clear(&mut self) {
+		self.input.clear();
+		self.position = 0.0;
+		self.continuation = None;
+		self.tail.fill([0.0; 2]);
+		self.output.clear();
+	}
+
+	// Number of frames ready to be pulled
+	pub fn available(&self) -> usize {
+		self.output.len()
+	}
+
+	pub fn push(&mut self, frames: &[[f32; 2]]) {
+		self.input.extend_from_slice(frames);
+		let (len, hop) = (self.window.len(), self.window.len() / 2);
+
+		loop {
+			let nominal = self.position.round() as usize;
+			if self.input.len() < nominal + self.tolerance + len {
+				break;
+			}
+			let start = match self.continuation {
+				Some(continuation) => self.best_match(continuation, nominal),
+				None => nominal,
+			};
+
+			let segment = &self.input[start..start + len];
+			for (i, (frame, w)) in segment.iter().zip(&self.window).enumerate() {
+				let frame = [frame[0] * w, frame[1] * w];
+				if i < hop {
+					let tail = self.tail[i];
+					self.output
+						.push_back([tail[0] + frame[0], tail[1] + frame[1]]);
+				} else {
+					self.tail[i - hop] = frame;
+				}
+			}
+			self.continuation = Some(start + hop);
+			self.position += hop as f64 * self.speed;
+		}
+
+		// drop the input that can't be used anymore
+		let used = (self.position as usize)
+			.saturating_sub(self.tolerance)
+			.min(self.continuation.unwrap_or(0));
+		self.input.drain(..used);
+		self.position -= used as f64;
+		self.continuation = self.continuation.map(|c| c - used);
+	}
+
+	// Plays out what is left of the input, once there is nothing more to come.
+	pub fn flush(&mut self) {
+		if self.input.is_empty() {
+			return;
+		}
+		let padding = self.tolerance + self.window.len();
+		self.push(&vec![[0.0; 2]; padding]);
+		self.output.extend(self.tail.iter().copied());
+		let output = std::mem::take(&mut self.output);
+		self.clear();
+		self.output = output;
+	}
+
+	// Fills the beginning of `out` with the ready frames, returns how many were written.
+	pub fn pull(&mut self, out: &mut [[f32; 2]]) -> usize {
+		let len = out.len().min(self.output.len());
+		for (frame, stretched) in out.iter_mut().zip(self.output.drain(..len)) {
+			*frame = stretched;
+		}
+		len
+	}
+
+	// Finds the segment start around `nominal` that looks the most like the input following `continuation`.
+	fn best_match(&self, continuation: usize, nominal: usize) -> usize {
+		let hop = self.window.len() / 2;
+		let mono = |i: usize| self.input[i][0] + self.input[i][1];
+		let first = nominal.saturating_sub(self.tolerance);
+		let (mut best, mut best_score) = (nominal, f32::NEG_INFINITY);
+		for start in first..=nominal + self.tolerance {
+			let (mut correlation, mut energy) = (0.0, 0.0);
+			// every other frame is enough to compare the waveforms
+			for i in (0..hop).step_by(2) {
+				let x = mono(start + i);
+				correlation += x * mono(continuation + i);
+				energy += x * x;
+			}
+			let score = correlation / (energy + f32::EPSILON).sqrt();
+			if score > best_score {
+				(best, best_score) = (start, score);
+			}
+		}
+		best
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	const SAMPLE_RATE: f64 = 48000.0;
+
+	fn sine(freq: f32, len: usize) -> Vec<[f32; 2]> {
+		(0..len)
+			.map(|i| {
+				let s = 0.5 * (2.0 * PI * freq * i as f32 / SAMPLE_RATE as f32).sin();
+				[s, s]
+			})
+			.collect()
+	}
+
+	fn stretch(speed: f64, input: &[[f32; 2]]) -> Vec<[f32; 2]> {
+		let mut stretcher = Stretcher::new(SAMPLE_RATE);
+		stretcher.set_speed(speed);
+		for chunk in input.chunks(1000) {
+			stretcher.push(chunk);
+		}
+		stretcher.flush();
+		let mut output = vec![[0.0; 2]; stretcher.available()];
+		stretcher.pull(&mut output);
+		output
+	}
+
+	// Frequency of a signal, from its rate of zero crossings
+	fn frequency(frames: &[[f32; 2]]) -> f32 {
+		let crossings = frames
+			.windows(2)
+			.filter(|w| (w[0][0] < 0.0) != (w[1][0] < 0.0))
+			.count();
+		crossings as f32 / 2.0 / (frames.len() as f32 / SAMPLE_RATE as f32)
+	}
+
+	#[test]
+	fn test_duration() {
+		let input = sine(440.0, SAMPLE_RATE as usize);
+		for speed in [0.5, 0.8, 1.25, 2.0] {
+			let output = stretch(speed, &input);
+			let expected = input.len() as f64 / speed;
+			// the flush adds at most a segment and a half of silence
+			let error = output.len() as f64 - expected;
+			assert!(
+				(-0.01 * SAMPLE_RATE..0.07 * SAMPLE_RATE).contains(&error),
+				"{speed}: {} frames",
+				output.len()
+			);
+		}
+	}
+
+	#[test]
+	fn test_pitch() {
+		let input = sine(440.0, SAMPLE_RATE as usize * 2);
+		for speed in [0.5, 0.75, 1.5] {
+			let output = stretch(speed, &input);
+			// leave out the fade in and the padding
+			let len = output.len();
+			let freq = frequency(&output[len / 10..len * 8 / 10]);
+			assert!((freq - 440.0).abs() < 440.0 * 0.02, "{speed}: {freq}Hz");
+		}
+	}
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use crate::{SourceError, TrackSource};
+
+// Number of buckets in a waveform, whatever the duration of the track
+pub const WAVEFORM_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bucket {
+	pub min: f32,
+	pub max: f32,
+	pub rms: f32,
+}
+
+// A summary of a track to draw it, its buckets are evenly spread over the duration of the track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+	pub buckets: Vec<Bucket>,
+}
+
+impl Waveform {
+	/// Reads the whole source, meant to be called in the background.
+	pub fn compute(mut source: TrackSource) -> Result<Waveform, SourceError> {
+		let mut builder = WaveformBuilder::new(source.sample_rate, source.info.duration);
+		let mut buf = vec![[0.0; 2]; 4096];
+		loop {
+			let read = match source.signal.next(&mut buf) {
+				Err(SourceError::EndOfStream) => 0,
+				r => r?,
+			};
+			builder.process(&buf[..read]);
+			if read < buf.len() {
+				return Ok(builder.finish());
+			}
+		}
+	}
+}
+
+const EMPTY: (Bucket, f32, usize) = (
+	Bucket {
+		min: f32::INFINITY,
+		max: f32::NEG_INFINITY,
+		rms: 0.0,
+	},
+	0.0,
+	0,
+);
+
+// Builds the waveform of a track as it is read. Buckets that were skipped by seeking stay empty.
+pub struct WaveformBuilder {
+	frames_per_bucket: f64,
+	position: usize,
+	buckets: Vec<Bucket>,
+	// the bucket being filled, with the sum of squares and number of frames so far
+	bucket: usize,
+	current: (Bucket, f32, usize),
+	complete: bool,
+	changed: bool,
+}
+
+impl WaveformBuilder {
+	pub fn new(sample_rate: f64, duration: Duration) -> Self {
+		Self {
+			frames_per_bucket: (duration.as_secs_f64() * sample_rate / WAVEFORM_LEN as f64)
+				.max(1.0),
+			position: 0,
+			buckets: vec![Bucket::default(); WAVEFORM_LEN],
+			bucket: 0,
+			current: EMPTY,
+			complete: true,
+			changed: false,
+		}
+	}
+
+	pub fn process(&mut self, frames: &[[f32; 2]]) {
+		for frame in frames {
+			let bucket = (self.position as f64 / self.frames_per_bucket) as usize;
+			if bucket != self.bucket {
+				self.close();
+				self.bucket = bucket;
+			}
+			let sample = (frame[0] + frame[1]) / 2.0;
+			let (current, power, count) = &mut self.current;
+			current.min = current.min.min(sample);
+			current.max = current.max.max(sample);
+			*power += sample * sample;
+			*count += 1;
+			self.position += 1;
+		}
+	}
+
+	// Jumps to another frame, the waveform won't be complete anymore.
+	pub fn seek(&mut self, frame: usize) {
+		self.close();
+		self.position = frame;
+		self.bucket = (frame as f64 / self.frames_per_bucket) as usize;
+		self.complete = false;
+	}
+
+	// Whether every frame was read so far
+	pub fn is_complete(&self) -> bool {
+		self.complete
+	}
+
+	// The waveform so far, if it changed since the last call
+	pub fn snapshot(&mut self) -> Option<Waveform> {
+		std::mem::take(&mut self.changed).then(|| Waveform {
+			buckets: self.buckets.clone(),
+		})
+	}
+
+	pub fn finish(mut self) -> Waveform {
+		self.close();
+		Waveform {
+			buckets: self.buckets,
+		}
+	}
+
+	fn close(&mut self) {
+		let (current, power, count) = std::mem::replace(&mut self.current, EMPTY);
+		if count == 0 {
+			return;
+		}
+		if let Some(bucket) = self.buckets.get_mut(self.bucket) {
+			*bucket = Bucket {
+				rms: (power / count as f32).sqrt(),
+				..current
+			};
+			self.changed = true;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_buckets() {
+		let sample_rate = 48000.0;
+		let mut builder = WaveformBuilder::new(sample_rate, Duration::from_secs(2));
+		// a square wave of amplitude 0.5 for a second, then silence
+		let frames = (0..48000)
+			.map(|i| if i % 100 < 50 { [0.5; 2] } else { [-0.5; 2] })
+			.chain(vec![[0.0; 2]; 48000])
+			.collect::<Vec<_>>();
+		for chunk in frames.chunks(1000) {
+			builder.process(chunk);
+		}
+		assert!(builder.is_complete());
+		let waveform = builder.finish();
+
+		assert_eq!(waveform.buckets.len(), WAVEFORM_LEN);
+		let loud = waveform.buckets[WAVEFORM_LEN / 4];
+		assert_eq!((loud.min, loud.max), (-0.5, 0.5));
+		assert!((loud.rms - 0.5).abs() < 0.01);
+		assert_eq!(waveform.buckets[WAVEFORM_LEN * 3 / 4], Bucket::default());
+	}
+
+	#[test]
+	fn test_seek() {
+		let mut builder = WaveformBuilder::new(1000.0, Duration::from_secs(1024));
+		builder.process(&[[1.0; 2]; 2000]);
+		builder.seek(500_000);
+		builder.process(&[[1.0; 2]; 2000]);
+		assert!(!builder.is_complete());
+
+		let waveform = builder.snapshot().unwrap();
+		assert_eq!(waveform.buckets[1].max, 1.0);
+		assert_eq!(waveform.buckets[2], Bucket::default());
+		assert_eq!(waveform.buckets[500].max, 1.0);
+		assert!(builder.snapshot().is_none());
+	}
+}
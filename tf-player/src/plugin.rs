@@ -1,12 +1,31 @@
 use core::fmt;
-use std::time::Duration;
+use std::{io::Write, ops::Range, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use url::Url;
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::player::{Loudness, Waveform};
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct TrackInfo {
 	pub duration: Duration,
+	// measured on a previous playback, if any
+	pub loudness: Option<Loudness>,
+	pub album_loudness: Option<Loudness>,
+	// computed while playing when unknown, the state then shows what was read so far
+	pub waveform: Option<Arc<Waveform>>,
+	// whether the fingerprint is known already, it is computed from the start of the track otherwise
+	pub fingerprinted: bool,
+	pub cover: Option<Arc<Cover>>,
+	/// Positions marked in the track, to jump to them with `Command::JumpToCue`
+	pub cues: Vec<Duration>,
+}
+
+// Artwork embedded in a track, as it was stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cover {
+	pub media_type: String,
+	pub data: Vec<u8>,
 }
 
 pub struct TrackSource {
@@ -26,18 +45,49 @@ impl fmt::Debug for TrackSource {
 
 #[derive(Debug)]
 pub enum SourceError {
-	General(Box<dyn std::error::Error>),
+	General(Box<dyn std::error::Error + Send + Sync>),
 	EndOfStream,
 }
 
+impl fmt::Display for SourceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SourceError::General(e) => write!(f, "{e}"),
+			SourceError::EndOfStream => write!(f, "end of stream"),
+		}
+	}
+}
+
+impl std::error::Error for SourceError {}
+
 pub trait Source: Send {
-	fn seek(&mut self, pos: Duration) -> Result<(), SourceError>;
+	/// Moves to `pos`, or as close to it as the source can, and returns where it landed.
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError>;
+
+	/// Fills `buf` with the next frames and returns how many were written.
+	/// It is only less than `buf.len()` once the end of the stream is reached.
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError>;
+
+	/// Some when `next` would have to wait for the network, with how much of what is needed
+	/// to go on is loaded, from 0 to 1.
+	fn buffering(&self) -> Option<f32> {
+		None
+	}
 
-	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<(), SourceError>;
+	/// The parts of the track that can be read without waiting, empty when it isn't streamed.
+	fn buffered(&self) -> Vec<Range<Duration>> {
+		vec![]
+	}
 }
 
 pub trait SourcePlugin: Send {
 	fn name(&self) -> &'static str;
 
 	fn handle_url(&self, url: &Url) -> Option<Result<TrackSource>>;
+
+	/// Writes the audio of the track as a file that can be played without the network,
+	/// and returns the extension of that file.
+	fn download(&self, _url: &Url, _writer: &mut dyn Write) -> Option<Result<&'static str>> {
+		None
+	}
 }
@@ -0,0 +1,219 @@
+use std::{collections::HashMap, path::PathBuf, sync::OnceLock, time::SystemTime};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use tracing::{debug, warn};
+
+// A size-bounded cache of downloaded audio on disk, shared by the streaming sources.
+// Entries are parts of a track (HLS segments, byte ranges) keyed by the canonical url of the track,
+// so that they are found again even when the media urls are signed differently on each play.
+// The least recently used entries are evicted once the cache is full. The order of use is
+// restored from the modification times when the cache is opened.
+
+static GLOBAL: OnceLock<DiskCache> = OnceLock::new();
+
+// Entries are written under this extension first, and only renamed once complete
+const TMP_EXTENSION: &str = ".tmp";
+
+/// Sets up the cache used by the sources. Without it, everything is fetched from the network.
+pub fn init(dir: impl Into<PathBuf>, max_size: u64) -> Result<()> {
+	let cache = DiskCache::open(dir, max_size)?;
+	if GLOBAL.set(cache).is_err() {
+		warn!("the disk cache was already set up");
+	}
+	Ok(())
+}
+
+pub fn global() -> Option<&'static DiskCache> {
+	GLOBAL.get()
+}
+
+pub struct DiskCache {
+	dir: PathBuf,
+	max_size: u64,
+	index: Mutex<Index>,
+}
+
+#[derive(Default)]
+struct Index {
+	// file name -> (size, last use)
+	entries: HashMap<String, (u64, u64)>,
+	size: u64,
+	clock: u64,
+}
+
+impl DiskCache {
+	pub fn open(dir: impl Into<PathBuf>, max_size: u64) -> Result<Self> {
+		let dir = dir.into();
+		std::fs::create_dir_all(&dir)?;
+
+		let mut files = vec![];
+		for entry in std::fs::read_dir(&dir)? {
+			let entry = entry?;
+			let metadata = entry.metadata()?;
+			if !metadata.is_file() {
+				continue;
+			}
+			let name = entry.file_name().to_string_lossy().into_owned();
+			if name.ends_with(TMP_EXTENSION) {
+				warn!("removing incomplete cache entry {name}");
+				std::fs::remove_file(entry.path()).ok();
+				continue;
+			}
+			let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+			files.push((modified, name, metadata.len()));
+		}
+		files.sort();
+
+		let mut index = Index::default();
+		for (_, name, size) in files {
+			index.clock += 1;
+			index.size += size;
+			index.entries.insert(name, (size, index.clock));
+		}
+		let cache = Self {
+			dir,
+			max_size,
+			index: Mutex::new(index),
+		};
+		cache.evict(&mut cache.index.lock());
+		Ok(cache)
+	}
+
+	/// Total size of the cached entries, in bytes
+	pub fn size(&self) -> u64 {
+		self.index.lock().size
+	}
+
+	pub fn get(&self, key: &str, part: &str) -> Option<Vec<u8>> {
+		let name = file_name(key, part);
+		let mut index = self.index.lock();
+		index.clock += 1;
+		let clock = index.clock;
+		let (_, last_use) = index.entries.get_mut(&name)?;
+		*last_use = clock;
+		drop(index);
+
+		match std::fs::read(self.dir.join(&name)) {
+			Ok(data) => Some(data),
+			Err(e) => {
+				warn!("failed to read {name} from the cache: {e}");
+				self.remove(&name);
+				None
+			}
+		}
+	}
+
+	// Errors are only logged, the data can always be fetched again.
+	pub fn insert(&self, key: &str, part: &str, data: &[u8]) {
+		let size = data.len() as u64;
+		if size > self.max_size {
+			return;
+		}
+		let name = file_name(key, part);
+		let path = self.dir.join(&name);
+		let tmp_path = self.dir.join(format!("{name}{TMP_EXTENSION}"));
+		let written =
+			std::fs::write(&tmp_path, data).and_then(|()| std::fs::rename(&tmp_path, path));
+		if let Err(e) = written {
+			warn!("failed to write {name} to the cache: {e}");
+			std::fs::remove_file(tmp_path).ok();
+			return;
+		}
+
+		let mut index = self.index.lock();
+		index.clock += 1;
+		let clock = index.clock;
+		if let Some((old, _)) = index.entries.insert(name, (size, clock)) {
+			index.size -= old;
+		}
+		index.size += size;
+		self.evict(&mut index);
+	}
+
+	fn remove(&self, name: &str) {
+		let mut index = self.index.lock();
+		if let Some((size, _)) = index.entries.remove(name) {
+			index.size -= size;
+		}
+		std::fs::remove_file(self.dir.join(name)).ok();
+	}
+
+	fn evict(&self, index: &mut Index) {
+		while index.size > self.max_size {
+			let Some(name) = index
+				.entries
+				.iter()
+				.min_by_key(|(_, (_, last_use))| last_use)
+				.map(|(name, _)| name.clone())
+			else {
+				break;
+			};
+			let (size, _) = index.entries.remove(&name).unwrap();
+			index.size -= size;
+			debug!("evicting {name} from the cache");
+			if let Err(e) = std::fs::remove_file(self.dir.join(&name)) {
+				warn!("failed to evict {name} from the cache: {e}");
+			}
+		}
+	}
+}
+
+// FNV-1a, to get file names that stay the same from one run to the next
+fn file_name(key: &str, part: &str) -> String {
+	let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+		(hash ^ byte as u64).wrapping_mul(0x100000001b3)
+	});
+	format!("{hash:016x}-{part}")
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn temp_dir(name: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("tf-cache-{}-{name}", std::process::id()));
+		std::fs::remove_dir_all(&dir).ok();
+		dir
+	}
+
+	#[test]
+	fn test_lru() {
+		let dir = temp_dir("lru");
+		let cache = DiskCache::open(&dir, 250).unwrap();
+		cache.insert("track a", "0", &[1; 100]);
+		cache.insert("track b", "0", &[2; 100]);
+		// a is now more recent than b
+		assert_eq!(cache.get("track a", "0"), Some(vec![1; 100]));
+		cache.insert("track c", "0", &[3; 100]);
+
+		assert_eq!(cache.get("track b", "0"), None);
+		assert_eq!(cache.get("track a", "0"), Some(vec![1; 100]));
+		assert_eq!(cache.get("track c", "0"), Some(vec![3; 100]));
+		assert_eq!(cache.size(), 200);
+		std::fs::remove_dir_all(dir).ok();
+	}
+
+	#[test]
+	fn test_reopen() {
+		let dir = temp_dir("reopen");
+		DiskCache::open(&dir, 1000)
+			.unwrap()
+			.insert("track", "segment-3", b"data");
+
+		let cache = DiskCache::open(&dir, 1000).unwrap();
+		assert_eq!(cache.get("track", "segment-3"), Some(b"data".to_vec()));
+		assert_eq!(cache.get("track", "segment-4"), None);
+		// an entry that was never completed isn't served
+		let partial = dir.join(format!("{}.tmp", file_name("track", "segment-4")));
+		std::fs::write(&partial, b"da").unwrap();
+		let cache = DiskCache::open(&dir, 1000).unwrap();
+		assert_eq!(cache.get("track", "segment-4"), None);
+		assert_eq!(cache.size(), 4);
+		assert!(!partial.exists());
+		// a smaller cache drops what doesn't fit
+		let cache = DiskCache::open(&dir, 2).unwrap();
+		assert_eq!(cache.size(), 0);
+		std::fs::remove_dir_all(dir).ok();
+	}
+}
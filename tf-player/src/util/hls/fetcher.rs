@@ -1,13 +1,20 @@
-use std::{io::Read, sync::Arc, time::Duration};
+use std::{
+	io::{Read, Write},
+	sync::Arc,
+	time::Duration,
+};
 
 use anyhow::{Context, Result};
 use parking_lot::Mutex;
-use tracing::{debug, instrument, trace};
+use tracing::{instrument, trace};
 
 use super::{SegmentCache, SegmentInfos};
+use crate::util::cache;
 
 #[derive(Debug)]
 pub struct Fetcher {
+	// canonical url of the track, the segments are kept in the disk cache under it
+	key: String,
 	duration: Duration,
 	segment_infos: SegmentInfos,
 	segment_cache: Arc<Mutex<SegmentCache>>,
@@ -15,6 +22,7 @@ pub struct Fetcher {
 
 impl Fetcher {
 	pub fn spawn(
+		key: &str,
 		duration: Duration,
 		segment_infos: SegmentInfos,
 		segment_cache: Arc<Mutex<SegmentCache>>,
@@ -23,10 +31,11 @@ impl Fetcher {
 		let first_empty = segment_cache.lock().segments[0].is_none();
 		if first_empty {
 			segment_cache.lock().segments[0] =
-				Some(fetch_segment_retry(&segment_infos.0[0].url, 5)?);
+				Some(fetch_segment_cached(key, 0, &segment_infos.0[0].url)?);
 		}
 
 		let fetcher = Fetcher {
+			key: key.to_owned(),
 			duration,
 			segment_infos,
 			segment_cache,
@@ -64,12 +73,8 @@ impl Fetcher {
 					trace!("fetching next segment: {}", idx);
 					let info = &self.segment_infos.0[idx];
 					self.segment_cache.lock().segments[idx] =
-						Some(fetch_segment_retry(&info.url, 5)?);
+						Some(fetch_segment_cached(&self.key, idx, &info.url)?);
 					trace!("next segment received!");
-					if idx == self.segment_cache.lock().source_position.0 {
-						debug!("STOPPED BUFFERING");
-						self.segment_cache.lock().buffering = false;
-					}
 				}
 			}
 			std::thread::sleep(Duration::from_millis(100));
@@ -77,6 +82,28 @@ impl Fetcher {
 	}
 }
 
+// Looks in the disk cache first, and keeps what is downloaded there
+fn fetch_segment_cached(key: &str, idx: usize, url: &str) -> Result<Vec<u8>> {
+	let part = format!("segment-{idx}");
+	let cache = cache::global();
+	if let Some(segment) = cache.and_then(|cache| cache.get(key, &part)) {
+		return Ok(segment);
+	}
+	let segment = fetch_segment_retry(url, 5)?;
+	if let Some(cache) = cache {
+		cache.insert(key, &part, &segment);
+	}
+	Ok(segment)
+}
+
+// Writes every segment one after the other, which makes a whole file for MPEG audio.
+pub fn download(key: &str, segment_infos: &SegmentInfos, writer: &mut dyn Write) -> Result<()> {
+	for (idx, info) in segment_infos.0.iter().enumerate() {
+		writer.write_all(&fetch_segment_cached(key, idx, &info.url)?)?;
+	}
+	Ok(())
+}
+
 fn fetch_segment_retry(url: &str, nb_tries: usize) -> Result<Vec<u8>> {
 	for _ in 0..nb_tries {
 		if let Ok(s) = fetch_segment(url) {
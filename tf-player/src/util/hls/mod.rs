@@ -1,11 +1,11 @@
-use std::time::Duration;
+use std::{ops::Range, time::Duration};
 
 use hls_m3u8::MediaPlaylist;
 
 mod fetcher;
 mod source;
 
-pub use fetcher::Fetcher;
+pub use fetcher::{download, Fetcher};
 pub use source::MediaSource;
 
 pub const BYTERATE: f64 = 128_000.0 / 8.0;
@@ -25,17 +25,43 @@ pub struct SegmentInfo {
 pub struct SegmentCache {
 	pub source_position: (usize, usize), // (segment idx, byte idx)
 	pub segments: Vec<Option<Vec<u8>>>,
-	pub buffering: bool,
 }
 
+// The source reads ahead into the following segment, so both have to be there to carry on.
+const NEEDED_SEGMENTS: usize = 2;
+
 impl SegmentCache {
 	pub fn new(nb_segments: usize) -> Self {
 		Self {
 			source_position: (0, 0),
 			segments: vec![None; nb_segments],
-			buffering: false,
 		}
 	}
+
+	// How many of the segments needed at the source position are there, None if all of them are
+	pub fn buffering(&self) -> Option<f32> {
+		let start = self.source_position.0.min(self.segments.len());
+		let needed = &self.segments[start..(start + NEEDED_SEGMENTS).min(self.segments.len())];
+		let loaded = needed.iter().filter(|s| s.is_some()).count();
+		(loaded < needed.len()).then(|| loaded as f32 / needed.len() as f32)
+	}
+
+	// The time ranges covered by consecutive segments in the cache
+	pub fn buffered(&self, segment_infos: &SegmentInfos) -> Vec<Range<Duration>> {
+		let mut ranges: Vec<Range<Duration>> = vec![];
+		let mut t = Duration::ZERO;
+		for (segment, info) in self.segments.iter().zip(&segment_infos.0) {
+			let end = t + info.duration;
+			if segment.is_some() {
+				match ranges.last_mut() {
+					Some(last) if last.end == t => last.end = end,
+					_ => ranges.push(t..end),
+				}
+			}
+			t = end;
+		}
+		ranges
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -67,6 +93,18 @@ impl SegmentInfos {
 		return None;
 	}
 
+	// The segment playing at `time` and when it starts, the last one past the end
+	pub fn segment_start(&self, time: Duration) -> (usize, Duration) {
+		let mut start = Duration::ZERO;
+		for (idx, segment) in self.0.iter().enumerate() {
+			if start + segment.duration > time || idx + 1 == self.0.len() {
+				return (idx, start);
+			}
+			start += segment.duration;
+		}
+		(0, Duration::ZERO)
+	}
+
 	pub fn time_at_position(&self, segment_idx: usize, byte_idx: usize) -> Duration {
 		let mut t = Duration::ZERO;
 		for (idx, segment) in self.0.iter().enumerate() {
@@ -79,3 +117,53 @@ impl SegmentInfos {
 		return t;
 	}
 }
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn segment_infos(nb_segments: usize) -> SegmentInfos {
+		SegmentInfos(
+			(0..nb_segments)
+				.map(|i| SegmentInfo {
+					url: format!("https://example.com/{i}.mp3"),
+					duration: Duration::from_secs(10),
+				})
+				.collect(),
+		)
+	}
+
+	#[test]
+	fn test_buffering() {
+		let infos = segment_infos(5);
+		let mut cache = SegmentCache::new(5);
+		assert_eq!(cache.buffering(), Some(0.0));
+		cache.segments[0] = Some(vec![0; 16]);
+		assert_eq!(cache.buffering(), Some(0.5));
+		cache.segments[1] = Some(vec![0; 16]);
+		assert_eq!(cache.buffering(), None);
+		// after a seek
+		cache.segments[3] = Some(vec![0; 16]);
+		cache.source_position = (3, 0);
+		assert_eq!(cache.buffering(), Some(0.5));
+		cache.segments[4] = Some(vec![0; 16]);
+		assert_eq!(cache.buffering(), None);
+
+		assert_eq!(
+			infos.segment_start(Duration::from_secs(25)),
+			(2, Duration::from_secs(20))
+		);
+		assert_eq!(
+			infos.segment_start(Duration::from_secs(60)),
+			(4, Duration::from_secs(40))
+		);
+
+		assert_eq!(
+			cache.buffered(&infos),
+			[
+				Duration::ZERO..Duration::from_secs(20),
+				Duration::from_secs(30)..Duration::from_secs(50)
+			]
+		);
+	}
+}
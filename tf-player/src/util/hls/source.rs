@@ -20,16 +20,14 @@ pub struct MediaSource {
 }
 
 impl MediaSource {
+	// Starts reading at the beginning of a segment, segments start on an MPEG frame
 	pub fn new(
 		duration: Duration,
 		segment_infos: SegmentInfos,
 		segment_cache: Arc<Mutex<SegmentCache>>,
-		pos: Duration,
+		curr_segment_idx: usize,
 	) -> Self {
-		let (curr_segment_idx, curr_offset) = segment_infos.segment_at(pos).unwrap();
-		if segment_cache.lock().segments[curr_segment_idx].is_some() {
-			segment_cache.lock().buffering = false;
-		}
+		let curr_offset = 0;
 		segment_cache.lock().source_position = (curr_segment_idx, curr_offset);
 
 		debug!(
@@ -47,6 +45,7 @@ impl MediaSource {
 		}
 	}
 
+	// Blocks until the fetcher has the segment, `SegmentCache::buffering` tells whether it would.
 	#[instrument(skip_all)]
 	fn load_segment(&mut self) -> std::io::Result<()> {
 		trace!(
@@ -59,10 +58,10 @@ impl MediaSource {
 			match &cache.segments[self.curr_segment_idx] {
 				Some(s) => break s.clone(),
 				None => {
-					debug!("failed to grab segment, retrying...");
+					trace!("failed to grab segment, retrying...");
 				}
 			}
-			std::thread::sleep(Duration::from_millis(1000));
+			std::thread::sleep(Duration::from_millis(100));
 		};
 		self.curr_segment = Some(segment);
 		Ok(())
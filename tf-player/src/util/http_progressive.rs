@@ -4,11 +4,23 @@ use anyhow::Result;
 use symphonia::core::io::MediaSource;
 use tracing::warn;
 
+use crate::util::cache;
+
+// Size of the byte ranges kept in the disk cache
+const CHUNK_LEN: usize = 1 << 17;
+
 pub struct HttpProgressive {
 	url: String,
-	reader: Box<dyn Read + Sync + Send>,
+	// opened lazily when everything read so far came from the cache
+	reader: Option<Box<dyn Read + Sync + Send>>,
 	length: usize,
 	position: usize,
+	// where `reader` is in the stream
+	reader_position: usize,
+	// canonical url of the track, when the disk cache is used
+	key: Option<String>,
+	// the cached chunk being read
+	chunk: Option<(usize, Vec<u8>)>,
 }
 
 impl HttpProgressive {
@@ -24,34 +36,125 @@ impl HttpProgressive {
 
 		Ok(Self {
 			url: url.to_owned(),
-			reader: response.into_reader(),
+			reader: Some(response.into_reader()),
 			length,
 			position,
+			reader_position: position,
+			key: None,
+			chunk: None,
 		})
 	}
-}
 
-impl Read for HttpProgressive {
-	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+	/// Goes through the disk cache, if it is set up, with chunks of the stream kept under `key`.
+	pub fn cached(url: &str, key: &str) -> Result<Self> {
+		let Some(disk_cache) = cache::global() else {
+			return Self::new(url);
+		};
+		let mut source = match disk_cache.get(key, "length") {
+			Some(length) if length.len() == 8 => Self {
+				url: url.to_owned(),
+				reader: None,
+				length: u64::from_le_bytes(length.try_into().unwrap()) as usize,
+				position: 0,
+				reader_position: 0,
+				key: None,
+				chunk: None,
+			},
+			_ => {
+				let source = Self::new(url)?;
+				if source.length > 0 {
+					disk_cache.insert(key, "length", &(source.length as u64).to_le_bytes());
+				}
+				source
+			}
+		};
+		source.key = Some(key.to_owned());
+		Ok(source)
+	}
+
+	fn request(&self, from: usize) -> std::io::Result<Box<dyn Read + Sync + Send>> {
+		let response = ureq::get(&self.url)
+			.set("Range", &format!("bytes={}-", from))
+			.call()
+			.map_err(std::io::Error::other)?;
+		Ok(response.into_reader())
+	}
+
+	fn read_network(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if self.reader.is_none() {
+			self.reader = Some(self.request(self.reader_position)?);
+		}
 		let mut i = 0;
 		let nb_read = loop {
-			match self.reader.read(buf) {
+			match self.reader.as_mut().unwrap().read(buf) {
 				Err(e) => {
 					warn!("failed to read http: {e}");
 					if i == 5 {
 						return Err(e);
 					}
 					warn!("retrying...{i}");
-					let response = ureq::get(&self.url)
-						.set("Range", &format!("bytes={}-", self.position))
-						.call()
-						.unwrap();
-					self.reader = response.into_reader();
+					self.reader = Some(self.request(self.reader_position)?);
 				}
 				Ok(nb_read) => break nb_read,
 			}
 			i += 1;
 		};
+		self.reader_position += nb_read;
+		Ok(nb_read)
+	}
+
+	fn read_cached(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let index = self.position / CHUNK_LEN;
+		if !matches!(&self.chunk, Some((i, _)) if *i == index) {
+			self.chunk = Some((index, self.load_chunk(index)?));
+		}
+		let (_, chunk) = self.chunk.as_ref().unwrap();
+
+		let offset = self.position - index * CHUNK_LEN;
+		let nb_read = buf.len().min(chunk.len().saturating_sub(offset));
+		buf[..nb_read].copy_from_slice(&chunk[offset..offset + nb_read]);
+		self.position += nb_read;
+		Ok(nb_read)
+	}
+
+	fn load_chunk(&mut self, index: usize) -> std::io::Result<Vec<u8>> {
+		let (Some(key), Some(disk_cache)) = (self.key.clone(), cache::global()) else {
+			unreachable!("only used with the disk cache");
+		};
+		let part = format!("range-{index}");
+		if let Some(chunk) = disk_cache.get(&key, &part) {
+			return Ok(chunk);
+		}
+
+		let start = index * CHUNK_LEN;
+		if self.reader_position != start {
+			self.reader = None;
+			self.reader_position = start;
+		}
+		let mut chunk = vec![0; CHUNK_LEN];
+		let mut filled = 0;
+		while filled < CHUNK_LEN {
+			let nb_read = self.read_network(&mut chunk[filled..])?;
+			if nb_read == 0 {
+				break;
+			}
+			filled += nb_read;
+		}
+		chunk.truncate(filled);
+		// only whole chunks are kept, apart from the last one
+		if filled == CHUNK_LEN || start + filled == self.length {
+			disk_cache.insert(&key, &part, &chunk);
+		}
+		Ok(chunk)
+	}
+}
+
+impl Read for HttpProgressive {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		if self.key.is_some() {
+			return self.read_cached(buf);
+		}
+		let nb_read = self.read_network(buf)?;
 		self.position += nb_read;
 		Ok(nb_read)
 	}
@@ -71,12 +174,11 @@ impl Seek for HttpProgressive {
 
 		self.position = idx as usize;
 
-		let response = ureq::get(&self.url)
-			.set("Range", &format!("bytes={}-", idx))
-			.call()
-			.unwrap();
-
-		self.reader = response.into_reader();
+		// with the cache, the reader is only moved if the chunk isn't cached
+		if self.key.is_none() {
+			self.reader = Some(self.request(self.position)?);
+			self.reader_position = self.position;
+		}
 
 		Ok(idx)
 	}
@@ -1,4 +1,8 @@
+pub mod cache;
 pub mod hls;
 pub mod http_progressive;
 
+#[cfg(feature = "opus")]
+mod opus;
 pub mod symphonia;
+pub mod trim;
@@ -0,0 +1,197 @@
+use audiopus::{coder::Decoder as Libopus, packet::Packet as OpusPacket, Channels, MutSignals};
+use parking_lot::Mutex;
+use symphonia::core::{
+	audio::{AsAudioBufferRef, AudioBuffer, AudioBufferRef, Signal, SignalSpec},
+	codecs::{
+		CodecDescriptor, CodecParameters, Decoder, DecoderOptions, FinalizeResult, CODEC_TYPE_OPUS,
+	},
+	errors::{decode_error, unsupported_error, Result},
+	formats::Packet,
+	support_codec,
+};
+
+// Opus is always decoded at 48kHz
+const SAMPLE_RATE: u32 = 48000;
+// The longest Opus packet is 120ms
+const MAX_FRAMES: usize = 5760;
+
+// Decodes the Opus streams found by symphonia's Ogg and Matroska readers with libopus.
+// Only mono and stereo streams are supported, surround Opus needs the multistream decoder.
+pub struct OpusDecoder {
+	params: CodecParameters,
+	channels: Channels,
+	// libopus' decoder isn't Sync, it's only used through `&mut self`
+	decoder: Mutex<Libopus>,
+	// the frames the encoder adds before the start of the stream
+	delay: usize,
+	// what is left of them to drop, when decoding from the start
+	pre_skip: usize,
+	pcm: Vec<f32>,
+	buf: AudioBuffer<f32>,
+}
+
+impl OpusDecoder {
+	fn libopus(channels: Channels) -> Result<Libopus> {
+		match Libopus::new(audiopus::SampleRate::Hz48000, channels) {
+			Ok(decoder) => Ok(decoder),
+			Err(_) => decode_error("opus: failed to create the decoder"),
+		}
+	}
+}
+
+impl Decoder for OpusDecoder {
+	fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> Result<Self> {
+		let Some(layout) = params.channels else {
+			return decode_error("opus: the channels are unknown");
+		};
+		let channels = match layout.count() {
+			1 => Channels::Mono,
+			2 => Channels::Stereo,
+			_ => return unsupported_error("opus: multistream isn't supported"),
+		};
+		Ok(Self {
+			params: params.clone(),
+			channels,
+			decoder: Mutex::new(Self::libopus(channels)?),
+			delay: params.delay.unwrap_or(0) as usize,
+			pre_skip: params.delay.unwrap_or(0) as usize,
+			pcm: vec![0.0; MAX_FRAMES * layout.count()],
+			buf: AudioBuffer::new(MAX_FRAMES as u64, SignalSpec::new(SAMPLE_RATE, layout)),
+		})
+	}
+
+	fn supported_codecs() -> &'static [CodecDescriptor] {
+		&[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus (libopus)")]
+	}
+
+	fn reset(&mut self) {
+		// libopus can only be reset through a ctl that isn't exposed, a new decoder does the same
+		if let Ok(decoder) = Self::libopus(self.channels) {
+			*self.decoder.get_mut() = decoder;
+		}
+		// dropped again if decoding starts over from the first packet
+		self.pre_skip = 0;
+	}
+
+	fn codec_params(&self) -> &CodecParameters {
+		&self.params
+	}
+
+	fn decode(&mut self, packet: &Packet) -> Result<AudioBufferRef<'_>> {
+		let Ok(input) = OpusPacket::try_from(packet.buf()) else {
+			return decode_error("opus: invalid packet");
+		};
+		let Ok(output) = MutSignals::try_from(&mut self.pcm[..]) else {
+			return decode_error("opus: invalid output buffer");
+		};
+		let nb_frames = match self
+			.decoder
+			.get_mut()
+			.decode_float(Some(input), output, false)
+		{
+			Ok(nb_frames) => nb_frames,
+			Err(_) => return decode_error("opus: failed to decode a packet"),
+		};
+
+		if packet.ts() == 0 {
+			self.pre_skip = self.delay;
+		}
+		let skipped = self.pre_skip.min(nb_frames);
+		self.pre_skip -= skipped;
+		let nb_channels = self.channels as usize;
+		self.buf.clear();
+		self.buf.render_reserved(Some(nb_frames - skipped));
+		for channel in 0..nb_channels {
+			for (i, sample) in self.buf.chan_mut(channel).iter_mut().enumerate() {
+				*sample = self.pcm[(skipped + i) * nb_channels + channel];
+			}
+		}
+		Ok(self.buf.as_audio_buffer_ref())
+	}
+
+	fn finalize(&mut self) -> FinalizeResult {
+		FinalizeResult::default()
+	}
+
+	fn last_decoded(&self) -> AudioBufferRef<'_> {
+		self.buf.as_audio_buffer_ref()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use audiopus::{coder::Encoder, Application, SampleRate};
+	use symphonia::core::{audio::Channels as Layout, codecs::CodecParameters};
+
+	use super::*;
+
+	fn decoder() -> OpusDecoder {
+		let mut params = CodecParameters::new();
+		params
+			.for_codec(CODEC_TYPE_OPUS)
+			.with_sample_rate(SAMPLE_RATE)
+			.with_channels(Layout::FRONT_LEFT | Layout::FRONT_RIGHT)
+			.with_delay(312);
+		OpusDecoder::try_new(&params, &Default::default()).unwrap()
+	}
+
+	// 20ms packets of a 440Hz sine
+	fn packets(n: usize) -> Vec<Packet> {
+		let encoder =
+			Encoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio).unwrap();
+		(0..n)
+			.map(|i| {
+				let input = (0..960)
+					.flat_map(|j| {
+						let t = (i * 960 + j) as f32 / SAMPLE_RATE as f32;
+						let s = 0.5 * (2.0 * std::f32::consts::PI * 440.0 * t).sin();
+						[s, s]
+					})
+					.collect::<Vec<_>>();
+				let mut encoded = vec![0; 4000];
+				let len = encoder.encode_float(&input, &mut encoded).unwrap();
+				encoded.truncate(len);
+				Packet::new_from_boxed_slice(0, (i * 960) as u64, 960, encoded.into())
+			})
+			.collect()
+	}
+
+	// The number of frames decoded, and their energy
+	fn decode(decoder: &mut OpusDecoder, packets: &[Packet]) -> (usize, f32) {
+		let mut nb_decoded = 0;
+		let mut energy = 0.0;
+		for packet in packets {
+			let AudioBufferRef::F32(decoded) = decoder.decode(packet).unwrap() else {
+				panic!("opus decodes to f32");
+			};
+			nb_decoded += decoded.frames();
+			energy += decoded.chan(0).iter().map(|s| s * s).sum::<f32>();
+		}
+		(nb_decoded, energy)
+	}
+
+	#[test]
+	fn test_decode() {
+		let (nb_decoded, energy) = decode(&mut decoder(), &packets(10));
+
+		// the pre-skip is left out
+		assert_eq!(nb_decoded, 10 * 960 - 312);
+		let rms = (energy / nb_decoded as f32).sqrt();
+		assert!((rms - 0.5 / 2f32.sqrt()).abs() < 0.05, "{rms}");
+	}
+
+	#[test]
+	fn test_reset() {
+		let packets = packets(10);
+		let mut decoder = decoder();
+		decode(&mut decoder, &packets);
+
+		// back to the start, the pre-skip is left out again
+		decoder.reset();
+		assert_eq!(decode(&mut decoder, &packets).0, 10 * 960 - 312);
+
+		// from the middle, nothing is left out
+		decoder.reset();
+		assert_eq!(decode(&mut decoder, &packets[5..]).0, 5 * 960);
+	}
+}
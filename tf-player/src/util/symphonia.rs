@@ -1,17 +1,39 @@
-use std::{fs::File, path::Path, time::Duration};
+use std::{
+	fs::File,
+	path::Path,
+	sync::{Arc, OnceLock},
+	time::Duration,
+};
 
-// use anyhow::{Context, Result};
+use anyhow::anyhow;
 use symphonia::core::{
-	audio::{AudioBufferRef, SampleBuffer},
-	codecs::Decoder,
+	audio::{AudioBufferRef, Channels, SampleBuffer, SignalSpec},
+	codecs::{CodecRegistry, CodecType, Decoder, CODEC_TYPE_NULL, CODEC_TYPE_OPUS},
 	errors::Error as SymphoniaError,
-	formats::{FormatReader, SeekMode, SeekTo},
+	formats::{FormatReader, SeekMode, SeekTo, Track},
 	io::MediaSourceStream,
-	probe::Hint,
-	units::Time,
+	meta::{MetadataRevision, StandardTagKey, StandardVisualKey, Tag},
+	probe::{Hint, ProbeResult},
+	units::{Time, TimeBase},
 };
 
-use crate::SourceError;
+use crate::{player::Loudness, Cover, SourceError};
+
+static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+
+// Opus needs 80ms of audio before the seek point to converge, or the first ones are off
+const OPUS_PRE_ROLL: Duration = Duration::from_millis(80);
+
+// The codecs of symphonia, along with the ones it doesn't have
+fn codecs() -> &'static CodecRegistry {
+	CODECS.get_or_init(|| {
+		let mut registry = CodecRegistry::new();
+		symphonia::default::register_enabled_codecs(&mut registry);
+		#[cfg(feature = "opus")]
+		registry.register_all::<super::opus::OpusDecoder>();
+		registry
+	})
+}
 
 pub struct Source {
 	format: Box<dyn FormatReader>,
@@ -19,40 +41,47 @@ pub struct Source {
 	track_id: u32,
 	pub duration: Duration,
 	pub sample_rate: f64,
+	pub metadata: Metadata,
+	time_base: Option<TimeBase>,
+	spec: SignalSpec,
+	// gains of each decoded channel into the left and right outputs
+	mix: Vec<[f32; 2]>,
 	sample_buf: symphonia::core::audio::SampleBuffer<f32>,
 	i: u32,
 }
 
 impl Source {
 	pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
-		let path = path.as_ref();
-		let file = Box::new(File::open(path).unwrap());
-		let mss = MediaSourceStream::new(file, Default::default());
-		let mut hint = Hint::new();
-		if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
-			hint.with_extension(extension);
-		}
-		Self::from_mss(mss, hint)
+		let mut probed = probe_file(path.as_ref())?;
+		let metadata = Metadata::from_probed(&mut probed);
+		Self::new(probed.format, metadata)
 	}
 
 	pub fn from_mss(mss: MediaSourceStream, hint: Hint) -> Result<Self, anyhow::Error> {
-		let probed = symphonia::default::get_probe()
-			.format(&hint, mss, &Default::default(), &Default::default())
-			.unwrap();
-		let format = probed.format;
-		Self::from_format_reader(format)
+		let mut probed = probe(mss, hint)?;
+		let metadata = Metadata::from_probed(&mut probed);
+		Self::new(probed.format, metadata)
 	}
 
 	pub fn from_format_reader(mut format: Box<dyn FormatReader>) -> Result<Self, anyhow::Error> {
-		let track = format.default_track().unwrap().clone();
-		let mut decoder =
-			symphonia::default::get_codecs().make(&track.codec_params, &Default::default())?;
+		let mut metadata = Metadata::default();
+		metadata.read_container(&mut *format);
+		Self::new(format, metadata)
+	}
+
+	fn new(mut format: Box<dyn FormatReader>, metadata: Metadata) -> Result<Self, anyhow::Error> {
+		let default = format.default_track().map(|track| track.id);
+		let track = best_track(format.tracks(), default)
+			.ok_or(anyhow!("the stream has no audio track that can be decoded"))?
+			.clone();
+		let mut decoder = codecs().make(&track.codec_params, &Default::default())?;
 		let codec_params = decoder.codec_params();
 		let duration = {
 			let time = codec_params
 				.time_base
-				.unwrap()
-				.calc_time(codec_params.n_frames.unwrap());
+				.zip(codec_params.n_frames)
+				.map(|(time_base, n_frames)| time_base.calc_time(n_frames))
+				.ok_or(anyhow!("the duration of the stream is unknown"))?;
 			Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
 		};
 		let audio_buf = get_next_audio_buffer(&mut *format, track.id, &mut *decoder)?;
@@ -63,21 +92,69 @@ impl Source {
 			sample_buf.copy_interleaved_ref(audio_buf);
 			sample_buf
 		};
+		if spec.channels.count() == 0 {
+			return Err(anyhow!("the audio track has no channels"));
+		}
 		Ok(Self {
 			format,
 			decoder,
 			track_id: track.id,
 			duration,
 			sample_rate: spec.rate as f64,
+			metadata,
+			time_base: track.codec_params.time_base,
+			spec,
+			mix: stereo_mix(spec.channels),
 			sample_buf,
 			i: 0,
 		})
 	}
 
+	/// Decodes and drops the next `nb_frames` frames, returns how many there were before the end.
+	pub fn skip(&mut self, nb_frames: u64) -> Result<u64, SourceError> {
+		let mut remaining = nb_frames;
+		while remaining > 0 {
+			if self.i >= self.sample_buf.len() as u32 {
+				match self.decode_next() {
+					Err(SourceError::EndOfStream) => break,
+					r => r?,
+				}
+				continue;
+			}
+			let nb_channels = self.mix.len() as u32;
+			let available = ((self.sample_buf.len() as u32 - self.i) / nb_channels) as u64;
+			let n = available.min(remaining);
+			self.i += n as u32 * nb_channels;
+			remaining -= n;
+		}
+		Ok(nb_frames - remaining)
+	}
+
+	// The frame at timestamp `ts` of the track
+	fn frame_at(&self, ts: u64) -> u64 {
+		match self.time_base {
+			Some(time_base) => {
+				let time = time_base.calc_time(ts);
+				((time.seconds as f64 + time.frac) * self.sample_rate).round() as u64
+			}
+			None => ts,
+		}
+	}
+
 	fn decode_next(&mut self) -> Result<(), SourceError> {
 		let next = get_next_audio_buffer(&mut *self.format, self.track_id, &mut *self.decoder);
 		match next {
 			Ok(audio_buf) => {
+				let spec = *audio_buf.spec();
+				let capacity = self.sample_buf.capacity() / self.mix.len();
+				if spec.channels.count() == 0 {
+					return Err(SourceError::General("a packet has no channels".into()));
+				}
+				if spec != self.spec || audio_buf.capacity() > capacity {
+					self.sample_buf = SampleBuffer::new(audio_buf.capacity() as u64, spec);
+					self.mix = stereo_mix(spec.channels);
+					self.spec = spec;
+				}
 				self.sample_buf.copy_interleaved_ref(audio_buf);
 				self.i = 0;
 				Ok(())
@@ -91,6 +168,253 @@ impl Source {
 	}
 }
 
+fn probe_file(path: &Path) -> Result<ProbeResult, anyhow::Error> {
+	let file = Box::new(File::open(path)?);
+	let mss = MediaSourceStream::new(file, Default::default());
+	let mut hint = Hint::new();
+	if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+		hint.with_extension(extension);
+	}
+	probe(mss, hint)
+}
+
+fn probe(mss: MediaSourceStream, hint: Hint) -> Result<ProbeResult, anyhow::Error> {
+	Ok(symphonia::default::get_probe().format(
+		&hint,
+		mss,
+		&Default::default(),
+		&Default::default(),
+	)?)
+}
+
+// What the tags of a file tell about its track
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+	pub title: Option<String>,
+	pub artists: Vec<String>,
+	pub album: Option<String>,
+	pub track_number: Option<u32>,
+	pub year: Option<i32>,
+	pub genre: Option<String>,
+	pub bpm: Option<f32>,
+	pub replay_gain: ReplayGain,
+	pub cover: Option<Arc<Cover>>,
+}
+
+// Gains in dB, peaks as a factor of full scale
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+	pub track_gain: Option<f32>,
+	pub track_peak: Option<f32>,
+	pub album_gain: Option<f32>,
+	pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+	// ReplayGain 2.0 brings tracks to -18 LUFS
+	const REFERENCE: f32 = -18.0;
+
+	pub fn track_loudness(&self) -> Option<Loudness> {
+		Self::loudness(self.track_gain, self.track_peak)
+	}
+
+	pub fn album_loudness(&self) -> Option<Loudness> {
+		Self::loudness(self.album_gain, self.album_peak)
+	}
+
+	fn loudness(gain: Option<f32>, peak: Option<f32>) -> Option<Loudness> {
+		Some(Loudness {
+			integrated: Self::REFERENCE - gain?,
+			true_peak: 20.0 * peak?.log10(),
+		})
+	}
+}
+
+impl Metadata {
+	/// Reads the tags of a file without decoding it
+	pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+		let mut probed = probe_file(path.as_ref())?;
+		Ok(Self::from_probed(&mut probed))
+	}
+
+	// The tags found before the container, like ID3 ones, then those of the container itself
+	fn from_probed(probed: &mut ProbeResult) -> Self {
+		let mut metadata = Self::default();
+		if let Some(mut log) = probed.metadata.get() {
+			if let Some(revision) = log.skip_to_latest() {
+				metadata.read(revision);
+			}
+		}
+		metadata.read_container(&mut *probed.format);
+		metadata
+	}
+
+	fn read_container(&mut self, format: &mut dyn FormatReader) {
+		if let Some(revision) = format.metadata().skip_to_latest() {
+			self.read(revision);
+		}
+	}
+
+	// What is found in `revision` replaces what was read before
+	fn read(&mut self, revision: &MetadataRevision) {
+		let (mut artists, mut album_artists) = (vec![], vec![]);
+		for tag in revision.tags() {
+			let value = tag.value.to_string();
+			let value = value.trim();
+			if value.is_empty() {
+				continue;
+			}
+			match standard_key(tag) {
+				Some(StandardTagKey::TrackTitle) => self.title = Some(value.to_owned()),
+				Some(StandardTagKey::Artist) => artists.extend(split_names(value)),
+				Some(StandardTagKey::AlbumArtist) => album_artists.extend(split_names(value)),
+				Some(StandardTagKey::Album) => self.album = Some(value.to_owned()),
+				// often written as "3/12"
+				Some(StandardTagKey::TrackNumber) => {
+					self.track_number = value.split('/').next().and_then(|n| n.trim().parse().ok())
+				}
+				Some(StandardTagKey::Date) => {
+					self.year = value.get(..4).and_then(|y| y.parse().ok())
+				}
+				Some(StandardTagKey::Genre) => self.genre = Some(value.to_owned()),
+				Some(StandardTagKey::Bpm) => self.bpm = value.parse().ok(),
+				Some(StandardTagKey::ReplayGainTrackGain) => {
+					self.replay_gain.track_gain = parse_gain(value)
+				}
+				Some(StandardTagKey::ReplayGainTrackPeak) => {
+					self.replay_gain.track_peak = value.parse().ok()
+				}
+				Some(StandardTagKey::ReplayGainAlbumGain) => {
+					self.replay_gain.album_gain = parse_gain(value)
+				}
+				Some(StandardTagKey::ReplayGainAlbumPeak) => {
+					self.replay_gain.album_peak = value.parse().ok()
+				}
+				_ => {}
+			}
+		}
+		if !artists.is_empty() {
+			self.artists = artists;
+		} else if !album_artists.is_empty() && self.artists.is_empty() {
+			self.artists = album_artists;
+		}
+
+		let visuals = revision.visuals();
+		let cover = visuals
+			.iter()
+			.find(|visual| visual.usage == Some(StandardVisualKey::FrontCover))
+			.or(visuals.first());
+		if let Some(visual) = cover {
+			self.cover = Some(Arc::new(Cover {
+				media_type: visual.media_type.clone(),
+				data: visual.data.to_vec(),
+			}));
+		}
+	}
+}
+
+// ReplayGain can be in free-form tags that aren't mapped, like MP4's "----:com.apple.iTunes:replaygain_track_gain"
+fn standard_key(tag: &Tag) -> Option<StandardTagKey> {
+	if tag.std_key.is_some() {
+		return tag.std_key;
+	}
+	let key = tag.key.to_ascii_lowercase();
+	let name = key.rsplit(':').next().unwrap_or(&key);
+	match name {
+		"replaygain_track_gain" => Some(StandardTagKey::ReplayGainTrackGain),
+		"replaygain_track_peak" => Some(StandardTagKey::ReplayGainTrackPeak),
+		"replaygain_album_gain" => Some(StandardTagKey::ReplayGainAlbumGain),
+		"replaygain_album_peak" => Some(StandardTagKey::ReplayGainAlbumPeak),
+		_ => None,
+	}
+}
+
+// Several names can be in a single tag, ID3v2.4 separates them with null characters
+fn split_names(value: &str) -> impl Iterator<Item = String> + '_ {
+	value
+		.split(['\0', ';'])
+		.map(str::trim)
+		.filter(|name| !name.is_empty())
+		.map(str::to_owned)
+}
+
+// "-6.50 dB"
+fn parse_gain(value: &str) -> Option<f32> {
+	value
+		.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+		.trim()
+		.parse()
+		.ok()
+}
+
+// The default track when it can be decoded, otherwise the one with the most channels and the highest rate
+fn best_track(tracks: &[Track], default: Option<u32>) -> Option<&Track> {
+	tracks
+		.iter()
+		.filter(|track| {
+			let codec = track.codec_params.codec;
+			codec != CODEC_TYPE_NULL && codecs().get_codec(codec).is_some()
+		})
+		.max_by_key(|track| {
+			let params = &track.codec_params;
+			(
+				Some(track.id) == default,
+				params.channels.map_or(0, |channels| channels.count()),
+				params.sample_rate,
+			)
+		})
+}
+
+// Mono is played on both sides, and the surround channels are folded into the front ones with the
+// usual -3dB, the LFE being left out. The gains are scaled down so that the sum can't clip.
+fn stereo_mix(channels: Channels) -> Vec<[f32; 2]> {
+	const HALF: f32 = std::f32::consts::FRAC_1_SQRT_2;
+	if channels.count() == 1 {
+		return vec![[1.0, 1.0]];
+	}
+	let left = Channels::REAR_LEFT
+		| Channels::SIDE_LEFT
+		| Channels::FRONT_LEFT_CENTRE
+		| Channels::REAR_LEFT_CENTRE
+		| Channels::FRONT_LEFT_WIDE
+		| Channels::FRONT_LEFT_HIGH
+		| Channels::TOP_FRONT_LEFT
+		| Channels::TOP_REAR_LEFT;
+	let right = Channels::REAR_RIGHT
+		| Channels::SIDE_RIGHT
+		| Channels::FRONT_RIGHT_CENTRE
+		| Channels::REAR_RIGHT_CENTRE
+		| Channels::FRONT_RIGHT_WIDE
+		| Channels::FRONT_RIGHT_HIGH
+		| Channels::TOP_FRONT_RIGHT
+		| Channels::TOP_REAR_RIGHT;
+	let mix = channels
+		.iter()
+		.map(|channel| match channel {
+			Channels::FRONT_LEFT => [1.0, 0.0],
+			Channels::FRONT_RIGHT => [0.0, 1.0],
+			Channels::LFE1 | Channels::LFE2 => [0.0, 0.0],
+			_ if left.contains(channel) => [HALF, 0.0],
+			_ if right.contains(channel) => [0.0, HALF],
+			_ => [HALF, HALF],
+		})
+		.collect::<Vec<_>>();
+	let sum = mix.iter().fold([0.0f32; 2], |sum, gains| {
+		[sum[0] + gains[0], sum[1] + gains[1]]
+	});
+	let scale = 1.0 / sum[0].max(sum[1]).max(1.0);
+	mix.into_iter()
+		.map(|[l, r]| [l * scale, r * scale])
+		.collect()
+}
+
+fn pre_roll(codec: CodecType) -> Duration {
+	match codec {
+		CODEC_TYPE_OPUS => OPUS_PRE_ROLL,
+		_ => Duration::ZERO,
+	}
+}
+
 fn get_next_audio_buffer<'a>(
 	format: &mut dyn FormatReader,
 	track_id: u32,
@@ -107,33 +431,427 @@ fn get_next_audio_buffer<'a>(
 }
 
 impl crate::Source for Source {
-	fn seek(&mut self, pos: Duration) -> Result<(), SourceError> {
-		self.format
+	// The format lands on a packet at or before `pos`, what comes before it is decoded and dropped.
+	// It lands earlier still for the codecs that need some audio before `pos` to decode it right.
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+		let pos = pos.min(self.duration);
+		let from = pos.saturating_sub(pre_roll(self.decoder.codec_params().codec));
+		let seeked = self
+			.format
 			.seek(
-				SeekMode::Coarse,
+				SeekMode::Accurate,
 				SeekTo::Time {
 					time: Time {
-						seconds: pos.as_secs(),
-						frac: pos.as_secs_f64().fract(),
+						seconds: from.as_secs(),
+						frac: from.as_secs_f64().fract(),
 					},
-					track_id: None,
+					track_id: Some(self.track_id),
 				},
 			)
 			.map_err(|e| SourceError::General(Box::new(e)))?;
-		self.decode_next()?;
-		self.i = 0;
-		Ok(())
+		self.decoder.reset();
+		self.i = self.sample_buf.len() as u32;
+		let actual = self.frame_at(seeked.actual_ts);
+		let required = ((pos.as_secs_f64() * self.sample_rate).round() as u64).max(actual);
+		let landed = actual + self.skip(required - actual)?;
+		Ok(Duration::from_secs_f64(landed as f64 / self.sample_rate))
 	}
 
-	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<(), SourceError> {
-		for b in buf {
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+		let mut n = 0;
+		while n < buf.len() {
 			if self.i >= self.sample_buf.len() as u32 {
-				self.decode_next()?;
+				match self.decode_next() {
+					Err(SourceError::EndOfStream) => return Ok(n),
+					r => r?,
+				}
+				continue;
+			}
+			let frame = &self.sample_buf.samples()[self.i as usize..][..self.mix.len()];
+			buf[n] = frame
+				.iter()
+				.zip(&self.mix)
+				.fold([0.0; 2], |out, (sample, gains)| {
+					[out[0] + sample * gains[0], out[1] + sample * gains[1]]
+				});
+			self.i += self.mix.len() as u32;
+			n += 1;
+		}
+		Ok(buf.len())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::PathBuf;
+
+	use symphonia::core::codecs::{CodecParameters, CODEC_TYPE_FLAC, CODEC_TYPE_PCM_S16LE};
+
+	use super::*;
+	use crate::Source as _;
+
+	const SCALE: f32 = (1 << 20) as f32;
+
+	fn fixture_path(name: &str) -> PathBuf {
+		std::env::temp_dir().join(format!("tf-player-fixture-{}-{name}", std::process::id()))
+	}
+
+	fn write_wav(
+		name: &str,
+		spec: hound::WavSpec,
+		nb_frames: u32,
+		sample: impl Fn(u32, u16) -> f32,
+	) -> PathBuf {
+		let path = fixture_path(name);
+		let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+		for i in 0..nb_frames {
+			for channel in 0..spec.channels {
+				let value = sample(i, channel);
+				match spec.sample_format {
+					hound::SampleFormat::Float => writer.write_sample(value).unwrap(),
+					hound::SampleFormat::Int => writer
+						.write_sample((value * i16::MAX as f32) as i16)
+						.unwrap(),
+				}
+			}
+		}
+		writer.finalize().unwrap();
+		path
+	}
+
+	// Each frame holds its own index, so the position can be read back from the samples
+	fn write_fixture(sample_rate: u32, nb_frames: u32) -> PathBuf {
+		let spec = hound::WavSpec {
+			channels: 2,
+			sample_rate,
+			bits_per_sample: 32,
+			sample_format: hound::SampleFormat::Float,
+		};
+		write_wav(
+			&format!("{sample_rate}.wav"),
+			spec,
+			nb_frames,
+			|i, channel| {
+				if channel == 0 {
+					i as f32 / SCALE
+				} else {
+					-(i as f32) / SCALE
+				}
+			},
+		)
+	}
+
+	// A FLAC stream of verbatim frames of 16 bit samples, there is no encoder to make one
+	fn write_flac(
+		name: &str,
+		blocks: &[(u8, Vec<u8>)],
+		sample_rate: u32,
+		channels: u8,
+		nb_frames: u32,
+		sample: impl Fn(u32, u8) -> i16,
+	) -> PathBuf {
+		const BLOCK_LEN: u32 = 4096;
+		let mut out = b"fLaC".to_vec();
+		out.extend([if blocks.is_empty() { 0x80 } else { 0 }, 0, 0, 34]);
+		out.extend((BLOCK_LEN as u16).to_be_bytes());
+		out.extend((BLOCK_LEN as u16).to_be_bytes());
+		out.extend([0; 6]);
+		let info = (sample_rate as u64) << 44
+			| ((channels - 1) as u64) << 41
+			| 15 << 36
+			| nb_frames as u64;
+		out.extend(info.to_be_bytes());
+		out.extend([0; 16]);
+		for (i, (kind, block)) in blocks.iter().enumerate() {
+			let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+			out.push(last | kind);
+			out.extend(&(block.len() as u32).to_be_bytes()[1..]);
+			out.extend(block);
+		}
+		for (number, start) in (0..nb_frames).step_by(BLOCK_LEN as usize).enumerate() {
+			let len = BLOCK_LEN.min(nb_frames - start);
+			let frame_start = out.len();
+			// the block size follows the header, the sample rate is the one of STREAMINFO
+			out.extend([0xff, 0xf8, 0x70, ((channels - 1) << 4) | 0x08, number as u8]);
+			out.extend(((len - 1) as u16).to_be_bytes());
+			out.push(crc8(&out[frame_start..]));
+			for channel in 0..channels {
+				out.push(0x02);
+				for i in start..start + len {
+					out.extend(sample(i, channel).to_be_bytes());
+				}
+			}
+			let crc = crc16(&out[frame_start..]);
+			out.extend(crc.to_be_bytes());
+		}
+		let path = fixture_path(name);
+		std::fs::write(&path, out).unwrap();
+		path
+	}
+
+	fn crc8(data: &[u8]) -> u8 {
+		data.iter().fold(0, |crc, byte| {
+			(0..8).fold(crc ^ byte, |crc, _| {
+				if crc & 0x80 != 0 {
+					(crc << 1) ^ 0x07
+				} else {
+					crc << 1
+				}
+			})
+		})
+	}
+
+	fn crc16(data: &[u8]) -> u16 {
+		data.iter().fold(0, |crc, byte| {
+			(0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+				if crc & 0x8000 != 0 {
+					(crc << 1) ^ 0x8005
+				} else {
+					crc << 1
+				}
+			})
+		})
+	}
+
+	fn read_all(source: &mut Source) -> Vec<[f32; 2]> {
+		let mut frames = vec![];
+		let mut buf = [[0.0; 2]; 1000];
+		loop {
+			let n = source.next(&mut buf).unwrap();
+			frames.extend_from_slice(&buf[..n]);
+			if n < buf.len() {
+				return frames;
+			}
+		}
+	}
+
+	#[test]
+	fn test_seek() {
+		for sample_rate in [44100, 48000] {
+			let nb_frames = sample_rate * 3 + 123;
+			let path = write_fixture(sample_rate, nb_frames);
+			let mut source = Source::from_file(&path).unwrap();
+			let mut buf = [[0.0; 2]; 16];
+			// forwards and backwards, in between the packets of the format
+			for ms in [1234, 10, 2999, 501, 0] {
+				let frame = (ms * sample_rate + 500) / 1000;
+				let landed = source.seek(Duration::from_millis(ms as u64)).unwrap();
+				assert_eq!(
+					(landed.as_secs_f64() * sample_rate as f64).round() as u32,
+					frame
+				);
+				assert_eq!(source.next(&mut buf).unwrap(), buf.len());
+				assert_eq!(buf[0], [frame as f32 / SCALE, -(frame as f32) / SCALE]);
+				assert_eq!(buf[15][0], (frame + 15) as f32 / SCALE);
 			}
-			b[0] = self.sample_buf.samples()[(self.i + 0) as usize];
-			b[1] = self.sample_buf.samples()[(self.i + 1) as usize];
-			self.i += 2;
+
+			// past the end
+			let landed = source.seek(Duration::from_secs(10)).unwrap();
+			assert_eq!(
+				(landed.as_secs_f64() * sample_rate as f64).round() as u32,
+				nb_frames
+			);
+			assert_eq!(source.next(&mut buf).unwrap(), 0);
+
+			// skipping goes on from where the source is
+			source.seek(Duration::ZERO).unwrap();
+			assert_eq!(source.skip(1000).unwrap(), 1000);
+			source.next(&mut buf).unwrap();
+			assert_eq!(buf[0][0], 1000.0 / SCALE);
+			assert_eq!(
+				source.skip(nb_frames as u64).unwrap(),
+				nb_frames as u64 - 1016
+			);
+			std::fs::remove_file(path).ok();
+		}
+	}
+
+	#[test]
+	fn test_formats() {
+		let ramp = |i: u32| (i % 1000) as f32 / 1000.0 - 0.5;
+		let wav = |channels, sample_format, bits_per_sample| hound::WavSpec {
+			channels,
+			sample_rate: 44100,
+			bits_per_sample,
+			sample_format,
+		};
+		let fixtures = [
+			write_wav(
+				"mono.wav",
+				wav(1, hound::SampleFormat::Int, 16),
+				44100,
+				|i, _| ramp(i),
+			),
+			write_wav(
+				"stereo.wav",
+				wav(2, hound::SampleFormat::Float, 32),
+				44100,
+				|i, channel| if channel == 0 { ramp(i) } else { -ramp(i) },
+			),
+			write_flac("mono.flac", &[], 44100, 1, 44100, |i, _| {
+				(ramp(i) * i16::MAX as f32) as i16
+			}),
+			write_flac("stereo.flac", &[], 44100, 2, 44100, |i, channel| {
+				let value = (ramp(i) * i16::MAX as f32) as i16;
+				if channel == 0 {
+					value
+				} else {
+					-value
+				}
+			}),
+		];
+		for (path, mono) in fixtures.iter().zip([true, false, true, false]) {
+			let mut source = Source::from_file(path).unwrap();
+			assert_eq!(source.sample_rate, 44100.0);
+			assert_eq!(source.duration, Duration::from_secs(1));
+			let frames = read_all(&mut source);
+			assert_eq!(frames.len(), 44100, "{path:?}");
+			for i in [0, 1, 777, 44099] {
+				let expected = if mono {
+					[ramp(i as u32); 2]
+				} else {
+					[ramp(i as u32), -ramp(i as u32)]
+				};
+				assert!(
+					(frames[i][0] - expected[0]).abs() < 1e-3
+						&& (frames[i][1] - expected[1]).abs() < 1e-3,
+					"{path:?} {i}: {:?}",
+					frames[i]
+				);
+			}
+
+			let landed = source.seek(Duration::from_millis(500)).unwrap();
+			assert_eq!(landed, Duration::from_millis(500));
+			let mut buf = [[0.0; 2]; 1];
+			source.next(&mut buf).unwrap();
+			assert!((buf[0][0] - ramp(22050)).abs() < 1e-3, "{path:?}");
+			std::fs::remove_file(path).ok();
 		}
-		Ok(())
+	}
+
+	#[test]
+	fn test_downmix() {
+		// 5.1, one channel at a time
+		let spec = hound::WavSpec {
+			channels: 6,
+			sample_rate: 48000,
+			bits_per_sample: 16,
+			sample_format: hound::SampleFormat::Int,
+		};
+		let path = write_wav("surround.wav", spec, 6000, |i, channel| {
+			if (i / 1000) as u16 == channel {
+				0.5
+			} else {
+				0.0
+			}
+		});
+		let mut source = Source::from_file(&path).unwrap();
+		let frames = read_all(&mut source);
+		let at = |channel: usize| frames[channel * 1000 + 500];
+		let scale = 1.0 / (1.0 + 2.0 * std::f32::consts::FRAC_1_SQRT_2);
+		let close =
+			|a: [f32; 2], b: [f32; 2]| (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3;
+		let half = 0.5 * scale;
+		let center = half * std::f32::consts::FRAC_1_SQRT_2;
+		// front left and right, center, LFE, rear left and right
+		assert!(close(at(0), [half, 0.0]), "{:?}", at(0));
+		assert!(close(at(1), [0.0, half]), "{:?}", at(1));
+		assert!(close(at(2), [center, center]), "{:?}", at(2));
+		assert!(close(at(3), [0.0, 0.0]), "{:?}", at(3));
+		assert!(close(at(4), [center, 0.0]), "{:?}", at(4));
+		assert!(close(at(5), [0.0, center]), "{:?}", at(5));
+		std::fs::remove_file(path).ok();
+	}
+
+	#[test]
+	fn test_best_track() {
+		let track = |id, codec, channels: Channels, sample_rate| {
+			let mut params = CodecParameters::new();
+			params
+				.for_codec(codec)
+				.with_channels(channels)
+				.with_sample_rate(sample_rate);
+			Track::new(id, params)
+		};
+		let stereo = Channels::FRONT_LEFT | Channels::FRONT_RIGHT;
+		let tracks = [
+			Track::new(1, CodecParameters::new()),
+			track(2, CODEC_TYPE_FLAC, Channels::FRONT_CENTRE, 44100),
+			track(3, CODEC_TYPE_PCM_S16LE, stereo, 44100),
+			track(4, CODEC_TYPE_FLAC, stereo, 96000),
+		];
+		// the default track can't be decoded
+		assert_eq!(best_track(&tracks, Some(1)).map(|t| t.id), Some(4));
+		assert_eq!(best_track(&tracks, Some(2)).map(|t| t.id), Some(2));
+		assert_eq!(best_track(&tracks[..1], Some(1)).map(|t| t.id), None);
+	}
+
+	#[test]
+	fn test_metadata() {
+		let comments = [
+			"TITLE=Song",
+			"ARTIST=First",
+			"ARTIST=Second; Third",
+			"ALBUM=Album",
+			"TRACKNUMBER=3/12",
+			"DATE=2019-04-01",
+			"GENRE=Techno",
+			"BPM=128.00",
+			"REPLAYGAIN_TRACK_GAIN=-6.50 dB",
+			"REPLAYGAIN_TRACK_PEAK=0.5",
+		];
+		let mut vorbis_comment = vec![0, 0, 0, 0];
+		vorbis_comment.extend((comments.len() as u32).to_le_bytes());
+		for comment in comments {
+			vorbis_comment.extend((comment.len() as u32).to_le_bytes());
+			vorbis_comment.extend(comment.as_bytes());
+		}
+		let image = [0x89, b'P', b'N', b'G'];
+		let mut picture = vec![];
+		// a front cover of 1x1, 24 bits
+		for field in [3, 9] {
+			picture.extend((field as u32).to_be_bytes());
+		}
+		picture.extend(b"image/png");
+		for field in [0, 1, 1, 24, 0, image.len() as u32] {
+			picture.extend(field.to_be_bytes());
+		}
+		picture.extend(image);
+		let path = write_flac(
+			"tags.flac",
+			&[(4, vorbis_comment), (6, picture)],
+			44100,
+			2,
+			4410,
+			|_, _| 0,
+		);
+
+		let expected = Metadata {
+			title: Some("Song".into()),
+			artists: vec!["First".into(), "Second".into(), "Third".into()],
+			album: Some("Album".into()),
+			track_number: Some(3),
+			year: Some(2019),
+			genre: Some("Techno".into()),
+			bpm: Some(128.0),
+			replay_gain: ReplayGain {
+				track_gain: Some(-6.5),
+				track_peak: Some(0.5),
+				..Default::default()
+			},
+			cover: Some(Arc::new(Cover {
+				media_type: "image/png".into(),
+				data: image.to_vec(),
+			})),
+		};
+		assert_eq!(Metadata::from_file(&path).unwrap(), expected);
+		let source = Source::from_file(&path).unwrap();
+		assert_eq!(source.metadata, expected);
+
+		let loudness = expected.replay_gain.track_loudness().unwrap();
+		assert_eq!(loudness.integrated, -11.5);
+		assert!((loudness.true_peak + 6.02).abs() < 0.01);
+		assert_eq!(expected.replay_gain.album_loudness(), None);
+		std::fs::remove_file(path).ok();
 	}
 }
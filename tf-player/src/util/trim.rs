@@ -0,0 +1,173 @@
+use std::{ops::Range, sync::Arc, time::Duration};
+
+use crate::{player::Waveform, Source, SourceError, TrackSource};
+
+// Plays the part of a source between `start` and `end`, as if there was nothing around it
+pub struct Trimmed {
+	source: Box<dyn Source>,
+	sample_rate: f64,
+	start: Duration,
+	end: Option<Duration>,
+	// frames left until `end`
+	remaining: Option<usize>,
+}
+
+impl Trimmed {
+	pub fn new(
+		source: Box<dyn Source>,
+		sample_rate: f64,
+		start: Duration,
+		end: Option<Duration>,
+	) -> Result<Self, SourceError> {
+		let mut trimmed = Self {
+			source,
+			sample_rate,
+			start,
+			end,
+			remaining: None,
+		};
+		trimmed.seek(Duration::ZERO)?;
+		Ok(trimmed)
+	}
+}
+
+impl Source for Trimmed {
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+		let landed = self.source.seek(self.start + pos)?;
+		self.remaining = self.end.map(|end| {
+			(end.saturating_sub(landed).as_secs_f64() * self.sample_rate).round() as usize
+		});
+		Ok(landed.saturating_sub(self.start))
+	}
+
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+		let len = self.remaining.map_or(buf.len(), |r| r.min(buf.len()));
+		if len == 0 {
+			return Ok(0);
+		}
+		let n = self.source.next(&mut buf[..len])?;
+		if let Some(remaining) = &mut self.remaining {
+			*remaining -= n;
+		}
+		Ok(n)
+	}
+
+	fn buffering(&self) -> Option<f32> {
+		self.source.buffering()
+	}
+
+	fn buffered(&self) -> Vec<Range<Duration>> {
+		let end = self.end.unwrap_or(Duration::MAX);
+		self.source
+			.buffered()
+			.into_iter()
+			.map(|range| {
+				range.start.max(self.start) - self.start
+					..range.end.min(end).max(self.start) - self.start
+			})
+			.filter(|range| !range.is_empty())
+			.collect()
+	}
+}
+
+impl TrackSource {
+	/// Plays the track from `start` to `end` only, like to leave out the silence around it.
+	/// Its duration, the positions it reports, its cues and its waveform are those of the part that is played.
+	pub fn trim(self, start: Duration, end: Option<Duration>) -> Result<Self, SourceError> {
+		let duration = self.info.duration;
+		let end = end.filter(|end| *end < duration);
+		let start = start.min(end.unwrap_or(duration));
+		if start.is_zero() && end.is_none() {
+			return Ok(self);
+		}
+		let mut info = self.info;
+		info.duration = end.unwrap_or(duration) - start;
+		info.cues = info
+			.cues
+			.into_iter()
+			.filter(|cue| (start..=end.unwrap_or(duration)).contains(cue))
+			.map(|cue| cue - start)
+			.collect();
+		info.waveform = info
+			.waveform
+			.map(|waveform| Arc::new(crop(&waveform, start, end.unwrap_or(duration), duration)));
+		Ok(TrackSource {
+			signal: Box::new(Trimmed::new(self.signal, self.sample_rate, start, end)?),
+			sample_rate: self.sample_rate,
+			info,
+		})
+	}
+}
+
+// The buckets of the waveform between `start` and `end`
+fn crop(waveform: &Waveform, start: Duration, end: Duration, duration: Duration) -> Waveform {
+	let len = waveform.buckets.len();
+	let bucket = |at: Duration| {
+		((at.as_secs_f64() / duration.as_secs_f64() * len as f64).round() as usize).min(len)
+	};
+	Waveform {
+		buckets: waveform.buckets[bucket(start)..bucket(end).max(bucket(start))].to_vec(),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::TrackInfo;
+
+	// Each frame holds its own index
+	struct Ramp {
+		position: usize,
+		len: usize,
+	}
+
+	impl Source for Ramp {
+		fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+			self.position = ((pos.as_secs_f64() * 1000.0) as usize).min(self.len);
+			Ok(Duration::from_millis(self.position as u64))
+		}
+
+		fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+			let n = buf.len().min(self.len - self.position);
+			for (i, frame) in buf[..n].iter_mut().enumerate() {
+				*frame = [(self.position + i) as f32; 2];
+			}
+			self.position += n;
+			Ok(n)
+		}
+	}
+
+	#[test]
+	fn test_trim() {
+		let source = TrackSource {
+			sample_rate: 1000.0,
+			signal: Box::new(Ramp {
+				position: 0,
+				len: 10000,
+			}),
+			info: TrackInfo {
+				duration: Duration::from_secs(10),
+				cues: vec![Duration::from_secs(1), Duration::from_secs(3)],
+				..Default::default()
+			},
+		};
+		let mut trimmed = source
+			.trim(Duration::from_secs(2), Some(Duration::from_secs(7)))
+			.unwrap();
+		assert_eq!(trimmed.info.duration, Duration::from_secs(5));
+		assert_eq!(trimmed.info.cues, [Duration::from_secs(1)]);
+
+		let mut buf = vec![[0.0; 2]; 4000];
+		assert_eq!(trimmed.signal.next(&mut buf).unwrap(), 4000);
+		assert_eq!(buf[0][0], 2000.0);
+		assert_eq!(trimmed.signal.next(&mut buf).unwrap(), 1000);
+		assert_eq!(buf[999][0], 6999.0);
+
+		assert_eq!(
+			trimmed.signal.seek(Duration::from_secs(4)).unwrap(),
+			Duration::from_secs(4)
+		);
+		assert_eq!(trimmed.signal.next(&mut buf).unwrap(), 1000);
+		assert_eq!(buf[0][0], 6000.0);
+	}
+}
@@ -0,0 +1,464 @@
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::{Duration, Instant},
+};
+
+use crossbeam_channel::Receiver;
+use tf_player::{
+	player::{sink, Event, Player, Readiness, SpeedMode, Upcoming},
+	Source, SourceError, TrackInfo, TrackSource,
+};
+
+struct Sine {
+	sample_rate: f64,
+	position: usize,
+	len: usize,
+}
+
+impl Source for Sine {
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+		self.position = (pos.as_secs_f64() * self.sample_rate) as usize;
+		Ok(Duration::from_secs_f64(
+			self.position as f64 / self.sample_rate,
+		))
+	}
+
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+		let n = buf.len().min(self.len.saturating_sub(self.position));
+		for frame in &mut buf[..n] {
+			let t = self.position as f64 / self.sample_rate;
+			let s = 0.5 * (2.0 * std::f64::consts::PI * 440.0 * t).sin() as f32;
+			*frame = [s, s];
+			self.position += 1;
+		}
+		Ok(n)
+	}
+}
+
+// Waits for the network whenever `loaded` is false
+struct Stalling {
+	sine: Sine,
+	loaded: Arc<AtomicBool>,
+}
+
+impl Source for Stalling {
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+		self.sine.seek(pos)
+	}
+
+	fn next(&mut self, buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+		assert!(self.loaded.load(Ordering::Relaxed), "read while buffering");
+		self.sine.next(buf)
+	}
+
+	fn buffering(&self) -> Option<f32> {
+		(!self.loaded.load(Ordering::Relaxed)).then_some(0.25)
+	}
+}
+
+struct Broken;
+
+impl Source for Broken {
+	fn seek(&mut self, pos: Duration) -> Result<Duration, SourceError> {
+		Ok(pos)
+	}
+
+	fn next(&mut self, _buf: &mut [[f32; 2]]) -> Result<usize, SourceError> {
+		Err(SourceError::General("broken stream".into()))
+	}
+}
+
+fn sine(sample_rate: f64, duration: Duration) -> TrackSource {
+	TrackSource {
+		sample_rate,
+		signal: Box::new(Sine {
+			sample_rate,
+			position: 0,
+			len: (duration.as_secs_f64() * sample_rate) as usize,
+		}),
+		info: TrackInfo {
+			duration,
+			..Default::default()
+		},
+	}
+}
+
+fn wait_track_ends(events: &Receiver<Event>, count: usize) {
+	let deadline = Instant::now() + Duration::from_secs(10);
+	let mut ended = 0;
+	while ended < count {
+		let timeout = deadline.saturating_duration_since(Instant::now());
+		match events.recv_timeout(timeout) {
+			Ok(Event::TrackEnd) => ended += 1,
+			Ok(_) => {}
+			Err(e) => panic!("only {ended} of {count} tracks ended: {e}"),
+		}
+	}
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+	std::env::temp_dir().join(format!("tf-player-test-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn test_queue() {
+	let (player, events) = Player::spawn_with(sink::Null {
+		sample_rate: 48000,
+		realtime: false,
+	})
+	.unwrap();
+	player
+		.queue_track(sine(44100.0, Duration::from_millis(500)))
+		.unwrap();
+	player
+		.queue_track(sine(48000.0, Duration::from_millis(500)))
+		.unwrap();
+	player.play().unwrap();
+
+	wait_track_ends(&events, 2);
+	assert_eq!(player.nb_queued(), 0);
+}
+
+#[test]
+fn test_source_error() {
+	let (player, events) = Player::spawn_with(sink::Null {
+		sample_rate: 48000,
+		realtime: false,
+	})
+	.unwrap();
+	player
+		.queue_track(TrackSource {
+			sample_rate: 44100.0,
+			signal: Box::new(Broken),
+			info: TrackInfo::default(),
+		})
+		.unwrap();
+	player
+		.queue_track(sine(44100.0, Duration::from_millis(500)))
+		.unwrap();
+	player.play().unwrap();
+
+	let error = events
+		.iter()
+		.find_map(|event| match event {
+			Event::SourceError { error, .. } => Some(error),
+			_ => None,
+		})
+		.unwrap();
+	assert_eq!(error.to_string(), "broken stream");
+	// the player keeps going with the next track
+	wait_track_ends(&events, 2);
+}
+
+#[test]
+fn test_seek() {
+	let (player, _events) = Player::spawn_with(sink::Null {
+		sample_rate: 48000,
+		realtime: true,
+	})
+	.unwrap();
+	player
+		.queue_track(sine(44100.0, Duration::from_secs(10)))
+		.unwrap();
+	player.play().unwrap();
+	std::thread::sleep(Duration::from_millis(300));
+
+	player.seek(Duration::from_secs(5)).unwrap();
+	std::thread::sleep(Duration::from_millis(300));
+	let offset = *player.state().read().current_time().unwrap();
+	assert!(offset >= Duration::from_secs(5));
+	// the player renders ahead of the output, but not by more than its buffer
+	assert!(offset < Duration::from_secs(6), "{offset:?}");
+}
+
+#[test]
+fn test_seek_landed() {
+	let (player, events) = Player::spawn_with(sink::Null {
+		sample_rate: 48000,
+		realtime: true,
+	})
+	.unwrap();
+	player
+		.queue_track(sine(44100.0, Duration::from_secs(10)))
+		.unwrap();
+	player.play().unwrap();
+	std::thread::sleep(Duration::from_millis(300));
+	player.pause().unwrap();
+
+	// in between two frames, the source lands on the first one
+	player.seek(Duration::from_nanos(5_000_012_345)).unwrap();
+	let offset = events
+		.iter()
+		.find_map(|event| match event {
+			Event::StateChanged(state) => state
+				.current_time()
+				.copied()
+				.filter(|offset| *offset > Duration::from_secs(1)),
+			_ => None,
+		})
+		.unwrap();
+	assert_eq!(offset, Duration::from_secs(5));
+}
+
+#[test]
+fn test_render_to_file() {
+	let path = temp_path("render.wav");
+	let (player, events) = Player::spawn_with(sink::Wav {
+		path: path.clone(),
+		sample_rate: 48000,
+	})
+	.unwrap();
+	player
+		.queue_track(sine(44100.0, Duration::from_secs(1)))
+		.unwrap();
+	player.play().unwrap();
+
+	wait_track_ends(&events, 1);
+	std::thread::sleep(Duration::from_millis(300));
+
+	let reader = hound::WavReader::open(&path).unwrap();
+	let samples = reader
+		.into_samples::<f32>()
+		.collect::<Result<Vec<_>, _>>()
+		.unwrap();
+	let frames = samples.len() / 2;
+	assert!((48000..48000 + 4096).contains(&frames), "{frames}");
+	assert!(samples.iter().any(|s| s.abs() > 0.4));
+	std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_fade_out() {
+	let path = temp_path("fade-out.wav");
+	let (player, events) = Player::spawn_with(sink::Wav {
+		path: path.clone(),
+		sample_rate: 48000,
+	})
+	.unwrap();
+	player
+		.queue_track(sine(48000.0, Duration::from_secs(10)))
+		.unwrap();
+	player.fade_out(Duration::from_millis(500)).unwrap();
+	player.play().unwrap();
+
+	events
+		.iter()
+		.find(|event| match event {
+			Event::StateChanged(state) => state.get_playing().map_or(false, |p| p.paused),
+			_ => false,
+		})
+		.unwrap();
+	std::thread::sleep(Duration::from_millis(100));
+
+	let samples = hound::WavReader::open(&path)
+		.unwrap()
+		.into_samples::<f32>()
+		.collect::<Result<Vec<_>, _>>()
+		.unwrap();
+	// nothing is rendered once paused
+	let frames = samples.len() / 2;
+	assert!(frames < 48000, "{frames}");
+	let peak = |s: &[f32]| s.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+	let quarter = samples.len() / 4;
+	assert!(peak(&samples[..quarter]) > peak(&samples[2 * quarter..3 * quarter]));
+	assert!(peak(&samples[samples.len() - 200..]) < 0.01);
+	std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_fade_out_past_queue() {
+	let path = temp_path("fade-out-past-queue.wav");
+	let (player, events) = Player::spawn_with(sink::Wav {
+		path: path.clone(),
+		sample_rate: 48000,
+	})
+	.unwrap();
+	player
+		.queue_track(sine(48000.0, Duration::from_millis(200)))
+		.unwrap();
+	player.fade_out(Duration::from_secs(2)).unwrap();
+	player.play().unwrap();
+	wait_track_ends(&events, 1);
+
+	// playing again after the queue ended
+	player
+		.queue_track(sine(48000.0, Duration::from_millis(500)))
+		.unwrap();
+	player.play().unwrap();
+	wait_track_ends(&events, 1);
+	std::thread::sleep(Duration::from_millis(100));
+
+	let samples = hound::WavReader::open(&path)
+		.unwrap()
+		.into_samples::<f32>()
+		.collect::<Result<Vec<_>, _>>()
+		.unwrap();
+	// the second track isn't faded
+	let end = samples.len() - 2 * 4800;
+	let peak = samples[end - 2 * 9600..end]
+		.iter()
+		.fold(0.0f32, |peak, s| peak.max(s.abs()));
+	assert!(peak > 0.48, "{peak}");
+	std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_speed() {
+	for (mode, speed, expected) in [
+		(SpeedMode::PreservePitch, 0.5, 96000),
+		(SpeedMode::Varispeed, 2.0, 24000),
+	] {
+		let path = temp_path(&format!("speed-{speed}.wav"));
+		let (player, events) = Player::spawn_with(sink::Wav {
+			path: path.clone(),
+			sample_rate: 48000,
+		})
+		.unwrap();
+		player.set_speed_mode(mode).unwrap();
+		player.set_speed(speed).unwrap();
+		player
+			.queue_track(sine(44100.0, Duration::from_secs(1)))
+			.unwrap();
+		player.play().unwrap();
+
+		wait_track_ends(&events, 1);
+		std::thread::sleep(Duration::from_millis(300));
+
+		let frames = hound::WavReader::open(&path).unwrap().duration() as usize;
+		assert!(
+			(expected..expected + 4096).contains(&frames),
+			"{mode:?}: {frames}"
+		);
+		std::fs::remove_file(path).ok();
+	}
+}
+
+#[test]
+fn test_upcoming() {
+	let (player, events) = Player::spawn_with(sink::Null {
+		sample_rate: 48000,
+		realtime: true,
+	})
+	.unwrap();
+	let upcoming = |key: &str, duration| Upcoming {
+		key: key.to_owned(),
+		resolve: Box::new(move || Ok(sine(44100.0, duration))),
+	};
+	player
+		.set_upcoming(vec![
+			upcoming("a", Duration::from_secs(5)),
+			upcoming("b", Duration::from_millis(500)),
+			upcoming("c", Duration::from_millis(500)),
+		])
+		.unwrap();
+	player.queue_upcoming("a").unwrap();
+	player.play().unwrap();
+
+	// "b" is prefetched while "a" plays, "c" is past the lookahead
+	let deadline = Instant::now() + Duration::from_secs(5);
+	loop {
+		let upcoming = player
+			.state()
+			.read()
+			.get_playing()
+			.map(|p| p.upcoming.clone());
+		if let Some([(b, Readiness::Ready), (c, Readiness::Waiting)]) = upcoming.as_deref() {
+			assert_eq!((b.as_str(), c.as_str()), ("b", "c"));
+			break;
+		}
+		assert!(Instant::now() < deadline, "{upcoming:?}");
+		std::thread::sleep(Duration::from_millis(10));
+	}
+	player.queue_upcoming("b").unwrap();
+	wait_track_ends(&events, 2);
+	assert_eq!(player.nb_queued(), 0);
+}
+
+#[test]
+fn test_upcoming_failed() {
+	let (player, events) = Player::spawn_with(sink::Null {
+		sample_rate: 48000,
+		realtime: true,
+	})
+	.unwrap();
+	player
+		.set_upcoming(vec![Upcoming {
+			key: "a".to_owned(),
+			resolve: Box::new(|| Err(anyhow::anyhow!("unavailable"))),
+		}])
+		.unwrap();
+	player.queue_upcoming("a").unwrap();
+	player.queue_upcoming("b").unwrap();
+
+	// the failing track is reported by its key, so is the one that isn't upcoming
+	let deadline = Instant::now() + Duration::from_secs(5);
+	let mut failed = vec![];
+	while failed.len() < 2 {
+		let timeout = deadline.saturating_duration_since(Instant::now());
+		match events.recv_timeout(timeout).unwrap() {
+			Event::UpcomingFailed { key, .. } => failed.push(key),
+			Event::UpcomingQueued(key) => panic!("{key} was queued"),
+			_ => {}
+		}
+	}
+	failed.sort();
+	assert_eq!(failed, ["a", "b"]);
+	assert_eq!(player.nb_queued(), 0);
+}
+
+#[test]
+fn test_buffering() {
+	let (player, events) = Player::spawn_with(sink::Null {
+		sample_rate: 48000,
+		realtime: true,
+	})
+	.unwrap();
+	let loaded = Arc::new(AtomicBool::new(true));
+	player
+		.queue_track(TrackSource {
+			sample_rate: 44100.0,
+			signal: Box::new(Stalling {
+				sine: Sine {
+					sample_rate: 44100.0,
+					position: 0,
+					len: 44100 * 10,
+				},
+				loaded: loaded.clone(),
+			}),
+			info: TrackInfo {
+				duration: Duration::from_secs(10),
+				..Default::default()
+			},
+		})
+		.unwrap();
+	player.play().unwrap();
+	std::thread::sleep(Duration::from_millis(300));
+
+	let buffering = |events: &Receiver<Event>| {
+		events
+			.iter()
+			.find_map(|event| match event {
+				Event::Buffering(progress) => Some(progress),
+				_ => None,
+			})
+			.unwrap()
+	};
+	loaded.store(false, Ordering::Relaxed);
+	assert_eq!(buffering(&events), 0.25);
+	let playing = player.state().read().get_playing().cloned().unwrap();
+	assert_eq!(playing.buffering, Some(0.25));
+
+	// the clock is held while the source waits
+	std::thread::sleep(Duration::from_millis(300));
+	assert_eq!(player.state().read().current_time(), Some(&playing.offset));
+
+	loaded.store(true, Ordering::Relaxed);
+	assert_eq!(buffering(&events), 1.0);
+	std::thread::sleep(Duration::from_millis(300));
+	let playing = player.state().read().get_playing().cloned().unwrap();
+	assert_eq!(playing.buffering, None);
+	assert!(playing.offset > Duration::from_millis(400), "{playing:?}");
+}
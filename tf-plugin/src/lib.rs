@@ -46,4 +46,6 @@ pub struct TrackInfo {
 	pub url: Arc<Url>,
 	pub artists: im::Vector<String>,
 	pub title: String,
+	// known from the source, like the genre in a file's metadata
+	pub tags: im::HashMap<String, f32>,
 }